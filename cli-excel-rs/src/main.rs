@@ -1,6 +1,6 @@
-use std::{fs::File, io::{Cursor, Read, Write}};
+use std::{fs::File, io::{self, BufWriter, Read, Write}};
 
-use clap::{arg, Command};
+use clap::{arg, value_parser, Command};
 use excel_rs_csv::{bytes_to_csv, get_headers, get_next_record};
 use excel_rs_xlsx::WorkBook;
 
@@ -13,7 +13,16 @@ fn cli() -> Command {
             Command::new("csv")
                 .about("Convert a csv file to xlsx")
                 .arg(arg!(--in <FILE> "csv file to convert"))
-                .arg(arg!(--out <FILE> "xlsx output file name")),
+                .arg(arg!(--out <FILE> "xlsx output file name, or - for stdout"))
+                .arg(arg!(--autofilter "add filter buttons to the header row"))
+                .arg(
+                    arg!(--table "format the data as an Excel table").conflicts_with("autofilter"),
+                )
+                .arg(
+                    arg!(--threads <N> "number of threads to compress the sheet on")
+                        .value_parser(value_parser!(usize))
+                        .default_value("1"),
+                ),
         )
 }
 
@@ -24,42 +33,77 @@ fn main() {
         Some(("csv", sub_matches)) => {
             let input = sub_matches.get_one::<String>("in").expect("required");
             let out = sub_matches.get_one::<String>("out").expect("required");
+            let autofilter = sub_matches.get_flag("autofilter");
+            let table = sub_matches.get_flag("table");
+            let threads = *sub_matches.get_one::<usize>("threads").expect("defaulted");
 
             let mut f = File::open(input).expect("input csv file not found");
             let mut data: Vec<u8> = Vec::new();
 
-            f.read_to_end(&mut data).expect(&format!("Unable to read file {input}"));
+            f.read_to_end(&mut data)
+                .unwrap_or_else(|_| panic!("Unable to read file {input}"));
 
-            let output_buffer = vec![];
-            let mut workbook = WorkBook::new(Cursor::new(output_buffer));
-            let mut worksheet = workbook.get_worksheet(String::from("Sheet 1"));
-
-            let mut reader = bytes_to_csv(data.as_slice());
-            let headers = get_headers(&mut reader);
-
-            if headers.is_some() {
-                let headers_to_bytes = headers.unwrap().iter().to_owned().collect();
-                if let Err(e) = worksheet.write_row(headers_to_bytes) {
-                    panic!("{e}");
-                }
+            // The workbook is written straight to the output as it is filled
+            if out == "-" {
+                let workbook = WorkBook::streaming(BufWriter::new(io::stdout().lock()));
+                csv_to_xlsx(workbook, &data, autofilter, table, threads, out);
+            } else {
+                let file =
+                    File::create(out).unwrap_or_else(|_| panic!("unable to write to {out}"));
+                let workbook = WorkBook::new(BufWriter::new(file));
+                csv_to_xlsx(workbook, &data, autofilter, table, threads, out);
             }
+        }
+        _ => unreachable!("Unsupported subcommand"),
+    }
+}
 
-            while let Some(record) = get_next_record(&mut reader) {
-                let row_data = record.iter().to_owned().collect();
-                if let Err(e) = worksheet.write_row(row_data) {
-                    panic!("{e}");
-                }
-            }
+fn csv_to_xlsx<W: Write>(
+    mut workbook: WorkBook<W>,
+    data: &[u8],
+    autofilter: bool,
+    table: bool,
+    threads: usize,
+    out: &str,
+) {
+    if let Err(e) = workbook.set_compression_threads(threads) {
+        panic!("{e}");
+    }
+    let mut worksheet = match workbook.get_worksheet(String::from("Sheet 1")) {
+        Ok(sheet) => sheet,
+        Err(e) => panic!("{e}"),
+    };
 
-            if let Err(e) = worksheet.close() {
+    let mut reader = bytes_to_csv(data);
+    let headers = get_headers(&mut reader);
+
+    if let Some(headers) = headers {
+        if table {
+            if let Err(e) = worksheet.add_header_table("Table1", None) {
                 panic!("{e}");
             }
+        }
 
-            let final_buffer = workbook.finish().ok().unwrap().into_inner();
+        if let Err(e) = worksheet.write_row_iter(headers) {
+            panic!("{e}");
+        }
 
-            f = File::create(out).expect(&format!("unable to write to {out}"));
-            f.write(&final_buffer).expect(&format!("Failed to write to file {out}"));
+        if autofilter {
+            worksheet.autofilter_header();
         }
-        _ => unreachable!("Unsupported subcommand"),
+    }
+
+    while let Some(record) = get_next_record(&mut reader) {
+        if let Err(e) = worksheet.write_row_iter(&record) {
+            panic!("{e}");
+        }
+    }
+
+    if let Err(e) = worksheet.close() {
+        panic!("{e}");
+    }
+
+    if let Err(e) = workbook.finish() {
+        panic!("Failed to write to {out}: {e}");
     }
 }
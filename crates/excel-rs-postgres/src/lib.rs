@@ -2,7 +2,7 @@ mod client;
 mod sql_impl;
 mod ssl;
 
-use std::io::Cursor;
+use std::io::Write;
 
 use anyhow::Result;
 pub use client::PostgresClient;
@@ -11,10 +11,15 @@ pub use postgres::fallible_iterator::FallibleIterator;
 use postgres::RowIter;
 pub use sql_impl::{ExcelBytes, ExcelBytesBorrowed};
 
-pub fn postgres_to_xlsx<'a>(mut iter: RowIter<'a>) -> Result<Vec<u8>> {
-    let output_buffer = vec![];
-    let mut workbook = WorkBook::new(Cursor::new(output_buffer));
-    let mut worksheet = workbook.get_worksheet(String::from("Sheet 1"));
+pub fn postgres_to_xlsx<'a>(iter: RowIter<'a>) -> Result<Vec<u8>> {
+    postgres_to_writer(iter, Vec::new())
+}
+
+// Streams the workbook into `writer` as rows arrive, returning it once done.
+// The writer never needs to seek.
+pub fn postgres_to_writer<'a, W: Write>(mut iter: RowIter<'a>, writer: W) -> Result<W> {
+    let mut workbook = WorkBook::streaming(writer);
+    let mut worksheet = workbook.get_worksheet(String::from("Sheet 1"))?;
 
     let headers = iter.next().ok().unwrap().unwrap();
     let len = headers.len();
@@ -43,14 +48,10 @@ pub fn postgres_to_xlsx<'a>(mut iter: RowIter<'a>) -> Result<Vec<u8>> {
             }
         }
 
-        let new_vec: Vec<&[u8]> = row_vec.iter().map(|x| x.as_ref()).collect();
-
-        worksheet.write_row(new_vec)?;
+        worksheet.write_row_iter(&row_vec)?;
     }
 
     worksheet.close()?;
 
-    let final_buffer = workbook.finish()?;
-
-    Ok(final_buffer.into_inner())
+    workbook.finish()
 }
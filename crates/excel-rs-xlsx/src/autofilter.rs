@@ -0,0 +1,73 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::cell::CellRange;
+
+pub(crate) enum AutoFilter {
+    Range(CellRange),
+    // Covers the first row down to the last row written, across every column
+    // used. Resolved when the sheet is closed.
+    Header,
+}
+
+impl AutoFilter {
+    pub fn range(&self, row_count: u32, col_count: u16) -> Option<CellRange> {
+        match self {
+            AutoFilter::Range(range) => Some(*range),
+            AutoFilter::Header if row_count == 0 || col_count == 0 => None,
+            AutoFilter::Header => Some(CellRange::new(0, 0, row_count - 1, col_count - 1)),
+        }
+    }
+
+    pub fn write_xml(&self, buf: &mut Vec<u8>, row_count: u32, col_count: u16) -> Result<()> {
+        if let Some(range) = self.range(row_count, col_count) {
+            write!(buf, "<autoFilter ref=\"{}\"/>", range.to_ref()?)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{read_part, write_workbook};
+
+    #[test]
+    fn filters_the_header_row_or_a_fixed_range() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Data"))?;
+            sheet.autofilter_header();
+            sheet.write_row(vec![b"a", b"b"])?;
+            sheet.write_row(vec![b"1", b"2", b"3"])?;
+            sheet.write_row(vec![b"4"])?;
+            sheet.close()?;
+
+            let mut sheet = workbook.get_worksheet(String::from("Fixed"))?;
+            sheet.write_row(vec![b"a"])?;
+            sheet.set_autofilter(1, 1, 9, 3)?;
+            assert!(sheet.set_autofilter(2, 0, 1, 0).is_err());
+            sheet.close()?;
+
+            let mut sheet = workbook.get_worksheet(String::from("Empty"))?;
+            sheet.autofilter_header();
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains("</sheetData>\n<autoFilter ref=\"A1:C3\"/>"));
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet2.xml");
+        assert!(sheet_xml.contains("<autoFilter ref=\"B2:D10\"/>"));
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet3.xml");
+        assert!(!sheet_xml.contains("<autoFilter"));
+
+        // Excel also names each filtered range, hidden from the Name Manager
+        let workbook_xml = read_part(&archive, "xl/workbook.xml");
+        assert!(workbook_xml.contains(concat!(
+            "<definedNames>",
+            "<definedName name=\"_xlnm._FilterDatabase\" localSheetId=\"0\" hidden=\"1\">&apos;Data&apos;!$A$1:$C$3</definedName>",
+            "<definedName name=\"_xlnm._FilterDatabase\" localSheetId=\"1\" hidden=\"1\">&apos;Fixed&apos;!$B$2:$D$10</definedName>",
+            "</definedNames>"
+        )));
+    }
+}
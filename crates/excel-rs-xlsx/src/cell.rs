@@ -0,0 +1,392 @@
+use anyhow::{bail, Result};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use memchr::memchr3_iter;
+
+use crate::columns::MAX_COLUMNS;
+
+pub const MAX_ROWS: u32 = 1_048_576;
+const SECONDS_IN_A_DAY: f64 = 60.0 * 60.0 * 24.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellValue<'a> {
+    Number(f64),
+    Int(i64),
+    Bool(bool),
+    String(&'a str),
+    DateTime(NaiveDateTime),
+    Date(NaiveDate),
+    Time(NaiveTime),
+    Blank,
+    Error(CellError),
+    // Formula text, with or without a leading '=', and the value Excel shows
+    // until it recalculates
+    Formula(&'a str, Option<FormulaResult<'a>>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FormulaResult<'a> {
+    Number(f64),
+    String(&'a str),
+    Bool(bool),
+    Error(CellError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellError {
+    Null,
+    Div0,
+    Value,
+    Ref,
+    Name,
+    Num,
+    NA,
+}
+
+impl CellError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CellError::Null => "#NULL!",
+            CellError::Div0 => "#DIV/0!",
+            CellError::Value => "#VALUE!",
+            CellError::Ref => "#REF!",
+            CellError::Name => "#NAME?",
+            CellError::Num => "#NUM!",
+            CellError::NA => "#N/A",
+        }
+    }
+}
+
+impl<'a> From<&'a str> for CellValue<'a> {
+    fn from(value: &'a str) -> Self {
+        CellValue::String(value)
+    }
+}
+
+impl From<f64> for CellValue<'_> {
+    fn from(value: f64) -> Self {
+        CellValue::Number(value)
+    }
+}
+
+impl From<i64> for CellValue<'_> {
+    fn from(value: i64) -> Self {
+        CellValue::Int(value)
+    }
+}
+
+impl From<bool> for CellValue<'_> {
+    fn from(value: bool) -> Self {
+        CellValue::Bool(value)
+    }
+}
+
+impl From<NaiveDateTime> for CellValue<'_> {
+    fn from(value: NaiveDateTime) -> Self {
+        CellValue::DateTime(value)
+    }
+}
+
+impl From<NaiveDate> for CellValue<'_> {
+    fn from(value: NaiveDate) -> Self {
+        CellValue::Date(value)
+    }
+}
+
+impl From<NaiveTime> for CellValue<'_> {
+    fn from(value: NaiveTime) -> Self {
+        CellValue::Time(value)
+    }
+}
+
+// Excel serial dates count days from 1899-12-30, except that Excel believes
+// 1900-02-29 exists, so dates before 1900-03-01 are shifted back by one.
+// Returns None for dates Excel cannot represent (before 1900-01-01).
+pub(crate) fn date_to_serial(date: NaiveDate) -> Option<f64> {
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30).expect("Excel Epoch");
+    let mut days = (date - epoch).num_days();
+
+    if days < 2 {
+        return None;
+    }
+
+    if days < 61 {
+        days -= 1;
+    }
+
+    Some(days as f64)
+}
+
+pub(crate) fn time_to_serial(time: NaiveTime) -> f64 {
+    let seconds = time.num_seconds_from_midnight() as f64 + time.nanosecond() as f64 / 1e9;
+    seconds / SECONDS_IN_A_DAY
+}
+
+pub(crate) fn date_time_to_serial(date_time: NaiveDateTime) -> Option<f64> {
+    Some(date_to_serial(date_time.date())? + time_to_serial(date_time.time()))
+}
+
+pub(crate) fn escape_xml_into(buf: &mut Vec<u8>, bytes: &[u8]) {
+    let mut current_pos = 0;
+    for (pos, byte) in bytes.iter().enumerate() {
+        let escaped: &[u8] = match byte {
+            b'<' => b"&lt;",
+            b'>' => b"&gt;",
+            b'\'' => b"&apos;",
+            b'&' => b"&amp;",
+            b'"' => b"&quot;",
+            _ => continue,
+        };
+
+        buf.extend_from_slice(&bytes[current_pos..pos]);
+        buf.extend_from_slice(escaped);
+        current_pos = pos + 1;
+    }
+
+    buf.extend_from_slice(&bytes[current_pos..]);
+}
+
+// Writes a <t> element, keeping leading and trailing whitespace that Excel
+// would otherwise trim
+pub(crate) fn write_text_element(buf: &mut Vec<u8>, bytes: &[u8]) {
+    let needs_preserve = matches!(bytes.first(), Some(b' ' | b'\t' | b'\n' | b'\r'))
+        || matches!(bytes.last(), Some(b' ' | b'\t' | b'\n' | b'\r'));
+
+    if needs_preserve {
+        buf.extend_from_slice(b"<t xml:space=\"preserve\">");
+    } else {
+        buf.extend_from_slice(b"<t>");
+    }
+
+    escape_text_into(buf, bytes);
+    buf.extend_from_slice(b"</t>");
+}
+
+// Escapes element content, where quotes can stay as they are. This is the
+// hot path for cell text, so it jumps between the three bytes that need
+// escaping with memchr rather than matching every byte.
+pub(crate) fn escape_text_into(buf: &mut Vec<u8>, bytes: &[u8]) {
+    let mut current_pos = 0;
+    for pos in memchr3_iter(b'<', b'>', b'&', bytes) {
+        let escaped: &[u8] = match bytes[pos] {
+            b'<' => b"&lt;",
+            b'>' => b"&gt;",
+            _ => b"&amp;",
+        };
+
+        buf.extend_from_slice(&bytes[current_pos..pos]);
+        buf.extend_from_slice(escaped);
+        current_pos = pos + 1;
+    }
+
+    buf.extend_from_slice(&bytes[current_pos..]);
+}
+
+// Number of characters in UTF-8 text, without validating it
+pub(crate) fn text_len(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|b| (**b & 0xC0) != 0x80).count()
+}
+
+// Approximate width of the value as Excel displays it with the default formats
+pub(crate) fn display_len(cell: &CellValue) -> usize {
+    match cell {
+        CellValue::Number(num) => format!("{}", num).len(),
+        CellValue::Int(num) => format!("{}", num).len(),
+        CellValue::Bool(_) => 5,
+        CellValue::String(s) => text_len(s.as_bytes()),
+        CellValue::DateTime(_) => 16,
+        CellValue::Date(_) => 10,
+        CellValue::Time(_) => 8,
+        CellValue::Blank => 0,
+        CellValue::Error(error) => error.as_str().len(),
+        CellValue::Formula(_, None) => 0,
+        CellValue::Formula(_, Some(result)) => match result {
+            FormulaResult::Number(num) => format!("{}", num).len(),
+            FormulaResult::String(s) => text_len(s.as_bytes()),
+            FormulaResult::Bool(_) => 5,
+            FormulaResult::Error(error) => error.as_str().len(),
+        },
+    }
+}
+
+// Text Excel shows for the value in a table header
+pub(crate) fn header_text(cell: &CellValue) -> Option<String> {
+    match cell {
+        CellValue::String(s) => Some(s.to_string()),
+        CellValue::Number(num) => Some(format!("{}", num)),
+        CellValue::Int(num) => Some(format!("{}", num)),
+        _ => None,
+    }
+}
+
+pub(crate) fn col_to_letters(col: u16) -> String {
+    let mut letters = Vec::with_capacity(3);
+    let mut col = col as i32;
+
+    loop {
+        letters.push(b'A' + (col % 26) as u8);
+        col = col / 26 - 1;
+        if col < 0 {
+            break;
+        }
+    }
+
+    letters.reverse();
+    String::from_utf8(letters).expect("column letters are ASCII")
+}
+
+// A1-style reference for a zero-based row and column
+pub(crate) fn cell_ref(row: u32, col: u16) -> String {
+    format!("{}{}", col_to_letters(col), row + 1)
+}
+
+// An inclusive range of zero-based rows and columns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellRange {
+    pub first_row: u32,
+    pub first_col: u16,
+    pub last_row: u32,
+    pub last_col: u16,
+}
+
+impl CellRange {
+    pub fn new(first_row: u32, first_col: u16, last_row: u32, last_col: u16) -> Self {
+        CellRange {
+            first_row,
+            first_col,
+            last_row,
+            last_col,
+        }
+    }
+
+    // Fails if the range is reversed or outside the sheet
+    pub(crate) fn to_ref(self) -> Result<String> {
+        range_ref(self.first_row, self.first_col, self.last_row, self.last_col)
+    }
+
+    // Absolute reference qualified with the sheet name, e.g. 'Sheet 1'!$A$1:$B$2
+    pub(crate) fn to_formula(self, sheet_name: &str) -> Result<String> {
+        self.to_ref()?;
+
+        let first = format!("${}${}", col_to_letters(self.first_col), self.first_row + 1);
+        let last = format!("${}${}", col_to_letters(self.last_col), self.last_row + 1);
+        let sheet_name = sheet_name.replace('\'', "''");
+
+        if first == last {
+            Ok(format!("'{}'!{}", sheet_name, first))
+        } else {
+            Ok(format!("'{}'!{}:{}", sheet_name, first, last))
+        }
+    }
+
+    pub(crate) fn overlaps(&self, other: &CellRange) -> bool {
+        self.first_row <= other.last_row
+            && other.first_row <= self.last_row
+            && self.first_col <= other.last_col
+            && other.first_col <= self.last_col
+    }
+}
+
+// A1:B2-style reference for an inclusive range of zero-based rows and columns
+pub(crate) fn range_ref(
+    first_row: u32,
+    first_col: u16,
+    last_row: u32,
+    last_col: u16,
+) -> Result<String> {
+    if first_row > last_row
+        || first_col > last_col
+        || last_row >= MAX_ROWS
+        || last_col >= MAX_COLUMNS
+    {
+        bail!("invalid cell range ({first_row}, {first_col}) to ({last_row}, {last_col})");
+    }
+
+    Ok(format!(
+        "{}:{}",
+        cell_ref(first_row, first_col),
+        cell_ref(last_row, last_col)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveTime};
+
+    use super::{
+        date_time_to_serial, date_to_serial, escape_text_into, escape_xml_into, time_to_serial,
+        write_text_element,
+    };
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn counts_dates_like_excel() {
+        assert_eq!(date_to_serial(date(1899, 12, 31)), None);
+        assert_eq!(date_to_serial(date(1900, 1, 1)), Some(1.0));
+        assert_eq!(date_to_serial(date(1900, 2, 28)), Some(59.0));
+        // Excel's phantom 1900-02-29 is serial 60
+        assert_eq!(date_to_serial(date(1900, 3, 1)), Some(61.0));
+        assert_eq!(date_to_serial(date(2024, 1, 2)), Some(45293.0));
+        assert_eq!(date_to_serial(date(9999, 12, 31)), Some(2958465.0));
+    }
+
+    #[test]
+    fn adds_the_time_as_a_fraction_of_a_day() {
+        let time = NaiveTime::from_hms_milli_opt(6, 0, 0, 500).unwrap();
+        assert_eq!(time_to_serial(NaiveTime::MIN), 0.0);
+        assert_eq!(time_to_serial(time), 0.25 + 0.5 / 86400.0);
+
+        let date_time = date(2024, 1, 2).and_hms_opt(18, 0, 0).unwrap();
+        assert_eq!(date_time_to_serial(date_time), Some(45293.75));
+        let date_time = date(1899, 12, 31).and_hms_opt(18, 0, 0).unwrap();
+        assert_eq!(date_time_to_serial(date_time), None);
+    }
+
+    #[test]
+    fn escapes_text_like_the_byte_by_byte_path() {
+        let long = "a<b>&c\"d'e".repeat(40);
+        let inputs = [
+            "",
+            "plain",
+            "<",
+            "&&&",
+            "<tag attr=\"1\">",
+            "Fish & Chips",
+            "ends with >",
+            "ünïcödé <& 日本語 >",
+            "0123456789abcdef0123456789abcdef<0123456789abcdef0123456789abcdef&",
+            long.as_str(),
+        ];
+
+        for input in inputs {
+            let mut text = Vec::new();
+            escape_text_into(&mut text, input.as_bytes());
+
+            // Attribute escaping only differs in also escaping quotes
+            let mut attribute = Vec::new();
+            escape_xml_into(&mut attribute, input.as_bytes());
+            let attribute = String::from_utf8(attribute)
+                .unwrap()
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'");
+
+            assert_eq!(String::from_utf8(text).unwrap(), attribute, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn preserves_outer_whitespace_in_text() {
+        for (input, expected) in [
+            ("a b", "<t>a b</t>"),
+            (" a", "<t xml:space=\"preserve\"> a</t>"),
+            ("a\n", "<t xml:space=\"preserve\">a\n</t>"),
+            ("", "<t></t>"),
+        ] {
+            let mut buf = Vec::new();
+            write_text_element(&mut buf, input.as_bytes());
+            assert_eq!(buf, expected.as_bytes());
+        }
+    }
+}
@@ -0,0 +1,517 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+use crate::cell::{escape_xml_into, CellRange};
+
+// 480 x 288 pixels, the size Excel gives a new chart
+const DEFAULT_WIDTH: u32 = 480;
+const DEFAULT_HEIGHT: u32 = 288;
+
+const CATEGORY_AXIS_ID: u32 = 50010001;
+const VALUE_AXIS_ID: u32 = 50010002;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartType {
+    // Horizontal bars
+    Bar,
+    // Vertical bars
+    Column,
+    Line,
+    Pie,
+    Scatter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegendPosition {
+    Right,
+    Left,
+    Top,
+    Bottom,
+    Hidden,
+}
+
+impl LegendPosition {
+    fn as_str(&self) -> Option<&'static str> {
+        match self {
+            LegendPosition::Right => Some("r"),
+            LegendPosition::Left => Some("l"),
+            LegendPosition::Top => Some("t"),
+            LegendPosition::Bottom => Some("b"),
+            LegendPosition::Hidden => None,
+        }
+    }
+}
+
+// A series of values from a sheet, optionally labelled by a range of
+// categories (x values for scatter charts)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartSeries {
+    sheet_name: String,
+    values: CellRange,
+    categories: Option<CellRange>,
+    name: Option<String>,
+}
+
+impl ChartSeries {
+    pub fn new(sheet_name: &str, values: CellRange) -> Self {
+        ChartSeries {
+            sheet_name: String::from(sheet_name),
+            values,
+            categories: None,
+            name: None,
+        }
+    }
+
+    // Categories are read from the same sheet as the values
+    pub fn categories(mut self, categories: CellRange) -> Self {
+        self.categories = Some(categories);
+        self
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(String::from(name));
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChartAxis {
+    title: Option<String>,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl ChartAxis {
+    pub fn new() -> Self {
+        ChartAxis::default()
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(String::from(title));
+        self
+    }
+
+    // Only applies to value axes
+    pub fn min(mut self, min: f64) -> Self {
+        self.min = Some(min);
+        self
+    }
+
+    pub fn max(mut self, max: f64) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        for bound in [self.min, self.max].into_iter().flatten() {
+            if !bound.is_finite() {
+                bail!("chart axis bound {bound} must be a finite number");
+            }
+        }
+
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            if min > max {
+                bail!("chart axis minimum {min} cannot be above its maximum {max}");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// A chart drawn from sheet ranges, placed with Sheet::insert_chart
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chart {
+    chart_type: ChartType,
+    title: Option<String>,
+    series: Vec<ChartSeries>,
+    x_axis: ChartAxis,
+    y_axis: ChartAxis,
+    legend: LegendPosition,
+    width: u32,
+    height: u32,
+}
+
+impl Chart {
+    pub fn new(chart_type: ChartType) -> Self {
+        Chart {
+            chart_type,
+            title: None,
+            series: Vec::new(),
+            x_axis: ChartAxis::default(),
+            y_axis: ChartAxis::default(),
+            legend: LegendPosition::Right,
+            width: DEFAULT_WIDTH,
+            height: DEFAULT_HEIGHT,
+        }
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(String::from(title));
+        self
+    }
+
+    pub fn add_series(mut self, series: ChartSeries) -> Self {
+        self.series.push(series);
+        self
+    }
+
+    // The category axis, which is vertical for bar charts
+    pub fn x_axis(mut self, axis: ChartAxis) -> Self {
+        self.x_axis = axis;
+        self
+    }
+
+    pub fn y_axis(mut self, axis: ChartAxis) -> Self {
+        self.y_axis = axis;
+        self
+    }
+
+    pub fn legend(mut self, position: LegendPosition) -> Self {
+        self.legend = position;
+        self
+    }
+
+    // Size in pixels
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub(crate) fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub(crate) fn to_xml(&self) -> Result<Vec<u8>> {
+        if self.series.is_empty() {
+            bail!("a chart needs at least one series");
+        }
+
+        if self.width == 0 || self.height == 0 {
+            bail!("chart size must be larger than zero");
+        }
+
+        for axis in [&self.x_axis, &self.y_axis] {
+            axis.validate()?;
+        }
+
+        let mut buf = Vec::with_capacity(2048);
+        buf.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<c:chartSpace xmlns:c=\"http://schemas.openxmlformats.org/drawingml/2006/chart\" xmlns:a=\"http://schemas.openxmlformats.org/drawingml/2006/main\" xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\"><c:roundedCorners val=\"0\"/><c:chart>")?;
+
+        match &self.title {
+            Some(title) => write_title(&mut buf, title)?,
+            None => buf.write_all(b"<c:autoTitleDeleted val=\"1\"/>")?,
+        }
+
+        buf.write_all(b"<c:plotArea><c:layout/>")?;
+        self.write_plot(&mut buf)?;
+        self.write_axes(&mut buf)?;
+        buf.write_all(b"</c:plotArea>")?;
+
+        if let Some(position) = self.legend.as_str() {
+            write!(
+                buf,
+                "<c:legend><c:legendPos val=\"{}\"/><c:overlay val=\"0\"/></c:legend>",
+                position
+            )?;
+        }
+
+        buf.write_all(b"<c:plotVisOnly val=\"1\"/></c:chart></c:chartSpace>")?;
+
+        Ok(buf)
+    }
+
+    fn write_plot(&self, buf: &mut Vec<u8>) -> Result<()> {
+        let element = match self.chart_type {
+            ChartType::Bar | ChartType::Column => "barChart",
+            ChartType::Line => "lineChart",
+            ChartType::Pie => "pieChart",
+            ChartType::Scatter => "scatterChart",
+        };
+
+        write!(buf, "<c:{}>", element)?;
+        match self.chart_type {
+            ChartType::Bar => buf.write_all(
+                b"<c:barDir val=\"bar\"/><c:grouping val=\"clustered\"/><c:varyColors val=\"0\"/>",
+            )?,
+            ChartType::Column => buf.write_all(
+                b"<c:barDir val=\"col\"/><c:grouping val=\"clustered\"/><c:varyColors val=\"0\"/>",
+            )?,
+            ChartType::Line => {
+                buf.write_all(b"<c:grouping val=\"standard\"/><c:varyColors val=\"0\"/>")?
+            }
+            ChartType::Pie => buf.write_all(b"<c:varyColors val=\"1\"/>")?,
+            ChartType::Scatter => {
+                buf.write_all(b"<c:scatterStyle val=\"lineMarker\"/><c:varyColors val=\"0\"/>")?
+            }
+        }
+
+        for (i, series) in self.series.iter().enumerate() {
+            self.write_series(buf, i, series)?;
+        }
+
+        match self.chart_type {
+            ChartType::Pie => buf.write_all(b"<c:firstSliceAng val=\"0\"/>")?,
+            _ => {
+                if self.chart_type == ChartType::Line {
+                    buf.write_all(b"<c:marker val=\"1\"/>")?;
+                }
+                write!(
+                    buf,
+                    "<c:axId val=\"{}\"/><c:axId val=\"{}\"/>",
+                    CATEGORY_AXIS_ID, VALUE_AXIS_ID
+                )?;
+            }
+        }
+        write!(buf, "</c:{}>", element)?;
+
+        Ok(())
+    }
+
+    fn write_series(&self, buf: &mut Vec<u8>, index: usize, series: &ChartSeries) -> Result<()> {
+        write!(
+            buf,
+            "<c:ser><c:idx val=\"{}\"/><c:order val=\"{}\"/>",
+            index, index
+        )?;
+
+        if let Some(name) = &series.name {
+            buf.write_all(b"<c:tx><c:v>")?;
+            escape_xml_into(buf, name.as_bytes());
+            buf.write_all(b"</c:v></c:tx>")?;
+        }
+
+        let values = series.values.to_formula(&series.sheet_name)?;
+        let categories = match series.categories {
+            Some(categories) => Some(categories.to_formula(&series.sheet_name)?),
+            None => None,
+        };
+
+        match self.chart_type {
+            ChartType::Bar | ChartType::Column => {
+                buf.write_all(b"<c:invertIfNegative val=\"0\"/>")?
+            }
+            // Markers only, without the connecting lines
+            ChartType::Scatter => {
+                buf.write_all(b"<c:spPr><a:ln w=\"28575\"><a:noFill/></a:ln></c:spPr>")?
+            }
+            _ => {}
+        }
+
+        if self.chart_type == ChartType::Scatter {
+            if let Some(categories) = categories {
+                write_ref(buf, "xVal", "numRef", &categories)?;
+            }
+            write_ref(buf, "yVal", "numRef", &values)?;
+            buf.write_all(b"<c:smooth val=\"0\"/>")?;
+        } else {
+            if let Some(categories) = categories {
+                write_ref(buf, "cat", "strRef", &categories)?;
+            }
+            write_ref(buf, "val", "numRef", &values)?;
+            if self.chart_type == ChartType::Line {
+                buf.write_all(b"<c:smooth val=\"0\"/>")?;
+            }
+        }
+
+        buf.write_all(b"</c:ser>")?;
+
+        Ok(())
+    }
+
+    fn write_axes(&self, buf: &mut Vec<u8>) -> Result<()> {
+        let (category_pos, value_pos) = match self.chart_type {
+            ChartType::Pie => return Ok(()),
+            ChartType::Bar => ("l", "b"),
+            _ => ("b", "l"),
+        };
+
+        if self.chart_type == ChartType::Scatter {
+            write_value_axis(
+                buf,
+                &self.x_axis,
+                CATEGORY_AXIS_ID,
+                VALUE_AXIS_ID,
+                category_pos,
+                false,
+            )?;
+        } else {
+            write!(
+                buf,
+                "<c:catAx><c:axId val=\"{}\"/><c:scaling><c:orientation val=\"minMax\"/></c:scaling><c:delete val=\"0\"/><c:axPos val=\"{}\"/>",
+                CATEGORY_AXIS_ID, category_pos
+            )?;
+            if let Some(title) = &self.x_axis.title {
+                write_title(buf, title)?;
+            }
+            write!(
+                buf,
+                "<c:numFmt formatCode=\"General\" sourceLinked=\"1\"/><c:tickLblPos val=\"nextTo\"/><c:crossAx val=\"{}\"/><c:crosses val=\"autoZero\"/><c:auto val=\"1\"/><c:lblAlgn val=\"ctr\"/><c:lblOffset val=\"100\"/></c:catAx>",
+                VALUE_AXIS_ID
+            )?;
+        }
+
+        write_value_axis(
+            buf,
+            &self.y_axis,
+            VALUE_AXIS_ID,
+            CATEGORY_AXIS_ID,
+            value_pos,
+            true,
+        )
+    }
+}
+
+fn write_value_axis(
+    buf: &mut Vec<u8>,
+    axis: &ChartAxis,
+    id: u32,
+    cross_id: u32,
+    position: &str,
+    gridlines: bool,
+) -> Result<()> {
+    write!(
+        buf,
+        "<c:valAx><c:axId val=\"{}\"/><c:scaling><c:orientation val=\"minMax\"/>",
+        id
+    )?;
+    if let Some(max) = axis.max {
+        write!(buf, "<c:max val=\"{}\"/>", max)?;
+    }
+    if let Some(min) = axis.min {
+        write!(buf, "<c:min val=\"{}\"/>", min)?;
+    }
+    write!(
+        buf,
+        "</c:scaling><c:delete val=\"0\"/><c:axPos val=\"{}\"/>",
+        position
+    )?;
+    if gridlines {
+        buf.write_all(b"<c:majorGridlines/>")?;
+    }
+    if let Some(title) = &axis.title {
+        write_title(buf, title)?;
+    }
+    write!(
+        buf,
+        "<c:numFmt formatCode=\"General\" sourceLinked=\"1\"/><c:tickLblPos val=\"nextTo\"/><c:crossAx val=\"{}\"/><c:crosses val=\"autoZero\"/><c:crossBetween val=\"between\"/></c:valAx>",
+        cross_id
+    )?;
+
+    Ok(())
+}
+
+fn write_title(buf: &mut Vec<u8>, title: &str) -> Result<()> {
+    buf.write_all(b"<c:title><c:tx><c:rich><a:bodyPr/><a:lstStyle/><a:p><a:r><a:t>")?;
+    escape_xml_into(buf, title.as_bytes());
+    buf.write_all(b"</a:t></a:r></a:p></c:rich></c:tx><c:overlay val=\"0\"/></c:title>")?;
+    Ok(())
+}
+
+fn write_ref(buf: &mut Vec<u8>, element: &str, ref_type: &str, formula: &str) -> Result<()> {
+    write!(buf, "<c:{}><c:{}><c:f>", element, ref_type)?;
+    escape_xml_into(buf, formula.as_bytes());
+    write!(buf, "</c:f></c:{}></c:{}>", ref_type, element)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chart, ChartAxis, ChartSeries, ChartType, LegendPosition};
+    use crate::cell::CellRange;
+    use crate::test_util::{read_part, write_workbook};
+
+    fn column_chart() -> Chart {
+        Chart::new(ChartType::Column)
+            .add_series(ChartSeries::new("Data", CellRange::new(1, 1, 3, 1)).name("Sales"))
+    }
+
+    #[test]
+    fn rejects_axis_bounds_excel_cannot_scale() {
+        for axis in [
+            ChartAxis::new().min(f64::NAN),
+            ChartAxis::new().max(f64::INFINITY),
+            ChartAxis::new().min(10.0).max(5.0),
+        ] {
+            assert!(column_chart().y_axis(axis.clone()).to_xml().is_err());
+            assert!(column_chart().x_axis(axis).to_xml().is_err());
+        }
+
+        let xml = column_chart()
+            .y_axis(ChartAxis::new().min(-2.5).max(-2.5))
+            .to_xml()
+            .unwrap();
+        let xml = String::from_utf8(xml).unwrap();
+        assert!(xml.contains(
+            "<c:scaling><c:orientation val=\"minMax\"/><c:max val=\"-2.5\"/><c:min val=\"-2.5\"/></c:scaling>"
+        ));
+    }
+
+    #[test]
+    fn writes_series_axes_and_legend() {
+        let chart = Chart::new(ChartType::Scatter)
+            .title("Height & weight")
+            .add_series(
+                ChartSeries::new("Tom's data", CellRange::new(1, 1, 4, 1))
+                    .categories(CellRange::new(1, 0, 4, 0)),
+            )
+            .x_axis(ChartAxis::new().title("Height"))
+            .legend(LegendPosition::Hidden);
+        let xml = String::from_utf8(chart.to_xml().unwrap()).unwrap();
+
+        assert!(xml.contains("<a:t>Height &amp; weight</a:t>"));
+        assert!(xml.contains(concat!(
+            "<c:xVal><c:numRef><c:f>&apos;Tom&apos;&apos;s data&apos;!$A$2:$A$5</c:f></c:numRef></c:xVal>",
+            "<c:yVal><c:numRef><c:f>&apos;Tom&apos;&apos;s data&apos;!$B$2:$B$5</c:f></c:numRef></c:yVal>"
+        )));
+        // Scatter charts plot both axes as values
+        assert_eq!(xml.matches("<c:valAx>").count(), 2);
+        assert!(!xml.contains("<c:catAx>"));
+        assert!(!xml.contains("<c:legend>"));
+
+        assert!(Chart::new(ChartType::Pie).to_xml().is_err());
+        assert!(column_chart().size(0, 100).to_xml().is_err());
+    }
+
+    #[test]
+    fn declares_chart_and_drawing_parts() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Data"))?;
+            sheet.write_row(vec![&b"month"[..], b"sales"])?;
+            sheet.insert_chart(1, 3, &column_chart())?;
+            sheet.insert_chart(20, 3, &column_chart().size(240, 144))?;
+            sheet.close()
+        });
+
+        let content_types_xml = read_part(&archive, "[Content_Types].xml");
+        assert!(content_types_xml.contains("<Override PartName=\"/xl/drawings/drawing1.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.drawing+xml\"/>"));
+        for i in [1, 2] {
+            assert!(content_types_xml.contains(&format!("<Override PartName=\"/xl/charts/chart{i}.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.drawingml.chart+xml\"/>")));
+        }
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains("<drawing r:id=\"rId1\"/>"));
+        let rels_xml = read_part(&archive, "xl/worksheets/_rels/sheet1.xml.rels");
+        assert!(rels_xml.contains("<Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/drawing\" Target=\"../drawings/drawing1.xml\"/>"));
+
+        let drawing_rels_xml = read_part(&archive, "xl/drawings/_rels/drawing1.xml.rels");
+        assert!(drawing_rels_xml.contains("<Relationship Id=\"rId2\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/chart\" Target=\"../charts/chart2.xml\"/>"));
+
+        // 240 x 144 pixels at 9525 EMU each
+        let drawing_xml = read_part(&archive, "xl/drawings/drawing1.xml");
+        assert!(drawing_xml.contains("<xdr:from><xdr:col>3</xdr:col><xdr:colOff>0</xdr:colOff><xdr:row>20</xdr:row><xdr:rowOff>0</xdr:rowOff></xdr:from><xdr:ext cx=\"2286000\" cy=\"1371600\"/>"));
+        assert!(drawing_xml.contains("<xdr:cNvPr id=\"3\" name=\"Chart 2\"/>"));
+        assert!(drawing_xml.contains("<c:chart xmlns:c=\"http://schemas.openxmlformats.org/drawingml/2006/chart\" r:id=\"rId2\"/>"));
+
+        let chart_xml = read_part(&archive, "xl/charts/chart1.xml");
+        assert!(chart_xml.contains("<c:tx><c:v>Sales</c:v></c:tx>"));
+        assert!(chart_xml
+            .contains("<c:val><c:numRef><c:f>&apos;Data&apos;!$B$2:$B$4</c:f></c:numRef></c:val>"));
+    }
+}
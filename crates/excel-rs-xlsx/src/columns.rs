@@ -0,0 +1,278 @@
+use std::{collections::BTreeMap, io::Write};
+
+use anyhow::{bail, Result};
+
+use crate::rows::MAX_OUTLINE_LEVEL;
+
+pub const MAX_COLUMNS: u16 = 16384;
+const MAX_COLUMN_WIDTH: f64 = 255.0;
+// Extra room for cell padding and the filter button when auto-fitting
+const AUTOFIT_PADDING: f64 = 2.0;
+
+// Width and outline grouping of columns, set with Sheet::set_column_options
+//
+// Like rows, a collapsed group has its columns hidden and the summary column
+// to their right marked as collapsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ColumnOptions {
+    width: Option<f64>,
+    outline_level: u8,
+    hidden: bool,
+    collapsed: bool,
+}
+
+impl ColumnOptions {
+    pub fn new() -> Self {
+        ColumnOptions::default()
+    }
+
+    pub fn width(mut self, width: f64) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    pub fn outline_level(mut self, level: u8) -> Self {
+        self.outline_level = level;
+        self
+    }
+
+    pub fn hidden(mut self) -> Self {
+        self.hidden = true;
+        self
+    }
+
+    pub fn collapsed(mut self) -> Self {
+        self.collapsed = true;
+        self
+    }
+}
+
+struct AutoFit {
+    max_lens: Vec<usize>,
+    // None measures every row, which keeps the whole sheet in memory until close
+    rows_left: Option<u32>,
+}
+
+#[derive(Default)]
+pub(crate) struct Columns {
+    options: BTreeMap<u16, ColumnOptions>,
+    autofit: Option<AutoFit>,
+}
+
+impl Columns {
+    pub fn set_width(&mut self, first_col: u16, last_col: u16, width: f64) -> Result<()> {
+        validate_range(first_col, last_col)?;
+        validate_width(width)?;
+
+        for col in first_col..=last_col {
+            self.options.entry(col).or_default().width = Some(width);
+        }
+
+        Ok(())
+    }
+
+    // Replaces everything set for the columns so far, including their width
+    pub fn set_options(
+        &mut self,
+        first_col: u16,
+        last_col: u16,
+        options: &ColumnOptions,
+    ) -> Result<()> {
+        validate_range(first_col, last_col)?;
+        if let Some(width) = options.width {
+            validate_width(width)?;
+        }
+        if options.outline_level > MAX_OUTLINE_LEVEL {
+            bail!(
+                "outline level {} is deeper than {MAX_OUTLINE_LEVEL}",
+                options.outline_level
+            );
+        }
+
+        for col in first_col..=last_col {
+            self.options.insert(col, *options);
+        }
+
+        Ok(())
+    }
+
+    // The deepest column outline level, for <sheetFormatPr>
+    pub fn outline_level(&self) -> u8 {
+        self.options
+            .values()
+            .map(|column| column.outline_level)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn enable_autofit(&mut self, sample_rows: Option<u32>) {
+        self.autofit = Some(AutoFit {
+            max_lens: Vec::new(),
+            rows_left: sample_rows,
+        });
+    }
+
+    pub fn is_measuring(&self) -> bool {
+        match &self.autofit {
+            Some(autofit) => autofit.rows_left != Some(0),
+            None => false,
+        }
+    }
+
+    pub fn measure(&mut self, col: usize, len: usize) {
+        if let Some(autofit) = &mut self.autofit {
+            if autofit.max_lens.len() <= col {
+                autofit.max_lens.resize(col + 1, 0);
+            }
+            autofit.max_lens[col] = autofit.max_lens[col].max(len);
+        }
+    }
+
+    // Returns true once the sampled rows have all been measured
+    pub fn finish_row(&mut self) -> bool {
+        match &mut self.autofit {
+            Some(AutoFit {
+                rows_left: Some(rows_left),
+                ..
+            }) => {
+                *rows_left = rows_left.saturating_sub(1);
+                *rows_left == 0
+            }
+            _ => false,
+        }
+    }
+
+    pub fn write_xml(&self, buf: &mut Vec<u8>) -> Result<()> {
+        let mut options = self.options.clone();
+
+        if let Some(autofit) = &self.autofit {
+            for (col, len) in autofit.max_lens.iter().enumerate() {
+                if *len == 0 || col >= MAX_COLUMNS as usize {
+                    continue;
+                }
+
+                let column = options.entry(col as u16).or_default();
+                if column.width.is_none() {
+                    column.width = Some((*len as f64 + AUTOFIT_PADDING).min(MAX_COLUMN_WIDTH));
+                }
+            }
+        }
+
+        if options.is_empty() {
+            return Ok(());
+        }
+
+        buf.write_all(b"<cols>")?;
+
+        // Neighbouring columns with identical options share one <col> element
+        let mut iter = options.into_iter().peekable();
+        while let Some((first_col, column)) = iter.next() {
+            let mut last_col = first_col;
+            while let Some((next_col, next_column)) = iter.peek() {
+                if *next_col != last_col + 1 || *next_column != column {
+                    break;
+                }
+                last_col = *next_col;
+                iter.next();
+            }
+
+            write!(
+                buf,
+                "<col min=\"{}\" max=\"{}\"",
+                first_col + 1,
+                last_col + 1
+            )?;
+            if let Some(width) = column.width {
+                write!(buf, " width=\"{}\" customWidth=\"1\"", width)?;
+            }
+            if column.hidden {
+                buf.write_all(b" hidden=\"1\"")?;
+            }
+            if column.outline_level > 0 {
+                write!(buf, " outlineLevel=\"{}\"", column.outline_level)?;
+            }
+            if column.collapsed {
+                buf.write_all(b" collapsed=\"1\"")?;
+            }
+            buf.write_all(b"/>")?;
+        }
+
+        buf.write_all(b"</cols>")?;
+
+        Ok(())
+    }
+}
+
+fn validate_range(first_col: u16, last_col: u16) -> Result<()> {
+    if first_col > last_col || last_col >= MAX_COLUMNS {
+        bail!("invalid column range {first_col}..={last_col}");
+    }
+    Ok(())
+}
+
+fn validate_width(width: f64) -> Result<()> {
+    if !(0.0..=MAX_COLUMN_WIDTH).contains(&width) {
+        bail!("column width {width} must be between 0 and {MAX_COLUMN_WIDTH}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{read_part, write_workbook};
+
+    #[test]
+    fn groups_neighbouring_widths_into_one_col() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Sheet"))?;
+            sheet.set_column_range_width(0, 2, 20.0)?;
+            sheet.set_column_width(4, 8.5)?;
+            for width in [-1.0, 255.5, f64::NAN] {
+                assert!(sheet.set_column_width(5, width).is_err());
+            }
+            assert!(sheet.set_column_range_width(3, 2, 10.0).is_err());
+            assert!(sheet.set_column_width(16384, 10.0).is_err());
+
+            sheet.write_row(vec![b"a"])?;
+            assert!(sheet.set_column_width(5, 10.0).is_err());
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains(concat!(
+            "<cols><col min=\"1\" max=\"3\" width=\"20\" customWidth=\"1\"/>",
+            "<col min=\"5\" max=\"5\" width=\"8.5\" customWidth=\"1\"/></cols>\n<sheetData>"
+        )));
+    }
+
+    #[test]
+    fn fits_columns_to_their_longest_value() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Every row"))?;
+            sheet.autofit_columns(None)?;
+            sheet.set_column_width(2, 30.0)?;
+            sheet.write_row(vec![&b"a"[..], "héllo wörld".as_bytes(), b"c"])?;
+            sheet.write_row(vec![b"abcd"])?;
+            sheet.close()?;
+
+            let mut sheet = workbook.get_worksheet(String::from("First row"))?;
+            sheet.autofit_columns(Some(1))?;
+            sheet.write_row(vec![b"a"])?;
+            sheet.write_row(vec![b"abcdefgh"])?;
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains(concat!(
+            "<cols><col min=\"1\" max=\"1\" width=\"6\" customWidth=\"1\"/>",
+            "<col min=\"2\" max=\"2\" width=\"13\" customWidth=\"1\"/>",
+            "<col min=\"3\" max=\"3\" width=\"30\" customWidth=\"1\"/></cols>"
+        )));
+        assert!(sheet_xml.contains("<t>abcd</t>"));
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet2.xml");
+        assert!(sheet_xml
+            .contains("<cols><col min=\"1\" max=\"1\" width=\"3\" customWidth=\"1\"/></cols>"));
+        assert!(sheet_xml.contains("<t>abcdefgh</t>"));
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+use crate::cell::{cell_ref, escape_xml_into, range_ref, write_text_element};
+use crate::format::XlsxFormatter;
+
+const COMMENTS_CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.comments+xml";
+pub(crate) const VML_CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.vmlDrawing";
+
+const MAX_COMMENT_LEN: usize = 32767;
+
+// Notes are drawn as shapes in the default column width and row height
+const COLUMN_WIDTH_PT: f64 = 48.0;
+const ROW_HEIGHT_PT: f64 = 15.0;
+
+struct Comment {
+    author_id: usize,
+    text: String,
+}
+
+// The comments part of one sheet and the VML drawing Excel shows them with
+pub(crate) struct Comments {
+    id: u32,
+    vml_rel_id: String,
+    authors: Vec<String>,
+    comments: BTreeMap<(u32, u16), Comment>,
+}
+
+impl Comments {
+    pub fn new(id: u32, vml_rel_id: String) -> Self {
+        Comments {
+            id,
+            vml_rel_id,
+            authors: Vec::new(),
+            comments: BTreeMap::new(),
+        }
+    }
+
+    pub fn vml_rel_id(&self) -> &str {
+        &self.vml_rel_id
+    }
+
+    pub fn add(&mut self, row: u32, col: u16, text: &str, author: &str) -> Result<()> {
+        range_ref(row, col, row, col)?;
+
+        if text.is_empty() {
+            bail!("comment text cannot be empty");
+        }
+
+        if text.chars().count() > MAX_COMMENT_LEN {
+            bail!("comment is longer than {MAX_COMMENT_LEN} characters");
+        }
+
+        if self.comments.contains_key(&(row, col)) {
+            bail!("cell {} already has a comment", cell_ref(row, col));
+        }
+
+        let author_id = match self.authors.iter().position(|existing| existing == author) {
+            Some(index) => index,
+            None => {
+                self.authors.push(String::from(author));
+                self.authors.len() - 1
+            }
+        };
+
+        self.comments.insert(
+            (row, col),
+            Comment {
+                author_id,
+                text: String::from(text),
+            },
+        );
+
+        Ok(())
+    }
+
+    // Writes commentsN.xml and vmlDrawingN.vml
+    pub fn write_parts<W: Write>(&self, formatter: &mut XlsxFormatter<W>) -> Result<()> {
+        let options = formatter.options.part_options();
+
+        let part_name = format!("xl/comments{}.xml", self.id);
+        formatter
+            .zip_writer
+            .start_file(part_name.as_str(), options)?;
+        formatter.zip_writer.write_all(&self.comments_xml()?)?;
+        formatter
+            .content_types
+            .push((format!("/{}", part_name), COMMENTS_CONTENT_TYPE));
+
+        // Shape ids follow on from the blocks earlier sheets used
+        let first_block = formatter.vml_block_count + 1;
+        let last_block = (first_block * 1024 + self.comments.len() as u32) / 1024;
+        formatter.vml_block_count = last_block;
+
+        formatter
+            .zip_writer
+            .start_file(format!("xl/drawings/vmlDrawing{}.vml", self.id), options)?;
+        formatter
+            .zip_writer
+            .write_all(&self.vml_xml(first_block, last_block)?)?;
+
+        Ok(())
+    }
+
+    fn comments_xml(&self) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(256 + 128 * self.comments.len());
+        buf.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<comments xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\"><authors>")?;
+        for author in &self.authors {
+            buf.write_all(b"<author>")?;
+            escape_xml_into(&mut buf, author.as_bytes());
+            buf.write_all(b"</author>")?;
+        }
+        buf.write_all(b"</authors><commentList>")?;
+
+        for ((row, col), comment) in &self.comments {
+            write!(
+                buf,
+                "<comment ref=\"{}\" authorId=\"{}\"><text><r><rPr><sz val=\"9\"/><color indexed=\"81\"/><rFont val=\"Tahoma\"/><family val=\"2\"/></rPr>",
+                cell_ref(*row, *col),
+                comment.author_id
+            )?;
+            write_text_element(&mut buf, comment.text.as_bytes());
+            buf.write_all(b"</r></text></comment>")?;
+        }
+        buf.write_all(b"</commentList></comments>")?;
+
+        Ok(buf)
+    }
+
+    // Hidden notes to the right of their cells, shown on hover. The idmap
+    // lists each block of 1024 shape ids the notes take up.
+    fn vml_xml(&self, first_block: u32, last_block: u32) -> Result<Vec<u8>> {
+        let blocks = (first_block..=last_block)
+            .map(|block| block.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let mut buf = Vec::with_capacity(1024 + 768 * self.comments.len());
+        write!(
+            buf,
+            "<xml xmlns:v=\"urn:schemas-microsoft-com:vml\" xmlns:o=\"urn:schemas-microsoft-com:office:office\" xmlns:x=\"urn:schemas-microsoft-com:office:excel\"><o:shapelayout v:ext=\"edit\"><o:idmap v:ext=\"edit\" data=\"{}\"/></o:shapelayout><v:shapetype id=\"_x0000_t202\" coordsize=\"21600,21600\" o:spt=\"202\" path=\"m,l,21600r21600,l21600,xe\"><v:stroke joinstyle=\"miter\"/><v:path gradientshapeok=\"t\" o:connecttype=\"rect\"/></v:shapetype>",
+            blocks
+        )?;
+
+        for (i, (row, col)) in self.comments.keys().enumerate() {
+            // The note starts one column right of its cell and, below the
+            // first row, one row up
+            let top_row = row.saturating_sub(1);
+            write!(
+                buf,
+                "<v:shape id=\"_x0000_s{}\" type=\"#_x0000_t202\" style=\"position:absolute;margin-left:{}pt;margin-top:{}pt;width:108pt;height:59.25pt;z-index:{};visibility:hidden\" fillcolor=\"#ffffe1\" o:insetmode=\"auto\"><v:fill color2=\"#ffffe1\"/><v:shadow on=\"t\" color=\"black\" obscured=\"t\"/><v:path o:connecttype=\"none\"/><v:textbox style=\"mso-direction-alt:auto\"><div style=\"text-align:left\"></div></v:textbox><x:ClientData ObjectType=\"Note\"><x:MoveWithCells/><x:SizeWithCells/><x:Anchor>{}, 15, {}, 10, {}, 15, {}, 4</x:Anchor><x:AutoFill>False</x:AutoFill><x:Row>{}</x:Row><x:Column>{}</x:Column></x:ClientData></v:shape>",
+                first_block * 1024 + i as u32 + 1,
+                (*col as f64 + 1.0) * COLUMN_WIDTH_PT + 11.25,
+                top_row as f64 * ROW_HEIGHT_PT + 1.5,
+                i + 1,
+                *col as u32 + 1,
+                top_row,
+                *col as u32 + 3,
+                top_row as u64 + 4,
+                row,
+                col
+            )?;
+        }
+        buf.write_all(b"</xml>")?;
+
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{read_part, write_workbook};
+
+    #[test]
+    fn writes_notes_and_their_authors() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Notes"))?;
+            sheet.write_comment(2, 1, "Check <this>", Some("Ann"))?;
+            sheet.write_comment(0, 0, "First", Some("Bob"))?;
+            sheet.write_comment(0, 3, "Again", Some("Ann"))?;
+            assert!(sheet.write_comment(0, 3, "Twice", None).is_err());
+            assert!(sheet.write_comment(1, 1, "", None).is_err());
+            sheet.close()
+        });
+
+        let comments_xml = read_part(&archive, "xl/comments1.xml");
+        assert!(
+            comments_xml.contains("<authors><author>Ann</author><author>Bob</author></authors>")
+        );
+        // Notes are listed by row, then column
+        let positions = [
+            "<comment ref=\"A1\" authorId=\"1\">",
+            "<comment ref=\"D1\" authorId=\"0\">",
+            "<comment ref=\"B3\" authorId=\"0\">",
+        ]
+        .map(|comment| comments_xml.find(comment).unwrap());
+        assert!(positions[0] < positions[1] && positions[1] < positions[2]);
+        assert!(comments_xml.contains("<t>Check &lt;this&gt;</t>"));
+
+        let vml = read_part(&archive, "xl/drawings/vmlDrawing1.vml");
+        assert!(vml.contains("<o:idmap v:ext=\"edit\" data=\"1\"/>"));
+        assert!(vml.contains("<v:shape id=\"_x0000_s1027\" type=\"#_x0000_t202\" style=\"position:absolute;margin-left:107.25pt;margin-top:16.5pt;width:108pt;height:59.25pt;z-index:3;visibility:hidden\""));
+        assert!(vml.contains("<x:Anchor>2, 15, 1, 10, 4, 15, 5, 4</x:Anchor><x:AutoFill>False</x:AutoFill><x:Row>2</x:Row><x:Column>1</x:Column>"));
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains("<legacyDrawing r:id=\"rId2\"/>"));
+        let rels_xml = read_part(&archive, "xl/worksheets/_rels/sheet1.xml.rels");
+        assert!(rels_xml.contains("<Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/comments\" Target=\"../comments1.xml\"/>"));
+        assert!(rels_xml.contains("<Relationship Id=\"rId2\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/vmlDrawing\" Target=\"../drawings/vmlDrawing1.vml\"/>"));
+
+        let content_types_xml = read_part(&archive, "[Content_Types].xml");
+        assert!(content_types_xml.contains("<Default Extension=\"vml\" ContentType=\"application/vnd.openxmlformats-officedocument.vmlDrawing\"/>"));
+        assert!(content_types_xml.contains("<Override PartName=\"/xl/comments1.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.comments+xml\"/>"));
+    }
+
+    #[test]
+    fn gives_each_sheet_its_own_shape_id_blocks() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Many"))?;
+            for row in 0..1100 {
+                sheet.write_comment(row, 0, "Note", None)?;
+            }
+            sheet.close()?;
+
+            let mut sheet = workbook.get_worksheet(String::from("One"))?;
+            sheet.write_comment(0, 0, "Note", None)?;
+            sheet.close()
+        });
+
+        let vml = read_part(&archive, "xl/drawings/vmlDrawing1.vml");
+        assert!(vml.contains("<o:idmap v:ext=\"edit\" data=\"1,2\"/>"));
+        assert!(vml.contains("<v:shape id=\"_x0000_s1025\""));
+        assert!(vml.contains("<v:shape id=\"_x0000_s2124\""));
+        assert!(!vml.contains("<v:shape id=\"_x0000_s2125\""));
+
+        let vml = read_part(&archive, "xl/drawings/vmlDrawing2.vml");
+        assert!(vml.contains("<o:idmap v:ext=\"edit\" data=\"3\"/>"));
+        assert!(vml.contains("<v:shape id=\"_x0000_s3073\""));
+    }
+}
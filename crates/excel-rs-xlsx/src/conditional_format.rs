@@ -0,0 +1,338 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+use crate::cell::{escape_xml_into, CellRange};
+use crate::styles::{validate_color, Format, StyleRegistry};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    LessThan,
+    LessThanOrEqual,
+    Between,
+    NotBetween,
+}
+
+impl Comparison {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Comparison::Equal => "equal",
+            Comparison::NotEqual => "notEqual",
+            Comparison::GreaterThan => "greaterThan",
+            Comparison::GreaterThanOrEqual => "greaterThanOrEqual",
+            Comparison::LessThan => "lessThan",
+            Comparison::LessThanOrEqual => "lessThanOrEqual",
+            Comparison::Between => "between",
+            Comparison::NotBetween => "notBetween",
+        }
+    }
+
+    pub(crate) fn operand_count(&self) -> usize {
+        match self {
+            Comparison::Between | Comparison::NotBetween => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Rule {
+    CellValue(Comparison, Vec<String>),
+    Formula(String),
+    // Colours for the lowest, middle (50th percentile) and highest values
+    ColorScale(u32, Option<u32>, u32),
+    DataBar(u32),
+}
+
+// A rule added with Sheet::add_conditional_format
+//
+// Values and formulas are written as in Excel, e.g. "100", "\"Late\"",
+// "$B$1" or "$C2>$D2", with or without the leading '='. Formulas are
+// relative to the top-left cell of the range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionalFormat {
+    rule: Rule,
+    format: Option<Format>,
+    stop_if_true: bool,
+}
+
+impl ConditionalFormat {
+    // Formats cells whose value compares true against `values`, which needs
+    // two values for Between and NotBetween and one otherwise
+    pub fn cell_value(comparison: Comparison, values: &[&str], format: Format) -> Self {
+        ConditionalFormat {
+            rule: Rule::CellValue(
+                comparison,
+                values.iter().map(|value| strip_equals(value)).collect(),
+            ),
+            format: Some(format),
+            stop_if_true: false,
+        }
+    }
+
+    // Formats cells for which the formula is true
+    pub fn formula(formula: &str, format: Format) -> Self {
+        ConditionalFormat {
+            rule: Rule::Formula(strip_equals(formula)),
+            format: Some(format),
+            stop_if_true: false,
+        }
+    }
+
+    pub fn two_color_scale(min_color: u32, max_color: u32) -> Self {
+        ConditionalFormat {
+            rule: Rule::ColorScale(min_color, None, max_color),
+            format: None,
+            stop_if_true: false,
+        }
+    }
+
+    pub fn three_color_scale(min_color: u32, mid_color: u32, max_color: u32) -> Self {
+        ConditionalFormat {
+            rule: Rule::ColorScale(min_color, Some(mid_color), max_color),
+            format: None,
+            stop_if_true: false,
+        }
+    }
+
+    pub fn data_bar(color: u32) -> Self {
+        ConditionalFormat {
+            rule: Rule::DataBar(color),
+            format: None,
+            stop_if_true: false,
+        }
+    }
+
+    // Skips the rules added after this one when it applies
+    pub fn stop_if_true(mut self) -> Self {
+        self.stop_if_true = true;
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        match &self.rule {
+            Rule::CellValue(comparison, values) => {
+                if values.len() != comparison.operand_count() {
+                    bail!(
+                        "{} rule needs {} value(s), got {}",
+                        comparison.as_str(),
+                        comparison.operand_count(),
+                        values.len()
+                    );
+                }
+                if values.iter().any(|value| value.is_empty()) {
+                    bail!("conditional format value cannot be empty");
+                }
+            }
+            Rule::Formula(formula) => {
+                if formula.is_empty() {
+                    bail!("conditional format formula cannot be empty");
+                }
+            }
+            Rule::ColorScale(min_color, mid_color, max_color) => {
+                for color in [Some(*min_color), *mid_color, Some(*max_color)]
+                    .into_iter()
+                    .flatten()
+                {
+                    validate_color(color)?;
+                }
+            }
+            Rule::DataBar(color) => validate_color(*color)?,
+        }
+
+        if let Some(format) = &self.format {
+            format.validate()?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Entry {
+    sqref: String,
+    rule: ConditionalFormat,
+    dxf_id: Option<u32>,
+}
+
+#[derive(Default)]
+pub(crate) struct ConditionalFormats {
+    entries: Vec<Entry>,
+}
+
+impl ConditionalFormats {
+    // The rule's Format is registered as a differential format
+    pub fn add(
+        &mut self,
+        range: CellRange,
+        rule: &ConditionalFormat,
+        styles: &mut StyleRegistry,
+    ) -> Result<()> {
+        let sqref = range.to_ref()?;
+        rule.validate()?;
+
+        let dxf_id = rule.format.as_ref().map(|format| styles.add_dxf(format));
+
+        self.entries.push(Entry {
+            sqref,
+            rule: rule.clone(),
+            dxf_id,
+        });
+
+        Ok(())
+    }
+
+    // Rules are evaluated in the order they were added
+    pub fn write_xml(&self, buf: &mut Vec<u8>) -> Result<()> {
+        for (i, entry) in self.entries.iter().enumerate() {
+            let rule = &entry.rule;
+            let rule_type = match rule.rule {
+                Rule::CellValue(..) => "cellIs",
+                Rule::Formula(_) => "expression",
+                Rule::ColorScale(..) => "colorScale",
+                Rule::DataBar(_) => "dataBar",
+            };
+
+            write!(
+                buf,
+                "<conditionalFormatting sqref=\"{}\"><cfRule type=\"{}\"",
+                entry.sqref, rule_type
+            )?;
+            if let Some(dxf_id) = entry.dxf_id {
+                write!(buf, " dxfId=\"{}\"", dxf_id)?;
+            }
+            write!(buf, " priority=\"{}\"", i + 1)?;
+            if rule.stop_if_true {
+                buf.write_all(b" stopIfTrue=\"1\"")?;
+            }
+
+            match &rule.rule {
+                Rule::CellValue(comparison, values) => {
+                    write!(buf, " operator=\"{}\">", comparison.as_str())?;
+                    for value in values {
+                        write_formula(buf, value)?;
+                    }
+                }
+                Rule::Formula(formula) => {
+                    buf.write_all(b">")?;
+                    write_formula(buf, formula)?;
+                }
+                Rule::ColorScale(min_color, mid_color, max_color) => {
+                    buf.write_all(b"><colorScale><cfvo type=\"min\"/>")?;
+                    if mid_color.is_some() {
+                        buf.write_all(b"<cfvo type=\"percentile\" val=\"50\"/>")?;
+                    }
+                    buf.write_all(b"<cfvo type=\"max\"/>")?;
+                    for color in [Some(min_color), mid_color.as_ref(), Some(max_color)]
+                        .into_iter()
+                        .flatten()
+                    {
+                        write!(buf, "<color rgb=\"FF{:06X}\"/>", color)?;
+                    }
+                    buf.write_all(b"</colorScale>")?;
+                }
+                Rule::DataBar(color) => {
+                    write!(
+                        buf,
+                        "><dataBar><cfvo type=\"min\"/><cfvo type=\"max\"/><color rgb=\"FF{:06X}\"/></dataBar>",
+                        color
+                    )?;
+                }
+            }
+
+            buf.write_all(b"</cfRule></conditionalFormatting>")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn strip_equals(formula: &str) -> String {
+    formula.strip_prefix('=').unwrap_or(formula).to_owned()
+}
+
+fn write_formula(buf: &mut Vec<u8>, formula: &str) -> Result<()> {
+    buf.write_all(b"<formula>")?;
+    escape_xml_into(buf, formula.as_bytes());
+    buf.write_all(b"</formula>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Comparison, ConditionalFormat, ConditionalFormats};
+    use crate::cell::CellRange;
+    use crate::styles::{Format, StyleRegistry};
+
+    fn write_rules(rules: &[ConditionalFormat]) -> String {
+        let mut styles = StyleRegistry::new();
+        let mut formats = ConditionalFormats::default();
+        for rule in rules {
+            formats
+                .add(CellRange::new(1, 0, 9, 1), rule, &mut styles)
+                .unwrap();
+        }
+
+        let mut buf = Vec::new();
+        formats.write_xml(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn writes_rules_in_priority_order() {
+        let red = Format::new().font_color(0x9C0006);
+        let xml = write_rules(&[
+            ConditionalFormat::cell_value(Comparison::Between, &["=1", "$B$1"], red.clone())
+                .stop_if_true(),
+            ConditionalFormat::formula("=$A2<>\"Done\"", Format::new().bold()),
+            ConditionalFormat::cell_value(Comparison::LessThan, &["0"], red),
+        ]);
+
+        assert_eq!(
+            xml,
+            concat!(
+                "<conditionalFormatting sqref=\"A2:B10\"><cfRule type=\"cellIs\" dxfId=\"0\" priority=\"1\" stopIfTrue=\"1\" operator=\"between\">",
+                "<formula>1</formula><formula>$B$1</formula></cfRule></conditionalFormatting>",
+                "<conditionalFormatting sqref=\"A2:B10\"><cfRule type=\"expression\" dxfId=\"1\" priority=\"2\">",
+                "<formula>$A2&lt;&gt;&quot;Done&quot;</formula></cfRule></conditionalFormatting>",
+                "<conditionalFormatting sqref=\"A2:B10\"><cfRule type=\"cellIs\" dxfId=\"0\" priority=\"3\" operator=\"lessThan\">",
+                "<formula>0</formula></cfRule></conditionalFormatting>"
+            )
+        );
+    }
+
+    #[test]
+    fn writes_scales_and_bars_without_a_format() {
+        let xml = write_rules(&[
+            ConditionalFormat::two_color_scale(0xF8696B, 0x63BE7B),
+            ConditionalFormat::three_color_scale(0xF8696B, 0xFFEB84, 0x63BE7B),
+            ConditionalFormat::data_bar(0x638EC6),
+        ]);
+
+        assert!(!xml.contains("dxfId"));
+        assert!(xml.contains("<cfRule type=\"colorScale\" priority=\"1\"><colorScale><cfvo type=\"min\"/><cfvo type=\"max\"/><color rgb=\"FFF8696B\"/><color rgb=\"FF63BE7B\"/></colorScale>"));
+        assert!(xml.contains("<colorScale><cfvo type=\"min\"/><cfvo type=\"percentile\" val=\"50\"/><cfvo type=\"max\"/><color rgb=\"FFF8696B\"/><color rgb=\"FFFFEB84\"/><color rgb=\"FF63BE7B\"/></colorScale>"));
+        assert!(xml.contains("<cfRule type=\"dataBar\" priority=\"3\"><dataBar><cfvo type=\"min\"/><cfvo type=\"max\"/><color rgb=\"FF638EC6\"/></dataBar>"));
+    }
+
+    #[test]
+    fn rejects_incomplete_rules() {
+        let mut styles = StyleRegistry::new();
+        let mut formats = ConditionalFormats::default();
+        let range = CellRange::new(0, 0, 0, 0);
+
+        for rule in [
+            ConditionalFormat::cell_value(Comparison::Between, &["1"], Format::new()),
+            ConditionalFormat::cell_value(Comparison::Equal, &["="], Format::new()),
+            ConditionalFormat::formula("", Format::new()),
+            ConditionalFormat::data_bar(0x1000000),
+            ConditionalFormat::formula("A1", Format::new().font_size(0.0)),
+        ] {
+            assert!(formats.add(range, &rule, &mut styles).is_err());
+        }
+        assert!(styles.is_unused());
+    }
+}
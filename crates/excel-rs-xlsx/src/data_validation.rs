@@ -0,0 +1,395 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+use crate::cell::{escape_xml_into, CellRange};
+use crate::conditional_format::Comparison;
+
+// Excel rejects longer lists, titles and messages
+const MAX_LIST_LEN: usize = 255;
+const MAX_TITLE_LEN: usize = 32;
+const MAX_MESSAGE_LEN: usize = 255;
+
+// What happens when a value breaks the rule
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ValidationErrorStyle {
+    // The value is refused
+    #[default]
+    Stop,
+    // The user may keep the value after a warning
+    Warning,
+    // The value is kept after a message
+    Information,
+}
+
+impl ValidationErrorStyle {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ValidationErrorStyle::Stop => "stop",
+            ValidationErrorStyle::Warning => "warning",
+            ValidationErrorStyle::Information => "information",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Rule {
+    List(Vec<String>),
+    ListRange(String, CellRange),
+    WholeNumber(Comparison, Vec<String>),
+    Decimal(Comparison, Vec<String>),
+    Date(Comparison, Vec<String>),
+    TextLength(Comparison, Vec<String>),
+    Custom(String),
+}
+
+impl Rule {
+    fn type_str(&self) -> &'static str {
+        match self {
+            Rule::List(_) | Rule::ListRange(..) => "list",
+            Rule::WholeNumber(..) => "whole",
+            Rule::Decimal(..) => "decimal",
+            Rule::Date(..) => "date",
+            Rule::TextLength(..) => "textLength",
+            Rule::Custom(_) => "custom",
+        }
+    }
+}
+
+// Input constraints added with Sheet::add_data_validation
+//
+// Limits are written as in Excel, e.g. "10", "$B$1" or "DATE(2024,1,1)",
+// with or without the leading '='.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataValidation {
+    rule: Rule,
+    ignore_blank: bool,
+    hide_dropdown: bool,
+    input_message: Option<(String, String)>,
+    error_message: Option<(String, String)>,
+    error_style: ValidationErrorStyle,
+}
+
+impl DataValidation {
+    fn new(rule: Rule) -> Self {
+        DataValidation {
+            rule,
+            ignore_blank: true,
+            hide_dropdown: false,
+            input_message: None,
+            error_message: None,
+            error_style: ValidationErrorStyle::default(),
+        }
+    }
+
+    // A dropdown of the given values, which cannot contain commas
+    pub fn list(values: &[&str]) -> Self {
+        DataValidation::new(Rule::List(
+            values.iter().map(|value| String::from(*value)).collect(),
+        ))
+    }
+
+    // A dropdown of the values in a range of any sheet in the workbook
+    pub fn list_range(sheet_name: &str, range: CellRange) -> Self {
+        DataValidation::new(Rule::ListRange(String::from(sheet_name), range))
+    }
+
+    // `limits` needs two values for Between and NotBetween and one otherwise
+    pub fn whole_number(comparison: Comparison, limits: &[&str]) -> Self {
+        DataValidation::new(Rule::WholeNumber(comparison, strip_equals(limits)))
+    }
+
+    pub fn decimal(comparison: Comparison, limits: &[&str]) -> Self {
+        DataValidation::new(Rule::Decimal(comparison, strip_equals(limits)))
+    }
+
+    pub fn date(comparison: Comparison, limits: &[&str]) -> Self {
+        DataValidation::new(Rule::Date(comparison, strip_equals(limits)))
+    }
+
+    pub fn text_length(comparison: Comparison, limits: &[&str]) -> Self {
+        DataValidation::new(Rule::TextLength(comparison, strip_equals(limits)))
+    }
+
+    // Accepts values for which the formula is true. The formula is relative
+    // to the top-left cell of the range.
+    pub fn custom(formula: &str) -> Self {
+        DataValidation::new(Rule::Custom(String::from(
+            formula.strip_prefix('=').unwrap_or(formula),
+        )))
+    }
+
+    // Blank cells are accepted unless this is called
+    pub fn reject_blank(mut self) -> Self {
+        self.ignore_blank = false;
+        self
+    }
+
+    // Lists show a dropdown unless this is called
+    pub fn hide_dropdown(mut self) -> Self {
+        self.hide_dropdown = true;
+        self
+    }
+
+    // Shown when one of the cells is selected
+    pub fn input_message(mut self, title: &str, message: &str) -> Self {
+        self.input_message = Some((String::from(title), String::from(message)));
+        self
+    }
+
+    // Shown when a value breaks the rule, instead of Excel's generic message
+    pub fn error_message(mut self, title: &str, message: &str) -> Self {
+        self.error_message = Some((String::from(title), String::from(message)));
+        self
+    }
+
+    pub fn error_style(mut self, style: ValidationErrorStyle) -> Self {
+        self.error_style = style;
+        self
+    }
+
+    // The formula1 and formula2 contents
+    fn formulas(&self) -> Result<Vec<String>> {
+        let (comparison, limits) = match &self.rule {
+            Rule::List(values) => {
+                if values.is_empty() {
+                    bail!("data validation list cannot be empty");
+                }
+                if values.iter().any(|value| value.contains(',')) {
+                    bail!("data validation list values cannot contain commas");
+                }
+
+                let list = values.join(",");
+                if list.chars().count() > MAX_LIST_LEN {
+                    bail!("data validation list is longer than {MAX_LIST_LEN} characters");
+                }
+                return Ok(vec![format!("\"{}\"", list.replace('"', "\"\""))]);
+            }
+            Rule::ListRange(sheet_name, range) => return Ok(vec![range.to_formula(sheet_name)?]),
+            Rule::Custom(formula) => {
+                if formula.is_empty() {
+                    bail!("data validation formula cannot be empty");
+                }
+                return Ok(vec![formula.clone()]);
+            }
+            Rule::WholeNumber(comparison, limits)
+            | Rule::Decimal(comparison, limits)
+            | Rule::Date(comparison, limits)
+            | Rule::TextLength(comparison, limits) => (comparison, limits),
+        };
+
+        if limits.len() != comparison.operand_count() {
+            bail!(
+                "{} validation needs {} limit(s), got {}",
+                comparison.as_str(),
+                comparison.operand_count(),
+                limits.len()
+            );
+        }
+        if limits.iter().any(|limit| limit.is_empty()) {
+            bail!("data validation limit cannot be empty");
+        }
+
+        Ok(limits.clone())
+    }
+
+    fn comparison(&self) -> Option<Comparison> {
+        match &self.rule {
+            Rule::WholeNumber(comparison, _)
+            | Rule::Decimal(comparison, _)
+            | Rule::Date(comparison, _)
+            | Rule::TextLength(comparison, _) => Some(*comparison),
+            Rule::List(_) | Rule::ListRange(..) | Rule::Custom(_) => None,
+        }
+    }
+}
+
+struct Entry {
+    sqref: String,
+    validation: DataValidation,
+    formulas: Vec<String>,
+}
+
+#[derive(Default)]
+pub(crate) struct DataValidations {
+    entries: Vec<Entry>,
+}
+
+impl DataValidations {
+    pub fn add(&mut self, range: CellRange, validation: &DataValidation) -> Result<()> {
+        let sqref = range.to_ref()?;
+        let formulas = validation.formulas()?;
+
+        for (title, message) in [&validation.input_message, &validation.error_message]
+            .into_iter()
+            .flatten()
+        {
+            if title.chars().count() > MAX_TITLE_LEN {
+                bail!("data validation title is longer than {MAX_TITLE_LEN} characters");
+            }
+            if message.chars().count() > MAX_MESSAGE_LEN {
+                bail!("data validation message is longer than {MAX_MESSAGE_LEN} characters");
+            }
+        }
+
+        self.entries.push(Entry {
+            sqref,
+            validation: validation.clone(),
+            formulas,
+        });
+
+        Ok(())
+    }
+
+    pub fn write_xml(&self, buf: &mut Vec<u8>) -> Result<()> {
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+
+        write!(buf, "<dataValidations count=\"{}\">", self.entries.len())?;
+        for entry in &self.entries {
+            let validation = &entry.validation;
+
+            write!(
+                buf,
+                "<dataValidation type=\"{}\"",
+                validation.rule.type_str()
+            )?;
+            if validation.error_style != ValidationErrorStyle::Stop {
+                write!(buf, " errorStyle=\"{}\"", validation.error_style.as_str())?;
+            }
+            if let Some(comparison) = validation.comparison() {
+                write!(buf, " operator=\"{}\"", comparison.as_str())?;
+            }
+            if validation.ignore_blank {
+                buf.write_all(b" allowBlank=\"1\"")?;
+            }
+            // Despite its name, showDropDown hides the dropdown
+            if validation.hide_dropdown {
+                buf.write_all(b" showDropDown=\"1\"")?;
+            }
+            buf.write_all(b" showInputMessage=\"1\" showErrorMessage=\"1\"")?;
+            if let Some((title, message)) = &validation.error_message {
+                write_attribute(buf, "errorTitle", title)?;
+                write_attribute(buf, "error", message)?;
+            }
+            if let Some((title, message)) = &validation.input_message {
+                write_attribute(buf, "promptTitle", title)?;
+                write_attribute(buf, "prompt", message)?;
+            }
+            write!(buf, " sqref=\"{}\">", entry.sqref)?;
+
+            for (i, formula) in entry.formulas.iter().enumerate() {
+                write!(buf, "<formula{}>", i + 1)?;
+                escape_xml_into(buf, formula.as_bytes());
+                write!(buf, "</formula{}>", i + 1)?;
+            }
+            buf.write_all(b"</dataValidation>")?;
+        }
+        buf.write_all(b"</dataValidations>")?;
+
+        Ok(())
+    }
+}
+
+fn strip_equals(limits: &[&str]) -> Vec<String> {
+    limits
+        .iter()
+        .map(|limit| String::from(limit.strip_prefix('=').unwrap_or(limit)))
+        .collect()
+}
+
+fn write_attribute(buf: &mut Vec<u8>, name: &str, value: &str) -> Result<()> {
+    write!(buf, " {}=\"", name)?;
+    escape_xml_into(buf, value.as_bytes());
+    buf.write_all(b"\"")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DataValidation, DataValidations, ValidationErrorStyle};
+    use crate::cell::CellRange;
+    use crate::conditional_format::Comparison;
+
+    fn write_validations(validations: &[DataValidation]) -> String {
+        let mut entries = DataValidations::default();
+        for validation in validations {
+            entries
+                .add(CellRange::new(1, 2, 99, 2), validation)
+                .unwrap();
+        }
+
+        let mut buf = Vec::new();
+        entries.write_xml(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn writes_lists_and_limits() {
+        let xml = write_validations(&[
+            DataValidation::list(&["Yes", "No", "\"Maybe\""])
+                .hide_dropdown()
+                .input_message("Answer", "Pick one"),
+            DataValidation::list_range("Lookup's", CellRange::new(0, 0, 9, 0)),
+            DataValidation::whole_number(Comparison::Between, &["=1", "$B$1"])
+                .reject_blank()
+                .error_style(ValidationErrorStyle::Warning)
+                .error_message("Out of range", "Use 1 to <max>"),
+            DataValidation::date(Comparison::GreaterThan, &["DATE(2024,1,1)"]),
+            DataValidation::custom("=ISNUMBER(C2)"),
+        ]);
+
+        assert_eq!(
+            xml,
+            concat!(
+                "<dataValidations count=\"5\">",
+                "<dataValidation type=\"list\" allowBlank=\"1\" showDropDown=\"1\" showInputMessage=\"1\" showErrorMessage=\"1\" promptTitle=\"Answer\" prompt=\"Pick one\" sqref=\"C2:C100\">",
+                "<formula1>&quot;Yes,No,&quot;&quot;Maybe&quot;&quot;&quot;</formula1></dataValidation>",
+                "<dataValidation type=\"list\" allowBlank=\"1\" showInputMessage=\"1\" showErrorMessage=\"1\" sqref=\"C2:C100\">",
+                "<formula1>&apos;Lookup&apos;&apos;s&apos;!$A$1:$A$10</formula1></dataValidation>",
+                "<dataValidation type=\"whole\" errorStyle=\"warning\" operator=\"between\" showInputMessage=\"1\" showErrorMessage=\"1\" errorTitle=\"Out of range\" error=\"Use 1 to &lt;max&gt;\" sqref=\"C2:C100\">",
+                "<formula1>1</formula1><formula2>$B$1</formula2></dataValidation>",
+                "<dataValidation type=\"date\" operator=\"greaterThan\" allowBlank=\"1\" showInputMessage=\"1\" showErrorMessage=\"1\" sqref=\"C2:C100\">",
+                "<formula1>DATE(2024,1,1)</formula1></dataValidation>",
+                "<dataValidation type=\"custom\" allowBlank=\"1\" showInputMessage=\"1\" showErrorMessage=\"1\" sqref=\"C2:C100\">",
+                "<formula1>ISNUMBER(C2)</formula1></dataValidation>",
+                "</dataValidations>"
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_what_excel_refuses() {
+        let long_list = vec!["abcd"; 52];
+        let mut entries = DataValidations::default();
+        let range = CellRange::new(0, 0, 0, 0);
+
+        for validation in [
+            DataValidation::list(&[]),
+            DataValidation::list(&["a,b"]),
+            DataValidation::list(&long_list),
+            DataValidation::decimal(Comparison::NotBetween, &["1"]),
+            DataValidation::text_length(Comparison::LessThan, &["="]),
+            DataValidation::custom(""),
+            DataValidation::custom("A1").input_message(&"t".repeat(33), "message"),
+            DataValidation::custom("A1").error_message("title", &"m".repeat(256)),
+        ] {
+            assert!(entries.add(range, &validation).is_err());
+        }
+
+        // 51 values and their commas come to 254 characters
+        entries
+            .add(range, &DataValidation::list(&long_list[1..]))
+            .unwrap();
+        let mut buf = Vec::new();
+        entries.write_xml(&mut buf).unwrap();
+        assert!(String::from_utf8(buf)
+            .unwrap()
+            .starts_with("<dataValidations count=\"1\">"));
+
+        let mut buf = Vec::new();
+        DataValidations::default().write_xml(&mut buf).unwrap();
+        assert!(buf.is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+use std::io::{self, Write};
+
+use anyhow::{bail, Result};
+
+use crate::cell::escape_xml_into;
+use crate::tables::looks_like_cell_ref;
+
+const MAX_NAME_LEN: usize = 255;
+const BUILTIN_PREFIX: &str = "_xlnm.";
+
+pub(crate) const PRINT_AREA: &str = "_xlnm.Print_Area";
+pub(crate) const PRINT_TITLES: &str = "_xlnm.Print_Titles";
+pub(crate) const FILTER_DATABASE: &str = "_xlnm._FilterDatabase";
+
+struct DefinedName {
+    name: String,
+    formula: String,
+    // Zero-based position of the sheet the name is local to
+    scope: Option<u16>,
+}
+
+#[derive(Default)]
+pub(crate) struct DefinedNames {
+    names: Vec<DefinedName>,
+}
+
+impl DefinedNames {
+    // Built-in names such as _xlnm.Print_Area must be local to a sheet
+    pub fn add(&mut self, name: &str, formula: &str, scope: Option<u16>) -> Result<()> {
+        if name.starts_with(BUILTIN_PREFIX) {
+            if ![PRINT_AREA, PRINT_TITLES, FILTER_DATABASE].contains(&name) {
+                bail!("{name:?} is not a supported built-in name");
+            }
+            if scope.is_none() {
+                bail!("built-in name {name:?} must be scoped to a sheet");
+            }
+        } else {
+            validate_name(name)?;
+        }
+
+        let formula = formula.strip_prefix('=').unwrap_or(formula);
+        if formula.is_empty() {
+            bail!("defined name {name:?} needs a formula");
+        }
+
+        if self.find(name, scope).is_some() {
+            match scope {
+                Some(_) => bail!("name {name:?} is already defined for this sheet"),
+                None => bail!("name {name:?} is already defined for the workbook"),
+            }
+        }
+
+        self.names.push(DefinedName {
+            name: name.to_owned(),
+            formula: formula.to_owned(),
+            scope,
+        });
+
+        Ok(())
+    }
+
+    // Whether the names defined by a sheet built apart from the workbook can
+    // be appended. They were validated when the sheet defined them, so only
+    // clashes with names defined since are left.
+    pub fn check_append(&self, other: &DefinedNames) -> Result<()> {
+        for defined_name in &other.names {
+            if defined_name.name != FILTER_DATABASE
+                && self.contains(&defined_name.name, defined_name.scope)
+            {
+                bail!(
+                    "name {:?} is already defined for this sheet",
+                    defined_name.name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Adds names that passed check_append. As when closing a sheet, a
+    // filter range defined by the user wins.
+    pub fn append(&mut self, other: DefinedNames) {
+        for defined_name in other.names {
+            if defined_name.name == FILTER_DATABASE
+                && self.contains(FILTER_DATABASE, defined_name.scope)
+            {
+                continue;
+            }
+            self.names.push(defined_name);
+        }
+    }
+
+    // Names are case-insensitive
+    pub fn contains(&self, name: &str, scope: Option<u16>) -> bool {
+        self.find(name, scope).is_some()
+    }
+
+    // Whether any sheet or the workbook defines the name
+    pub fn is_defined(&self, name: &str) -> bool {
+        let lowercase_name = name.to_lowercase();
+        self.names
+            .iter()
+            .any(|existing| existing.name.to_lowercase() == lowercase_name)
+    }
+
+    fn find(&self, name: &str, scope: Option<u16>) -> Option<&DefinedName> {
+        let lowercase_name = name.to_lowercase();
+        self.names.iter().find(|existing| {
+            existing.scope == scope && existing.name.to_lowercase() == lowercase_name
+        })
+    }
+
+    pub fn write_xml<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if self.names.is_empty() {
+            return Ok(());
+        }
+
+        let mut buf = Vec::with_capacity(64 * self.names.len());
+        buf.write_all(b"<definedNames>")?;
+        for defined_name in &self.names {
+            buf.write_all(b"<definedName name=\"")?;
+            escape_xml_into(&mut buf, defined_name.name.as_bytes());
+            buf.write_all(b"\"")?;
+            if let Some(scope) = defined_name.scope {
+                write!(buf, " localSheetId=\"{}\"", scope)?;
+            }
+            // Excel keeps the filter range out of the Name Manager
+            if defined_name.name == FILTER_DATABASE {
+                buf.write_all(b" hidden=\"1\"")?;
+            }
+            buf.write_all(b">")?;
+            escape_xml_into(&mut buf, defined_name.formula.as_bytes());
+            buf.write_all(b"</definedName>")?;
+        }
+        buf.write_all(b"</definedNames>")?;
+        writer.write_all(&buf)?;
+
+        Ok(())
+    }
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+
+    let valid_start = match chars.next() {
+        Some(c) => c.is_alphabetic() || c == '_' || c == '\\',
+        None => bail!("defined name cannot be empty"),
+    };
+
+    if !valid_start || !chars.all(|c| c.is_alphanumeric() || c == '_' || c == '.' || c == '\\') {
+        bail!("defined name {name:?} must start with a letter or underscore and contain only letters, digits, underscores and periods");
+    }
+
+    if name.chars().count() > MAX_NAME_LEN {
+        bail!("defined name {name:?} is longer than {MAX_NAME_LEN} characters");
+    }
+
+    if looks_like_cell_ref(name) {
+        bail!("defined name {name:?} cannot look like a cell reference");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DefinedNames, FILTER_DATABASE, PRINT_AREA};
+    use crate::test_util::{read_part, write_workbook};
+
+    #[test]
+    fn rejects_names_excel_refuses() {
+        let mut names = DefinedNames::default();
+        for name in [
+            "",
+            "1st",
+            "Tax rate",
+            "A1",
+            "xfd1048576",
+            "R1C1",
+            "c",
+            "_xlnm.Criteria",
+        ] {
+            assert!(names.add(name, "0.2", None).is_err(), "{name:?}");
+        }
+        assert!(names.add(&"n".repeat(256), "1", None).is_err());
+        assert!(names.add(PRINT_AREA, "$A$1", None).is_err());
+        assert!(names.add("Empty", "=", None).is_err());
+
+        for name in ["Tax_rate", "_total", "\\path", "Q1.Sales", "ABCD1", "Größe"] {
+            names.add(name, "1", None).unwrap();
+        }
+    }
+
+    #[test]
+    fn keeps_names_unique_within_their_scope() {
+        let mut names = DefinedNames::default();
+        names.add("Rate", "0.2", None).unwrap();
+        names.add("rate", "0.3", Some(0)).unwrap();
+        assert!(names.add("RATE", "0.4", None).is_err());
+        assert!(names.add("Rate", "0.4", Some(0)).is_err());
+
+        let mut other = DefinedNames::default();
+        other.add(FILTER_DATABASE, "$A$1:$B$2", Some(0)).unwrap();
+        other.add("Total", "1", Some(1)).unwrap();
+        names.check_append(&other).unwrap();
+        names.append(other);
+        assert!(names.contains("total", Some(1)));
+        assert!(names.is_defined("TOTAL"));
+
+        let mut clash = DefinedNames::default();
+        clash.add("Rate", "1", Some(0)).unwrap();
+        assert!(names.check_append(&clash).is_err());
+    }
+
+    #[test]
+    fn writes_names_into_the_workbook() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Sales"))?;
+            sheet.add_header_table("Orders", None)?;
+            sheet.write_row(vec![b"id"])?;
+            sheet.close()?;
+
+            let mut sheet = workbook.get_worksheet(String::from("Q&A"))?;
+            sheet.close()?;
+
+            workbook.define_name("Tax", "=0.2", None)?;
+            workbook.define_name("Answers", "'Q&A'!$A$1:$A$10", Some("Q&A"))?;
+            workbook.define_name(PRINT_AREA, "'Q&A'!$A$1:$C$20", Some("Q&A"))?;
+            assert!(workbook.define_name("orders", "1", None).is_err());
+            assert!(workbook.define_name("Local", "1", Some("Missing")).is_err());
+            Ok(())
+        });
+
+        let workbook_xml = read_part(&archive, "xl/workbook.xml");
+        assert!(workbook_xml.contains(concat!(
+            "<definedNames><definedName name=\"Tax\">0.2</definedName>",
+            "<definedName name=\"Answers\" localSheetId=\"1\">&apos;Q&amp;A&apos;!$A$1:$A$10</definedName>",
+            "<definedName name=\"_xlnm.Print_Area\" localSheetId=\"1\">&apos;Q&amp;A&apos;!$A$1:$C$20</definedName>",
+            "</definedNames>"
+        )));
+    }
+}
@@ -0,0 +1,188 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::cell::escape_xml_into;
+use crate::format::XlsxFormatter;
+use crate::image::ImageFormat;
+use crate::relationships::{Relationships, CHART_REL_TYPE, IMAGE_REL_TYPE};
+use crate::workbook_options::CompressionMethod;
+
+const DRAWING_CONTENT_TYPE: &str = "application/vnd.openxmlformats-officedocument.drawing+xml";
+const CHART_CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.drawingml.chart+xml";
+
+const EMU_PER_PIXEL: u64 = 9525;
+
+// The drawing part of one sheet, holding everything anchored over its cells
+pub(crate) struct Drawing {
+    id: u32,
+    rel_id: String,
+    anchors: Vec<u8>,
+    object_count: u32,
+    rels: Relationships,
+    // Chart id and rendered chartN.xml
+    charts: Vec<(u32, Vec<u8>)>,
+    // Part name and contents of each image
+    media: Vec<(String, Vec<u8>)>,
+}
+
+impl Drawing {
+    pub fn new(id: u32, rel_id: String) -> Self {
+        Drawing {
+            id,
+            rel_id,
+            anchors: Vec::new(),
+            object_count: 0,
+            rels: Relationships::default(),
+            charts: Vec::new(),
+            media: Vec::new(),
+        }
+    }
+
+    pub fn rel_id(&self) -> &str {
+        &self.rel_id
+    }
+
+    pub fn add_chart(
+        &mut self,
+        row: u32,
+        col: u16,
+        size: (u32, u32),
+        chart_id: u32,
+        chart_xml: Vec<u8>,
+    ) -> Result<()> {
+        let rel_id = self.rels.add(
+            CHART_REL_TYPE,
+            format!("../charts/chart{}.xml", chart_id),
+            false,
+        );
+        self.object_count += 1;
+
+        self.write_anchor_start(row, col, (0, 0), size)?;
+        write!(
+            self.anchors,
+            "<xdr:graphicFrame macro=\"\"><xdr:nvGraphicFramePr><xdr:cNvPr id=\"{}\" name=\"Chart {}\"/><xdr:cNvGraphicFramePr/></xdr:nvGraphicFramePr><xdr:xfrm><a:off x=\"0\" y=\"0\"/><a:ext cx=\"0\" cy=\"0\"/></xdr:xfrm><a:graphic><a:graphicData uri=\"http://schemas.openxmlformats.org/drawingml/2006/chart\"><c:chart xmlns:c=\"http://schemas.openxmlformats.org/drawingml/2006/chart\" r:id=\"{}\"/></a:graphicData></a:graphic></xdr:graphicFrame>",
+            self.object_count + 1,
+            self.object_count,
+            rel_id
+        )?;
+        self.anchors
+            .write_all(b"<xdr:clientData/></xdr:oneCellAnchor>")?;
+
+        self.charts.push((chart_id, chart_xml));
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_image(
+        &mut self,
+        row: u32,
+        col: u16,
+        offset: (u32, u32),
+        size: (u32, u32),
+        image_id: u32,
+        format: ImageFormat,
+        alt_text: Option<&str>,
+        data: &[u8],
+    ) -> Result<()> {
+        let file_name = format!("image{}.{}", image_id, format.extension());
+        let rel_id = self
+            .rels
+            .add(IMAGE_REL_TYPE, format!("../media/{}", file_name), false);
+        self.object_count += 1;
+
+        self.write_anchor_start(row, col, offset, size)?;
+        write!(
+            self.anchors,
+            "<xdr:pic><xdr:nvPicPr><xdr:cNvPr id=\"{}\" name=\"Picture {}\"",
+            self.object_count + 1,
+            self.object_count
+        )?;
+        if let Some(alt_text) = alt_text {
+            self.anchors.write_all(b" descr=\"")?;
+            escape_xml_into(&mut self.anchors, alt_text.as_bytes());
+            self.anchors.write_all(b"\"")?;
+        }
+        write!(
+            self.anchors,
+            "/><xdr:cNvPicPr><a:picLocks noChangeAspect=\"1\"/></xdr:cNvPicPr></xdr:nvPicPr><xdr:blipFill><a:blip r:embed=\"{}\"/><a:stretch><a:fillRect/></a:stretch></xdr:blipFill><xdr:spPr><a:xfrm><a:off x=\"0\" y=\"0\"/><a:ext cx=\"{}\" cy=\"{}\"/></a:xfrm><a:prstGeom prst=\"rect\"><a:avLst/></a:prstGeom></xdr:spPr></xdr:pic>",
+            rel_id,
+            size.0 as u64 * EMU_PER_PIXEL,
+            size.1 as u64 * EMU_PER_PIXEL
+        )?;
+        self.anchors
+            .write_all(b"<xdr:clientData/></xdr:oneCellAnchor>")?;
+
+        self.media
+            .push((format!("xl/media/{}", file_name), data.to_vec()));
+
+        Ok(())
+    }
+
+    // Objects keep their size in pixels, whatever the size of the cells under them
+    fn write_anchor_start(
+        &mut self,
+        row: u32,
+        col: u16,
+        (offset_x, offset_y): (u32, u32),
+        (width, height): (u32, u32),
+    ) -> Result<()> {
+        write!(
+            self.anchors,
+            "<xdr:oneCellAnchor><xdr:from><xdr:col>{}</xdr:col><xdr:colOff>{}</xdr:colOff><xdr:row>{}</xdr:row><xdr:rowOff>{}</xdr:rowOff></xdr:from><xdr:ext cx=\"{}\" cy=\"{}\"/>",
+            col,
+            offset_x as u64 * EMU_PER_PIXEL,
+            row,
+            offset_y as u64 * EMU_PER_PIXEL,
+            width as u64 * EMU_PER_PIXEL,
+            height as u64 * EMU_PER_PIXEL
+        )?;
+        Ok(())
+    }
+
+    // Writes drawingN.xml, its relationships and the parts it refers to
+    pub fn write_parts<W: Write>(&self, formatter: &mut XlsxFormatter<W>) -> Result<()> {
+        let options = formatter.options.part_options();
+
+        let part_name = format!("xl/drawings/drawing{}.xml", self.id);
+        formatter
+            .zip_writer
+            .start_file(part_name.as_str(), options)?;
+        formatter.zip_writer.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<xdr:wsDr xmlns:xdr=\"http://schemas.openxmlformats.org/drawingml/2006/spreadsheetDrawing\" xmlns:a=\"http://schemas.openxmlformats.org/drawingml/2006/main\" xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\">")?;
+        formatter.zip_writer.write_all(&self.anchors)?;
+        formatter.zip_writer.write_all(b"</xdr:wsDr>")?;
+        formatter
+            .content_types
+            .push((format!("/{}", part_name), DRAWING_CONTENT_TYPE));
+
+        formatter.zip_writer.start_file(
+            format!("xl/drawings/_rels/drawing{}.xml.rels", self.id),
+            options,
+        )?;
+        self.rels.write_xml(&mut formatter.zip_writer)?;
+
+        for (chart_id, chart_xml) in &self.charts {
+            let part_name = format!("xl/charts/chart{}.xml", chart_id);
+            formatter
+                .zip_writer
+                .start_file(part_name.as_str(), options)?;
+            formatter.zip_writer.write_all(chart_xml)?;
+            formatter
+                .content_types
+                .push((format!("/{}", part_name), CHART_CONTENT_TYPE));
+        }
+
+        // Images are compressed already
+        let media_options = options.compression_method(CompressionMethod::Stored);
+        for (part_name, data) in &self.media {
+            formatter
+                .zip_writer
+                .start_file(part_name.as_str(), media_options)?;
+            formatter.zip_writer.write_all(data)?;
+        }
+
+        Ok(())
+    }
+}
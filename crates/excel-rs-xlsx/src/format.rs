@@ -1,14 +1,55 @@
-use std::io::{Result, Seek, Write};
+use std::io::{Result, Write};
 
-use zip::{write::SimpleFileOptions, ZipWriter};
+use crate::cell::{escape_xml_into, write_text_element};
+use crate::defined_names::DefinedNames;
+use crate::protection::WorkbookProtection;
+use crate::shared_strings::SharedStrings;
+use crate::styles::StyleRegistry;
+use crate::workbook_options::WorkBookOptions;
+use crate::zip_stream::{PartOptions, ZipStream};
 
-pub struct XlsxFormatter<W: Write + Seek> {
-    pub zip_writer: ZipWriter<W>,
+pub struct XlsxFormatter<W: Write> {
+    pub zip_writer: ZipStream<W>,
+    pub options: WorkBookOptions,
+    pub shared_strings: Option<SharedStrings>,
+    pub styles: StyleRegistry,
+    // Sheet id and cell reference of each formula, when recalculating on open
+    pub calc_chain: Option<Vec<(u16, String)>>,
+    // Names of the tables in every sheet; a table's id is its position + 1
+    pub table_names: Vec<String>,
+    pub defined_names: DefinedNames,
+    pub workbook_protection: Option<WorkbookProtection>,
+    pub drawing_count: u32,
+    pub chart_count: u32,
+    pub image_count: u32,
+    pub comments_count: u32,
+    // Blocks of 1024 VML shape ids taken by the notes of earlier sheets
+    pub vml_block_count: u32,
+    // Extension and content type of parts typed by extension, such as images
+    pub default_types: Vec<(&'static str, &'static str)>,
+    // Part name and content type of parts written by sheets, such as tables
+    pub content_types: Vec<(String, &'static str)>,
 }
 
-impl<W: Write + Seek> XlsxFormatter<W> {
-    pub fn new(zip_writer: ZipWriter<W>) -> Self {
-        XlsxFormatter { zip_writer }
+impl<W: Write> XlsxFormatter<W> {
+    pub fn new(zip_writer: ZipStream<W>, options: WorkBookOptions) -> Self {
+        XlsxFormatter {
+            zip_writer,
+            options,
+            shared_strings: None,
+            styles: StyleRegistry::new(),
+            calc_chain: None,
+            table_names: Vec::new(),
+            defined_names: DefinedNames::default(),
+            workbook_protection: None,
+            drawing_count: 0,
+            chart_count: 0,
+            image_count: 0,
+            comments_count: 0,
+            vml_block_count: 0,
+            default_types: Vec::new(),
+            content_types: Vec::new(),
+        }
     }
 
     // pub fn write_sheet(&mut self, sheet: Sheet) -> Result<()> {
@@ -26,14 +67,21 @@ impl<W: Write + Seek> XlsxFormatter<W> {
     //     Ok(())
     // }
 
-    pub fn finish(mut self, num_of_sheets: u16) -> Result<W> {
-        let options = SimpleFileOptions::default();
+    pub fn add_default_type(&mut self, default_type: (&'static str, &'static str)) {
+        if !self.default_types.contains(&default_type) {
+            self.default_types.push(default_type);
+        }
+    }
+
+    pub fn finish(mut self, sheet_names: &[String]) -> Result<W> {
+        let options = self.options.part_options();
+        let num_of_sheets = sheet_names.len() as u16;
         self.write_content_type(&options, num_of_sheets)?;
         self.write_rels(&options)?;
-        self.write_doc_props(&options)?;
+        self.write_doc_props(&options, sheet_names)?;
         self.write_styles(&options)?;
         self.write_shared_strings(&options)?;
-        self.write_work_book(&options, num_of_sheets)?;
+        self.write_work_book(&options, sheet_names)?;
         self.write_calc_chain(&options)?;
         self.write_xl_rels(&options, num_of_sheets)?;
         self.write_theme(&options)?;
@@ -43,7 +91,7 @@ impl<W: Write + Seek> XlsxFormatter<W> {
 
     fn write_content_type(
         &mut self,
-        options: &SimpleFileOptions,
+        options: &PartOptions,
         num_of_sheets: u16,
     ) -> Result<()> {
         self.zip_writer
@@ -52,16 +100,36 @@ impl<W: Write + Seek> XlsxFormatter<W> {
             self.zip_writer,
             r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types" xmlns:xsd="http://www.w3.org/2001/XMLSchema" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"><Default Extension="xml" ContentType="application/xml"/><Default Extension="bin" ContentType="application/vnd.ms-excel.sheet.binary.macroEnabled.main"/><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>"#
         )?;
+        for (extension, content_type) in &self.default_types {
+            write!(
+                self.zip_writer,
+                "<Default Extension=\"{}\" ContentType=\"{}\"/>",
+                extension, content_type
+            )?;
+        }
         for i in 0..num_of_sheets {
             writeln!(self.zip_writer, "<Override PartName=\"/xl/worksheets/sheet{}.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml\"/>", i + 1)?;
         }
+        for (part_name, content_type) in &self.content_types {
+            write!(
+                self.zip_writer,
+                "<Override PartName=\"{}\" ContentType=\"{}\"/>",
+                part_name, content_type
+            )?;
+        }
+        if self.has_calc_chain() {
+            write!(
+                self.zip_writer,
+                r#"<Override PartName="/xl/calcChain.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.calcChain+xml"/>"#
+            )?;
+        }
         write!(
             self.zip_writer,
             r#"<Override PartName="/xl/theme/theme1.xml" ContentType="application/vnd.openxmlformats-officedocument.theme+xml"/><Override PartName="/xl/sharedStrings.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sharedStrings+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/><Override PartName="/docProps/core.xml" ContentType="application/vnd.openxmlformats-package.core-properties+xml"/><Override PartName="/docProps/app.xml" ContentType="application/vnd.openxmlformats-officedocument.extended-properties+xml"/></Types>"#
         )
     }
 
-    fn write_rels(&mut self, options: &SimpleFileOptions) -> Result<()> {
+    fn write_rels(&mut self, options: &PartOptions) -> Result<()> {
         self.zip_writer.start_file("_rels/.rels", *options)?;
         write!(
             self.zip_writer,
@@ -69,7 +137,11 @@ impl<W: Write + Seek> XlsxFormatter<W> {
         )
     }
 
-    fn write_doc_props(&mut self, options: &SimpleFileOptions) -> Result<()> {
+    fn write_doc_props(
+        &mut self,
+        options: &PartOptions,
+        sheet_names: &[String],
+    ) -> Result<()> {
         self.zip_writer.start_file("docProps/app.xml", *options)?;
         write!(
             self.zip_writer,
@@ -83,13 +155,26 @@ impl<W: Write + Seek> XlsxFormatter<W> {
                         <vt:lpstr>Worksheets</vt:lpstr>
                     </vt:variant>
                     <vt:variant>
-                        <vt:i4>1</vt:i4>
+                        <vt:i4>{}</vt:i4>
                     </vt:variant>
                 </vt:vector>
             </HeadingPairs>
             <TitlesOfParts>
-                <vt:vector size="1" baseType="lpstr">
-                <vt:lpstr>SheetJS</vt:lpstr>
+                <vt:vector size="{}" baseType="lpstr">
+    "#,
+            sheet_names.len(),
+            sheet_names.len()
+        )?;
+        let mut buf = Vec::new();
+        for name in sheet_names {
+            buf.extend_from_slice(b"<vt:lpstr>");
+            escape_xml_into(&mut buf, name.as_bytes());
+            buf.extend_from_slice(b"</vt:lpstr>\n");
+        }
+        self.zip_writer.write_all(&buf)?;
+        write!(
+            self.zip_writer,
+            r#"
                 </vt:vector>
             </TitlesOfParts>
         </Properties>"#
@@ -104,103 +189,130 @@ impl<W: Write + Seek> XlsxFormatter<W> {
         )
     }
 
-    fn write_styles(&mut self, options: &SimpleFileOptions) -> Result<()> {
+    fn write_styles(&mut self, options: &PartOptions) -> Result<()> {
         self.zip_writer.start_file("xl/styles.xml", *options)?;
-        write!(
-            self.zip_writer,
-            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
-        <styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"
-                    xmlns:vt="http://schemas.openxmlformats.org/officeDocument/2006/docPropsVTypes">
-            <fonts count="1">
-                <font>
-                    <sz val="12"/>
-                    <color theme="1"/>
-                    <name val="Calibri"/>
-                    <family val="2"/>
-                    <scheme val="minor"/>
-                </font>
-            </fonts>
-            <fills count="2">
-                <fill>
-                    <patternFill patternType="none"/>
-                </fill>
-                <fill>
-                    <patternFill patternType="gray125"/>
-                </fill>
-            </fills>
-            <borders count="1">
-                <border>
-                    <left/>
-                    <right/>
-                    <top/>
-                    <bottom/>
-                    <diagonal/>
-                </border>
-            </borders>
-        <cellStyleXfs count="1">
-            <xf numFmtId="0" fontId="0" fillId="0" borderId="0"/>
-        </cellStyleXfs>
-        <cellXfs count="1">
-            <xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/>
-            <xf numFmtId="14" borderId="0" fillId="0" fontId="0" xfId="0"/>
-        </cellXfs>
-        <cellStyles count="1">
-            <cellStyle name="Normal" xfId="0" builtinId="0"/>
-        </cellStyles>
-        <dxfs count="0"/>
-        <tableStyles count="0" defaultTableStyle="TableStyleMedium9" defaultPivotStyle="PivotStyleMedium4"/>
-    </styleSheet>"#
-        )
+        self.styles.write_xml(&mut self.zip_writer)
     }
 
-    fn write_shared_strings(&mut self, options: &SimpleFileOptions) -> Result<()> {
+    fn write_shared_strings(&mut self, options: &PartOptions) -> Result<()> {
         self.zip_writer
             .start_file("xl/sharedStrings.xml", *options)?;
+
+        let shared_strings = match self.shared_strings.take() {
+            Some(shared_strings) => shared_strings,
+            None => {
+                return write!(
+                    self.zip_writer,
+                    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+            <sst xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" count="0" uniqueCount="0"></sst>"#
+                )
+            }
+        };
+
         write!(
             self.zip_writer,
             r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
-            <sst xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" count="0" uniqueCount="0"></sst>"#
-        )
+            <sst xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" count="{}" uniqueCount="{}">"#,
+            shared_strings.count(),
+            shared_strings.unique_count()
+        )?;
+
+        let mut buf = Vec::with_capacity(64 * 1024);
+        for string in shared_strings.into_ordered() {
+            buf.extend_from_slice(b"<si>");
+            write_text_element(&mut buf, &string);
+            buf.extend_from_slice(b"</si>");
+
+            if buf.len() >= 60 * 1024 {
+                self.zip_writer.write_all(&buf)?;
+                buf.clear();
+            }
+        }
+        self.zip_writer.write_all(&buf)?;
+
+        write!(self.zip_writer, "</sst>")
     }
 
-    fn write_work_book(&mut self, options: &SimpleFileOptions, num_of_sheets: u16) -> Result<()> {
+    fn write_work_book(
+        &mut self,
+        options: &PartOptions,
+        sheet_names: &[String],
+    ) -> Result<()> {
         self.zip_writer.start_file("xl/workbook.xml", *options)?;
         write!(
             self.zip_writer,
             r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
         <workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">
             <workbookPr date1904="false"/>
+    "#
+        )?;
+        if let Some(protection) = &self.workbook_protection {
+            protection.write_xml(&mut self.zip_writer)?;
+        }
+        write!(
+            self.zip_writer,
+            r#"
             <sheets>
     "#
         )?;
-        for i in 0..num_of_sheets {
-            writeln!(
-                self.zip_writer,
-                "<sheet name=\"Sheet {}\" sheetId=\"{}\" r:id=\"rId{}\"/>",
-                i + 1,
-                i + 1,
-                i + 3
-            )?;
+        let mut buf = Vec::new();
+        for (i, name) in sheet_names.iter().enumerate() {
+            buf.extend_from_slice(b"<sheet name=\"");
+            escape_xml_into(&mut buf, name.as_bytes());
+            writeln!(buf, "\" sheetId=\"{}\" r:id=\"rId{}\"/>", i + 1, i + 3)?;
         }
+        self.zip_writer.write_all(&buf)?;
         write!(
             self.zip_writer,
             r#"
         </sheets>
+    "#
+        )?;
+        self.defined_names.write_xml(&mut self.zip_writer)?;
+        if self.calc_chain.is_some() {
+            write!(self.zip_writer, r#"<calcPr fullCalcOnLoad="1"/>"#)?;
+        }
+        write!(
+            self.zip_writer,
+            r#"
     </workbook>
     "#
         )
     }
 
-    fn write_calc_chain(&mut self, options: &SimpleFileOptions) -> Result<()> {
+    fn has_calc_chain(&self) -> bool {
+        matches!(&self.calc_chain, Some(calc_chain) if !calc_chain.is_empty())
+    }
+
+    fn write_calc_chain(&mut self, options: &PartOptions) -> Result<()> {
         self.zip_writer.start_file("xl/calcChain.xml", *options)?;
         write!(
             self.zip_writer,
             r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
-        <calcChain xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"></calcChain>"#
-        )
+        <calcChain xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">"#
+        )?;
+
+        if let Some(calc_chain) = &self.calc_chain {
+            // The sheet id only needs repeating when it changes
+            let mut last_sheet_id = 0;
+            for (sheet_id, cell_ref) in calc_chain {
+                if *sheet_id == last_sheet_id {
+                    write!(self.zip_writer, "<c r=\"{}\"/>", cell_ref)?;
+                } else {
+                    write!(
+                        self.zip_writer,
+                        "<c r=\"{}\" i=\"{}\"/>",
+                        cell_ref, sheet_id
+                    )?;
+                    last_sheet_id = *sheet_id;
+                }
+            }
+        }
+
+        write!(self.zip_writer, "</calcChain>")
     }
 
-    fn write_xl_rels(&mut self, options: &SimpleFileOptions, num_of_sheets: u16) -> Result<()> {
+    fn write_xl_rels(&mut self, options: &PartOptions, num_of_sheets: u16) -> Result<()> {
         self.zip_writer
             .start_file("xl/_rels/workbook.xml.rels", *options)?;
         write!(
@@ -222,13 +334,25 @@ impl<W: Write + Seek> XlsxFormatter<W> {
         write!(
             self.zip_writer,
             r#"
-            <Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/sharedStrings" Target="sharedStrings.xml"/>
-        </Relationships>"#,
+            <Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/sharedStrings" Target="sharedStrings.xml"/>"#,
             last_rid + 1
+        )?;
+        if self.has_calc_chain() {
+            write!(
+                self.zip_writer,
+                r#"
+            <Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/calcChain" Target="calcChain.xml"/>"#,
+                last_rid + 2
+            )?;
+        }
+        write!(
+            self.zip_writer,
+            r#"
+        </Relationships>"#
         )
     }
 
-    fn write_theme(&mut self, options: &SimpleFileOptions) -> Result<()> {
+    fn write_theme(&mut self, options: &PartOptions) -> Result<()> {
         self.zip_writer
             .start_file("xl/theme/theme1.xml", *options)?;
         write!(
@@ -554,3 +678,71 @@ impl<W: Write + Seek> XlsxFormatter<W> {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cell::{CellError, CellValue, FormulaResult};
+    use crate::test_util::{read_part, write_workbook};
+
+    #[test]
+    fn writes_formulas_with_their_cached_values() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_typed_worksheet(String::from("Sheet"))?;
+            sheet.write_row(&[
+                CellValue::Formula("=A2&\"<b>\"", Some(FormulaResult::String("x<b>"))),
+                CellValue::Formula("A2>1", Some(FormulaResult::Bool(false))),
+                CellValue::Formula("1/0", Some(FormulaResult::Error(CellError::Div0))),
+                CellValue::Formula("A2*2", Some(FormulaResult::Number(f64::INFINITY))),
+                CellValue::Formula("NOW()", None),
+            ])?;
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains(concat!(
+            "<c r=\"A1\" t=\"str\"><f>A2&amp;&quot;&lt;b&gt;&quot;</f><v>x&lt;b&gt;</v></c>",
+            "<c r=\"B1\" t=\"b\"><f>A2&gt;1</f><v>0</v></c>",
+            "<c r=\"C1\" t=\"e\"><f>1/0</f><v>#DIV/0!</v></c>",
+            "<c r=\"D1\"><f>A2*2</f></c>",
+            "<c r=\"E1\"><f>NOW()</f></c>"
+        )));
+
+        // Without recalculate_on_open there is no calc chain to declare
+        assert!(!read_part(&archive, "[Content_Types].xml").contains("calcChain"));
+        assert!(!read_part(&archive, "xl/_rels/workbook.xml.rels").contains("calcChain"));
+        assert!(!read_part(&archive, "xl/workbook.xml").contains("<calcPr"));
+    }
+
+    #[test]
+    fn lists_every_formula_in_the_calc_chain() {
+        let archive = write_workbook(|workbook| {
+            workbook.recalculate_on_open();
+
+            let mut sheet = workbook.get_typed_worksheet(String::from("First"))?;
+            sheet.merge_range(1, 0, 1, 1, CellValue::Formula("SUM(B1:C1)", None))?;
+            sheet.write_row(&[
+                CellValue::Int(1),
+                CellValue::Formula("A1+1", None),
+                CellValue::Formula("B1+1", None),
+            ])?;
+            sheet.close()?;
+
+            let mut sheet = workbook.get_typed_worksheet(String::from("Second"))?;
+            sheet.write_row(&[CellValue::Formula("First!C1", None)])?;
+            sheet.close()
+        });
+
+        let calc_chain_xml = read_part(&archive, "xl/calcChain.xml");
+        assert!(calc_chain_xml.contains(
+            "<c r=\"A2\" i=\"1\"/><c r=\"B1\"/><c r=\"C1\"/><c r=\"A1\" i=\"2\"/></calcChain>"
+        ));
+
+        assert!(read_part(&archive, "[Content_Types].xml").contains(
+            "<Override PartName=\"/xl/calcChain.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.calcChain+xml\"/>"
+        ));
+        assert!(read_part(&archive, "xl/_rels/workbook.xml.rels").contains(
+            "<Relationship Id=\"rId6\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/calcChain\" Target=\"calcChain.xml\"/>"
+        ));
+        assert!(read_part(&archive, "xl/workbook.xml").contains("<calcPr fullCalcOnLoad=\"1\"/>"));
+    }
+}
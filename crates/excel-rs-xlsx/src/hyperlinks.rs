@@ -0,0 +1,128 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+use crate::cell::{cell_ref, escape_xml_into};
+use crate::relationships::{Relationships, HYPERLINK_REL_TYPE};
+
+// Excel ignores longer URLs and refuses more links per sheet
+const MAX_URL_LEN: usize = 2079;
+const MAX_HYPERLINKS: usize = 65530;
+
+enum LinkTarget {
+    // Relationship id of the URL
+    External(String),
+    // A place in the workbook, such as 'Sheet 2'!A1
+    Internal(String),
+}
+
+struct Hyperlink {
+    cell_ref: String,
+    target: LinkTarget,
+}
+
+#[derive(Default)]
+pub(crate) struct Hyperlinks {
+    links: Vec<Hyperlink>,
+}
+
+impl Hyperlinks {
+    // URLs starting with '#' link within the workbook
+    pub fn add(&mut self, row: u32, col: u16, url: &str, rels: &mut Relationships) -> Result<()> {
+        if url.is_empty() || url == "#" {
+            bail!("hyperlink URL cannot be empty");
+        }
+
+        if url.chars().count() > MAX_URL_LEN {
+            bail!("hyperlink URL is longer than {MAX_URL_LEN} characters");
+        }
+
+        if self.links.len() >= MAX_HYPERLINKS {
+            bail!("sheet cannot hold more than {MAX_HYPERLINKS} hyperlinks");
+        }
+
+        let target = match url.strip_prefix('#') {
+            Some(location) => LinkTarget::Internal(location.to_owned()),
+            None => LinkTarget::External(rels.add(HYPERLINK_REL_TYPE, url.to_owned(), true)),
+        };
+
+        self.links.push(Hyperlink {
+            cell_ref: cell_ref(row, col),
+            target,
+        });
+
+        Ok(())
+    }
+
+    pub fn write_xml(&self, buf: &mut Vec<u8>) -> Result<()> {
+        if self.links.is_empty() {
+            return Ok(());
+        }
+
+        buf.write_all(b"<hyperlinks>")?;
+        for link in &self.links {
+            write!(buf, "<hyperlink ref=\"{}\"", link.cell_ref)?;
+            match &link.target {
+                LinkTarget::External(rel_id) => write!(buf, " r:id=\"{}\"", rel_id)?,
+                LinkTarget::Internal(location) => {
+                    buf.write_all(b" location=\"")?;
+                    escape_xml_into(buf, location.as_bytes());
+                    buf.write_all(b"\"")?;
+                }
+            }
+            buf.write_all(b"/>")?;
+        }
+        buf.write_all(b"</hyperlinks>")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{read_part, write_workbook};
+
+    #[test]
+    fn links_cells_to_urls_and_places() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Sheet"))?;
+            sheet.write_url(0, 0, "https://example.com/?a=1&b=2", Some("Example"))?;
+            sheet.write_url(0, 1, "mailto:someone@example.com", None)?;
+            sheet.write_url(2, 0, "#'Sheet 2'!A1", None)?;
+
+            assert!(sheet.write_url(0, 0, "https://example.com", None).is_err());
+            assert!(sheet.write_url(3, 0, "", None).is_err());
+            assert!(sheet.write_url(3, 0, "#", None).is_err());
+            let long_url = format!("https://example.com/{}", "a".repeat(2060));
+            assert!(sheet.write_url(3, 0, &long_url, None).is_err());
+
+            sheet.write_row(vec![b"replaced", b"replaced", b"C1"])?;
+            assert!(sheet.write_url(0, 3, "https://example.com", None).is_err());
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains(concat!(
+            "<row r=\"1\"><c r=\"A1\" s=\"4\" t=\"inlineStr\"><is><t>Example</t></is></c>",
+            "<c r=\"B1\" s=\"4\" t=\"inlineStr\"><is><t>mailto:someone@example.com</t></is></c>",
+            "<c r=\"C1\" t=\"inlineStr\"><is><t>C1</t></is></c></row>",
+            "<row r=\"3\"><c r=\"A3\" s=\"4\" t=\"inlineStr\"><is><t>'Sheet 2'!A1</t></is></c></row>"
+        )));
+        assert!(sheet_xml.contains(concat!(
+            "<hyperlinks><hyperlink ref=\"A1\" r:id=\"rId1\"/><hyperlink ref=\"B1\" r:id=\"rId2\"/>",
+            "<hyperlink ref=\"A3\" location=\"&apos;Sheet 2&apos;!A1\"/></hyperlinks>"
+        )));
+
+        let rels_xml = read_part(&archive, "xl/worksheets/_rels/sheet1.xml.rels");
+        assert!(rels_xml.contains(concat!(
+            "<Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink\" Target=\"https://example.com/?a=1&amp;b=2\" TargetMode=\"External\"/>",
+            "<Relationship Id=\"rId2\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink\" Target=\"mailto:someone@example.com\" TargetMode=\"External\"/>",
+            "</Relationships>"
+        )));
+
+        // Links share one blue, underlined cell format
+        let styles_xml = read_part(&archive, "xl/styles.xml");
+        assert!(styles_xml.contains("<font><u/><sz val=\"12\"/><color rgb=\"FF0563C1\"/>"));
+        assert!(styles_xml.contains("<cellXfs count=\"5\">"));
+    }
+}
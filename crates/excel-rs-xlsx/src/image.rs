@@ -0,0 +1,273 @@
+use anyhow::{bail, Result};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const JPEG_SIGNATURE: &[u8] = b"\xFF\xD8\xFF";
+
+// Placement of an image inserted with Sheet::insert_image
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageOptions {
+    scale_x: f64,
+    scale_y: f64,
+    offset_x: u32,
+    offset_y: u32,
+    alt_text: Option<String>,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        ImageOptions {
+            scale_x: 1.0,
+            scale_y: 1.0,
+            offset_x: 0,
+            offset_y: 0,
+            alt_text: None,
+        }
+    }
+}
+
+impl ImageOptions {
+    pub fn new() -> Self {
+        ImageOptions::default()
+    }
+
+    pub fn scale(mut self, x: f64, y: f64) -> Self {
+        self.scale_x = x;
+        self.scale_y = y;
+        self
+    }
+
+    // Distance in pixels from the top-left corner of the anchor cell
+    pub fn offset(mut self, x: u32, y: u32) -> Self {
+        self.offset_x = x;
+        self.offset_y = y;
+        self
+    }
+
+    // Description read out by screen readers
+    pub fn alt_text(mut self, text: &str) -> Self {
+        self.alt_text = Some(String::from(text));
+        self
+    }
+
+    pub(crate) fn offset_px(&self) -> (u32, u32) {
+        (self.offset_x, self.offset_y)
+    }
+
+    pub(crate) fn alt_text_str(&self) -> Option<&str> {
+        self.alt_text.as_deref()
+    }
+
+    // Displayed size in pixels
+    pub(crate) fn scaled_size(&self, image: &Image) -> Result<(u32, u32)> {
+        if !(self.scale_x > 0.0 && self.scale_y > 0.0) {
+            bail!("image scale must be larger than zero");
+        }
+
+        let width = (image.width as f64 * self.scale_x).round();
+        let height = (image.height as f64 * self.scale_y).round();
+        if width < 1.0 || height < 1.0 || width > u32::MAX as f64 || height > u32::MAX as f64 {
+            bail!("scaled image size {width}x{height} is out of range");
+        }
+
+        Ok((width as u32, height as u32))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ImageFormat {
+    Png,
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpeg",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+        }
+    }
+}
+
+pub(crate) struct Image {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Image {
+    // Reads the format and pixel size from the image header
+    pub fn parse(data: &[u8]) -> Result<Image> {
+        let (format, size) = if data.starts_with(PNG_SIGNATURE) {
+            (ImageFormat::Png, png_size(data))
+        } else if data.starts_with(JPEG_SIGNATURE) {
+            (ImageFormat::Jpeg, jpeg_size(data))
+        } else {
+            bail!("only PNG and JPEG images are supported");
+        };
+
+        match size {
+            Some((width, height)) if width > 0 && height > 0 => Ok(Image {
+                format,
+                width,
+                height,
+            }),
+            _ => bail!(
+                "could not read the size of the {} image",
+                format.extension()
+            ),
+        }
+    }
+}
+
+// The IHDR chunk always comes first, right after the signature
+fn png_size(data: &[u8]) -> Option<(u32, u32)> {
+    if data.get(12..16)? != b"IHDR" {
+        return None;
+    }
+
+    Some((read_u32(data, 16)?, read_u32(data, 20)?))
+}
+
+// Walks the marker segments up to the first start-of-frame
+fn jpeg_size(data: &[u8]) -> Option<(u32, u32)> {
+    let mut pos = 2;
+
+    loop {
+        // Markers may be preceded by any number of 0xFF fill bytes
+        while *data.get(pos)? == 0xFF && *data.get(pos + 1)? == 0xFF {
+            pos += 1;
+        }
+
+        if *data.get(pos)? != 0xFF {
+            return None;
+        }
+
+        let marker = *data.get(pos + 1)?;
+        match marker {
+            // Standalone markers carry no length
+            0x01 | 0xD0..=0xD7 => pos += 2,
+            0xD9 | 0xDA => return None,
+            0xC0..=0xCF if marker != 0xC4 && marker != 0xC8 && marker != 0xCC => {
+                let height = read_u16(data, pos + 5)?;
+                let width = read_u16(data, pos + 7)?;
+                return Some((width as u32, height as u32));
+            }
+            _ => pos += 2 + read_u16(data, pos + 2)? as usize,
+        }
+    }
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    let bytes = data.get(pos..pos + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    let bytes = data.get(pos..pos + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Image, ImageFormat, ImageOptions, PNG_SIGNATURE};
+    use crate::test_util::{read_part, write_workbook};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend_from_slice(&13u32.to_be_bytes());
+        data.extend_from_slice(b"IHDR");
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[8, 6, 0, 0, 0]);
+        data
+    }
+
+    // An APP0 segment and fill bytes ahead of a baseline frame
+    fn jpeg(width: u16, height: u16) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10];
+        data.extend_from_slice(b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+        data.extend_from_slice(&[0xFF, 0xFF, 0xC0, 0x00, 0x11, 0x08]);
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&width.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn reads_the_size_from_the_header() {
+        let image = Image::parse(&png(640, 480)).unwrap();
+        assert_eq!(
+            (image.format, image.width, image.height),
+            (ImageFormat::Png, 640, 480)
+        );
+
+        let image = Image::parse(&jpeg(32, 16)).unwrap();
+        assert_eq!(
+            (image.format, image.width, image.height),
+            (ImageFormat::Jpeg, 32, 16)
+        );
+
+        assert!(Image::parse(b"GIF89a").is_err());
+        assert!(Image::parse(&png(0, 10)).is_err());
+        assert!(Image::parse(&jpeg(32, 16)[..20]).is_err());
+    }
+
+    #[test]
+    fn scales_to_whole_pixels() {
+        let image = Image::parse(&png(101, 50)).unwrap();
+        assert_eq!(
+            ImageOptions::new()
+                .scale(0.5, 2.0)
+                .scaled_size(&image)
+                .unwrap(),
+            (51, 100)
+        );
+
+        for (x, y) in [(0.0, 1.0), (1.0, -1.0), (f64::NAN, 1.0), (0.001, 1.0)] {
+            assert!(ImageOptions::new().scale(x, y).scaled_size(&image).is_err());
+        }
+    }
+
+    #[test]
+    fn stores_images_in_the_drawing() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Pictures"))?;
+            let options = ImageOptions::new().offset(4, 2).alt_text("Logo <small>");
+            sheet.insert_image(1, 2, &png(20, 10), &options)?;
+            sheet.insert_image(5, 2, &jpeg(8, 8), &ImageOptions::new())?;
+            sheet.insert_image(9, 2, &png(1, 1), &ImageOptions::new())?;
+            sheet.close()
+        });
+
+        let content_types_xml = read_part(&archive, "[Content_Types].xml");
+        assert_eq!(
+            content_types_xml
+                .matches("<Default Extension=\"png\" ContentType=\"image/png\"/>")
+                .count(),
+            1
+        );
+        assert!(
+            content_types_xml.contains("<Default Extension=\"jpeg\" ContentType=\"image/jpeg\"/>")
+        );
+
+        let drawing_xml = read_part(&archive, "xl/drawings/drawing1.xml");
+        assert!(drawing_xml.contains("<xdr:from><xdr:col>2</xdr:col><xdr:colOff>38100</xdr:colOff><xdr:row>1</xdr:row><xdr:rowOff>19050</xdr:rowOff></xdr:from><xdr:ext cx=\"190500\" cy=\"95250\"/>"));
+        assert!(drawing_xml
+            .contains("<xdr:cNvPr id=\"2\" name=\"Picture 1\" descr=\"Logo &lt;small&gt;\"/>"));
+        assert!(drawing_xml.contains("<xdr:cNvPr id=\"3\" name=\"Picture 2\"/>"));
+        assert!(drawing_xml.contains("<a:blip r:embed=\"rId2\"/>"));
+
+        let drawing_rels_xml = read_part(&archive, "xl/drawings/_rels/drawing1.xml.rels");
+        assert!(drawing_rels_xml.contains("<Relationship Id=\"rId2\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/image\" Target=\"../media/image2.jpeg\"/>"));
+
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(&archive[..])).unwrap();
+        let media = zip.by_name("xl/media/image3.png").unwrap();
+        assert_eq!(media.compression(), zip::CompressionMethod::Stored);
+    }
+}
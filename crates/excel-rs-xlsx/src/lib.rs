@@ -1,18 +1,47 @@
 
+mod autofilter;
+mod comments;
+mod defined_names;
+mod drawing;
 mod format;
+mod hyperlinks;
+mod merges;
+mod parallel_deflate;
+mod pending_cells;
+mod relationships;
+mod shared_strings;
+mod tables;
+mod views;
+mod zip_stream;
+#[cfg(test)]
+mod test_util;
+pub mod cell;
+pub mod chart;
+pub mod columns;
+pub mod conditional_format;
+pub mod data_validation;
+pub mod image;
+pub mod page_setup;
+pub mod protection;
+pub mod rows;
 pub mod workbook;
+pub mod workbook_options;
 pub mod sheet;
+pub mod sheet_builder;
+pub mod styles;
 pub mod typed_sheet;
 
+pub use cell::{CellError, CellRange, CellValue, FormulaResult};
+pub use chart::{Chart, ChartAxis, ChartSeries, ChartType, LegendPosition};
+pub use columns::ColumnOptions;
+pub use conditional_format::{Comparison, ConditionalFormat};
+pub use data_validation::{DataValidation, ValidationErrorStyle};
+pub use image::ImageOptions;
+pub use page_setup::{Orientation, PageSetup, PaperSize};
+pub use protection::ProtectionOptions;
+pub use rows::RowOptions;
+pub use sheet_builder::{SheetBuilder, SheetPart};
+pub use styles::{BorderStyle, Format, HorizontalAlign, StyleId, VerticalAlign};
 pub use workbook::WorkBook;
+pub use workbook_options::{CompressionMethod, WorkBookOptions, Zip64Policy};
 
-// TODO: Implement Tests
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-
-//     #[test]
-//     fn it_works() {
-//         assert_eq!(1, 1);
-//     }
-// }
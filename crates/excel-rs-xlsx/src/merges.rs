@@ -0,0 +1,99 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+use crate::cell::CellRange;
+
+#[derive(Default)]
+pub(crate) struct Merges {
+    ranges: Vec<CellRange>,
+}
+
+impl Merges {
+    pub fn add(&mut self, range: CellRange) -> Result<()> {
+        let range_ref = range.to_ref()?;
+
+        if range.first_row == range.last_row && range.first_col == range.last_col {
+            bail!("a merged range must span more than one cell");
+        }
+
+        if let Some(existing) = self.find_overlap(&range) {
+            bail!("merged range {} overlaps {}", range_ref, existing.to_ref()?);
+        }
+
+        self.ranges.push(range);
+
+        Ok(())
+    }
+
+    pub fn find_overlap(&self, range: &CellRange) -> Option<&CellRange> {
+        self.ranges.iter().find(|existing| existing.overlaps(range))
+    }
+
+    pub fn write_xml(&self, buf: &mut Vec<u8>) -> Result<()> {
+        if self.ranges.is_empty() {
+            return Ok(());
+        }
+
+        write!(buf, "<mergeCells count=\"{}\">", self.ranges.len())?;
+        for range in &self.ranges {
+            write!(buf, "<mergeCell ref=\"{}\"/>", range.to_ref()?)?;
+        }
+        buf.write_all(b"</mergeCells>")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Merges;
+    use crate::cell::{CellRange, CellValue};
+    use crate::test_util::{read_part, write_workbook};
+
+    #[test]
+    fn merges_ranges_and_places_their_values() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Sheet"))?;
+            sheet.merge_range(0, 0, 0, 2, CellValue::String("Title"))?;
+            sheet.merge_range(1, 1, 2, 1, CellValue::Blank)?;
+            sheet.merge_range(5, 0, 6, 1, CellValue::Int(42))?;
+
+            assert!(sheet.merge_range(0, 2, 1, 3, CellValue::Blank).is_err());
+            assert!(sheet.merge_range(3, 3, 3, 3, CellValue::Blank).is_err());
+            assert!(sheet.merge_range(3, 4, 3, 3, CellValue::Blank).is_err());
+
+            sheet.write_row(vec![b"replaced", b"kept"])?;
+            assert!(sheet.merge_range(0, 5, 0, 6, CellValue::Blank).is_err());
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains(concat!(
+            "<row r=\"1\"><c r=\"A1\" t=\"inlineStr\"><is><t>Title</t></is></c>",
+            "<c r=\"B1\" t=\"inlineStr\"><is><t>kept</t></is></c></row>"
+        )));
+        // A row that was never written is added for the merged value
+        assert!(sheet_xml.contains("<row r=\"6\"><c r=\"A6\"><v>42</v></c></row>"));
+        assert!(sheet_xml.contains(concat!(
+            "<mergeCells count=\"3\"><mergeCell ref=\"A1:C1\"/>",
+            "<mergeCell ref=\"B2:B3\"/><mergeCell ref=\"A6:B7\"/></mergeCells>"
+        )));
+    }
+
+    #[test]
+    fn finds_overlapping_ranges() {
+        let mut merges = Merges::default();
+        merges.add(CellRange::new(2, 2, 4, 4)).unwrap();
+        for range in [
+            CellRange::new(0, 0, 2, 2),
+            CellRange::new(4, 4, 5, 5),
+            CellRange::new(3, 0, 3, 9),
+        ] {
+            assert!(merges.find_overlap(&range).is_some());
+            assert!(merges.add(range).is_err());
+        }
+        assert!(merges.find_overlap(&CellRange::new(0, 0, 1, 9)).is_none());
+        merges.add(CellRange::new(5, 0, 5, 3)).unwrap();
+    }
+}
@@ -0,0 +1,320 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+use crate::cell::{escape_xml_into, MAX_ROWS};
+
+// Excel rejects longer header and footer text
+const MAX_HEADER_FOOTER_LEN: usize = 255;
+const MAX_FIT_PAGES: u16 = 32767;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaperSize {
+    Letter,
+    Tabloid,
+    Legal,
+    Executive,
+    A3,
+    A4,
+    A5,
+    B4,
+    B5,
+}
+
+impl PaperSize {
+    fn code(&self) -> u8 {
+        match self {
+            PaperSize::Letter => 1,
+            PaperSize::Tabloid => 3,
+            PaperSize::Legal => 5,
+            PaperSize::Executive => 7,
+            PaperSize::A3 => 8,
+            PaperSize::A4 => 9,
+            PaperSize::A5 => 11,
+            PaperSize::B4 => 12,
+            PaperSize::B5 => 13,
+        }
+    }
+}
+
+// Margins in inches
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Margins {
+    left: f64,
+    right: f64,
+    top: f64,
+    bottom: f64,
+    header: f64,
+    footer: f64,
+}
+
+// Excel's "Normal" margins
+impl Default for Margins {
+    fn default() -> Self {
+        Margins {
+            left: 0.7,
+            right: 0.7,
+            top: 0.75,
+            bottom: 0.75,
+            header: 0.3,
+            footer: 0.3,
+        }
+    }
+}
+
+// How the sheet is printed, set with Sheet::set_page_setup
+//
+// Header and footer text may use Excel's codes, e.g. "&L&D&RPage &P of &N"
+// for the date on the left and page numbers on the right.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageSetup {
+    orientation: Option<Orientation>,
+    paper_size: Option<PaperSize>,
+    // Pages wide and tall, where 0 leaves that direction unconstrained
+    fit_to: Option<(u16, u16)>,
+    margins: Margins,
+    repeat_rows: Option<(u32, u32)>,
+    header: Option<String>,
+    footer: Option<String>,
+}
+
+impl PageSetup {
+    pub fn new() -> Self {
+        PageSetup::default()
+    }
+
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = Some(orientation);
+        self
+    }
+
+    pub fn paper_size(mut self, paper_size: PaperSize) -> Self {
+        self.paper_size = Some(paper_size);
+        self
+    }
+
+    // Shrinks the sheet to `pages` pages wide, with as many pages down as it
+    // needs
+    pub fn fit_to_width(self, pages: u16) -> Self {
+        self.fit_to_pages(pages, 0)
+    }
+
+    // 0 leaves that direction unconstrained
+    pub fn fit_to_pages(mut self, width: u16, height: u16) -> Self {
+        self.fit_to = Some((width, height));
+        self
+    }
+
+    pub fn margins(mut self, left: f64, right: f64, top: f64, bottom: f64) -> Self {
+        self.margins.left = left;
+        self.margins.right = right;
+        self.margins.top = top;
+        self.margins.bottom = bottom;
+        self
+    }
+
+    // Distance of the header and footer from the edge of the page
+    pub fn header_footer_margins(mut self, header: f64, footer: f64) -> Self {
+        self.margins.header = header;
+        self.margins.footer = footer;
+        self
+    }
+
+    // Prints the zero-based rows at the top of every page
+    pub fn repeat_rows(mut self, first_row: u32, last_row: u32) -> Self {
+        self.repeat_rows = Some((first_row, last_row));
+        self
+    }
+
+    pub fn header(mut self, text: &str) -> Self {
+        self.header = Some(String::from(text));
+        self
+    }
+
+    pub fn footer(mut self, text: &str) -> Self {
+        self.footer = Some(String::from(text));
+        self
+    }
+
+    pub(crate) fn fits_to_page(&self) -> bool {
+        self.fit_to.is_some()
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Some((width, height)) = self.fit_to {
+            if width > MAX_FIT_PAGES || height > MAX_FIT_PAGES {
+                bail!("cannot fit the sheet to more than {MAX_FIT_PAGES} pages");
+            }
+        }
+
+        let margins = &self.margins;
+        for margin in [
+            margins.left,
+            margins.right,
+            margins.top,
+            margins.bottom,
+            margins.header,
+            margins.footer,
+        ] {
+            if !(margin.is_finite() && margin >= 0.0) {
+                bail!("page margin {margin} must be zero or more inches");
+            }
+        }
+
+        if let Some((first_row, last_row)) = self.repeat_rows {
+            if first_row > last_row || last_row >= MAX_ROWS {
+                bail!("cannot repeat rows {first_row} to {last_row}");
+            }
+        }
+
+        for text in [&self.header, &self.footer].into_iter().flatten() {
+            if text.chars().count() > MAX_HEADER_FOOTER_LEN {
+                bail!("page header and footer text cannot be longer than {MAX_HEADER_FOOTER_LEN} characters");
+            }
+        }
+
+        Ok(())
+    }
+
+    // The _xlnm.Print_Titles formula, e.g. 'Sheet1'!$1:$1
+    pub(crate) fn print_titles(&self, sheet_name: &str) -> Option<String> {
+        self.repeat_rows.map(|(first_row, last_row)| {
+            format!(
+                "'{}'!${}:${}",
+                sheet_name.replace('\'', "''"),
+                first_row + 1,
+                last_row + 1
+            )
+        })
+    }
+
+    // Writes pageMargins, pageSetup and headerFooter
+    pub(crate) fn write_xml(&self, buf: &mut Vec<u8>) -> Result<()> {
+        let margins = &self.margins;
+        write!(
+            buf,
+            "<pageMargins left=\"{}\" right=\"{}\" top=\"{}\" bottom=\"{}\" header=\"{}\" footer=\"{}\"/>",
+            margins.left, margins.right, margins.top, margins.bottom, margins.header, margins.footer
+        )?;
+
+        buf.write_all(b"<pageSetup")?;
+        if let Some(paper_size) = self.paper_size {
+            write!(buf, " paperSize=\"{}\"", paper_size.code())?;
+        }
+        if let Some((width, height)) = self.fit_to {
+            write!(buf, " fitToWidth=\"{}\" fitToHeight=\"{}\"", width, height)?;
+        }
+        match self.orientation {
+            Some(Orientation::Portrait) => buf.write_all(b" orientation=\"portrait\"")?,
+            Some(Orientation::Landscape) => buf.write_all(b" orientation=\"landscape\"")?,
+            None => {}
+        }
+        buf.write_all(b"/>")?;
+
+        if self.header.is_some() || self.footer.is_some() {
+            buf.write_all(b"<headerFooter>")?;
+            if let Some(header) = &self.header {
+                buf.write_all(b"<oddHeader>")?;
+                escape_xml_into(buf, header.as_bytes());
+                buf.write_all(b"</oddHeader>")?;
+            }
+            if let Some(footer) = &self.footer {
+                buf.write_all(b"<oddFooter>")?;
+                escape_xml_into(buf, footer.as_bytes());
+                buf.write_all(b"</oddFooter>")?;
+            }
+            buf.write_all(b"</headerFooter>")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Orientation, PageSetup, PaperSize};
+    use crate::test_util::{read_part, write_workbook};
+
+    fn write_setup(setup: &PageSetup) -> String {
+        let mut buf = Vec::new();
+        setup.write_xml(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn writes_excels_normal_margins_by_default() {
+        assert_eq!(
+            write_setup(&PageSetup::new()),
+            "<pageMargins left=\"0.7\" right=\"0.7\" top=\"0.75\" bottom=\"0.75\" header=\"0.3\" footer=\"0.3\"/><pageSetup/>"
+        );
+    }
+
+    #[test]
+    fn writes_paper_orientation_and_headers() {
+        let setup = PageSetup::new()
+            .orientation(Orientation::Landscape)
+            .paper_size(PaperSize::A4)
+            .fit_to_width(1)
+            .margins(0.25, 0.25, 1.0, 1.0)
+            .header_footer_margins(0.5, 0.0)
+            .header("&CR&D \"Report\"")
+            .footer("Page &P of &N");
+
+        assert_eq!(
+            write_setup(&setup),
+            concat!(
+                "<pageMargins left=\"0.25\" right=\"0.25\" top=\"1\" bottom=\"1\" header=\"0.5\" footer=\"0\"/>",
+                "<pageSetup paperSize=\"9\" fitToWidth=\"1\" fitToHeight=\"0\" orientation=\"landscape\"/>",
+                "<headerFooter><oddHeader>&amp;CR&amp;D &quot;Report&quot;</oddHeader>",
+                "<oddFooter>Page &amp;P of &amp;N</oddFooter></headerFooter>"
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_settings_excel_refuses() {
+        for setup in [
+            PageSetup::new().fit_to_pages(32768, 1),
+            PageSetup::new().margins(-0.1, 0.7, 0.75, 0.75),
+            PageSetup::new().header_footer_margins(f64::NAN, 0.3),
+            PageSetup::new().repeat_rows(3, 2),
+            PageSetup::new().repeat_rows(0, 1_048_576),
+            PageSetup::new().footer(&"f".repeat(256)),
+        ] {
+            assert!(setup.validate().is_err());
+        }
+
+        PageSetup::new()
+            .fit_to_pages(32767, 0)
+            .repeat_rows(1_048_575, 1_048_575)
+            .header(&"h".repeat(255))
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn flags_fitting_and_names_print_titles() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Tom's"))?;
+            sheet.set_page_setup(&PageSetup::new().fit_to_pages(1, 2).repeat_rows(0, 1))?;
+            sheet.write_row(vec![b"a"])?;
+            assert!(sheet
+                .set_page_setup(&PageSetup::new().fit_to_width(1))
+                .is_err());
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains("<sheetPr><pageSetUpPr fitToPage=\"1\"/></sheetPr>"));
+        assert!(sheet_xml.contains("<pageSetup fitToWidth=\"1\" fitToHeight=\"2\"/>"));
+
+        let workbook_xml = read_part(&archive, "xl/workbook.xml");
+        assert!(workbook_xml.contains("<definedName name=\"_xlnm.Print_Titles\" localSheetId=\"0\">&apos;Tom&apos;&apos;s&apos;!$1:$2</definedName>"));
+    }
+}
@@ -0,0 +1,273 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    mem,
+    sync::{
+        mpsc::{self, Receiver, Sender, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use flate2::{Compress, Compression, FlushCompress, Status};
+
+// Big enough that the sync flush between chunks costs next to nothing in
+// compression ratio
+const CHUNK_SIZE: usize = 1024 * 1024;
+// Chunks queued or being compressed per thread before writes block
+const CHUNKS_IN_FLIGHT_PER_THREAD: usize = 2;
+
+struct Job {
+    data: Vec<u8>,
+    level: u32,
+    last: bool,
+    result: SyncSender<io::Result<Vec<u8>>>,
+}
+
+// Threads that compress chunks for every part written with ParallelDeflate.
+// They stop once the pool and every encoder using it are dropped.
+pub(crate) struct DeflatePool {
+    jobs: Sender<Job>,
+    threads: usize,
+}
+
+impl DeflatePool {
+    pub fn new(threads: usize) -> io::Result<Self> {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for i in 0..threads {
+            let receiver = Arc::clone(&receiver);
+            thread::Builder::new()
+                .name(format!("excel-rs-deflate-{i}"))
+                .spawn(move || run_worker(&receiver))?;
+        }
+
+        Ok(DeflatePool { jobs, threads })
+    }
+}
+
+fn run_worker(receiver: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+        let Ok(job) = job else {
+            return;
+        };
+
+        // The part may have failed and stopped waiting for its chunks
+        let _ = job
+            .result
+            .send(compress_chunk(&job.data, job.level, job.last));
+    }
+}
+
+// Raw deflate of one chunk. Every chunk but the last ends with a sync flush,
+// which pads it to a byte boundary without ending the stream, so the chunks
+// concatenate into a single valid deflate stream.
+fn compress_chunk(data: &[u8], level: u32, last: bool) -> io::Result<Vec<u8>> {
+    let mut compress = Compress::new(Compression::new(level), false);
+    let flush = if last {
+        FlushCompress::Finish
+    } else {
+        FlushCompress::Sync
+    };
+
+    let mut output = Vec::with_capacity(data.len() / 2 + 64);
+    loop {
+        if output.len() == output.capacity() {
+            output.reserve(data.len() / 4 + 64);
+        }
+
+        let consumed = compress.total_in() as usize;
+        let status = compress
+            .compress_vec(&data[consumed..], &mut output, flush)
+            .map_err(io::Error::other)?;
+
+        let all_flushed =
+            compress.total_in() as usize == data.len() && output.len() < output.capacity();
+        if status == Status::StreamEnd || (!last && all_flushed) {
+            return Ok(output);
+        }
+    }
+}
+
+// A deflate encoder for one part that hands fixed-size chunks to a
+// DeflatePool, pigz-style. Compressed chunks are collected in order into a
+// buffer that the caller drains, like a DeflateEncoder writing to a Vec.
+pub(crate) struct ParallelDeflate {
+    jobs: Sender<Job>,
+    level: u32,
+    max_in_flight: usize,
+    chunk: Vec<u8>,
+    in_flight: VecDeque<Receiver<io::Result<Vec<u8>>>>,
+    output: Vec<u8>,
+}
+
+impl ParallelDeflate {
+    pub fn new(pool: &DeflatePool, level: u32) -> Self {
+        ParallelDeflate {
+            jobs: pool.jobs.clone(),
+            level,
+            max_in_flight: pool.threads * CHUNKS_IN_FLIGHT_PER_THREAD,
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+            in_flight: VecDeque::new(),
+            output: Vec::new(),
+        }
+    }
+
+    // Compressed bytes ready to be written
+    pub fn get_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.output
+    }
+
+    // Compresses what is left and returns every compressed byte not yet
+    // drained
+    pub fn finish(mut self) -> io::Result<Vec<u8>> {
+        let last_chunk = mem::take(&mut self.chunk);
+        self.submit(last_chunk, true)?;
+        while !self.in_flight.is_empty() {
+            self.collect_next()?;
+        }
+
+        Ok(self.output)
+    }
+
+    fn submit(&mut self, data: Vec<u8>, last: bool) -> io::Result<()> {
+        while self.in_flight.len() >= self.max_in_flight {
+            self.collect_next()?;
+        }
+
+        let (result, receiver) = mpsc::sync_channel(1);
+        let job = Job {
+            data,
+            level: self.level,
+            last,
+            result,
+        };
+        if self.jobs.send(job).is_err() {
+            return Err(io::Error::other("compression threads have stopped"));
+        }
+        self.in_flight.push_back(receiver);
+
+        // Pick up whatever has finished in the meantime without waiting
+        while let Some(Ok(compressed)) = self.in_flight.front().map(|next| next.try_recv()) {
+            self.in_flight.pop_front();
+            self.output.extend_from_slice(&compressed?);
+        }
+
+        Ok(())
+    }
+
+    // Waits for the oldest chunk still being compressed
+    fn collect_next(&mut self) -> io::Result<()> {
+        if let Some(next) = self.in_flight.pop_front() {
+            match next.recv() {
+                Ok(compressed) => self.output.extend_from_slice(&compressed?),
+                Err(_) => return Err(io::Error::other("compression thread stopped")),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for ParallelDeflate {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let take = rest.len().min(CHUNK_SIZE - self.chunk.len());
+            self.chunk.extend_from_slice(&rest[..take]);
+            rest = &rest[take..];
+
+            if self.chunk.len() == CHUNK_SIZE {
+                let full_chunk = mem::replace(&mut self.chunk, Vec::with_capacity(CHUNK_SIZE));
+                self.submit(full_chunk, false)?;
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    // Chunks are only compressed once full, so there is nothing to flush
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::DeflateDecoder;
+
+    use super::*;
+
+    // Varied enough that chunks don't all compress the same way
+    fn sample(len: usize) -> Vec<u8> {
+        let mut data = Vec::with_capacity(len);
+        let mut i = 0u64;
+        while data.len() < len {
+            write!(
+                data,
+                "<row r=\"{i}\"><c><v>{}</v></c></row>",
+                i * 7919 % 10007
+            )
+            .unwrap();
+            i += 1;
+        }
+        data.truncate(len);
+        data
+    }
+
+    fn compress(data: &[u8], threads: usize, level: u32) -> Vec<u8> {
+        let pool = DeflatePool::new(threads).unwrap();
+        let mut encoder = ParallelDeflate::new(&pool, level);
+        let mut compressed = Vec::new();
+        // Uneven writes, drained as the zip stream does
+        for piece in data.chunks(100_003) {
+            encoder.write_all(piece).unwrap();
+            compressed.append(encoder.get_mut());
+        }
+        compressed.extend(encoder.finish().unwrap());
+        compressed
+    }
+
+    fn inflate(compressed: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        DeflateDecoder::new(compressed)
+            .read_to_end(&mut data)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn chunks_inflate_as_one_stream() {
+        let data = sample(CHUNK_SIZE * 5 + 12345);
+        for (threads, level) in [(1, 1), (3, 6), (4, 9)] {
+            assert_eq!(inflate(&compress(&data, threads, level)), data);
+        }
+    }
+
+    #[test]
+    fn ends_on_a_chunk_boundary() {
+        let data = sample(CHUNK_SIZE * 2);
+        assert_eq!(inflate(&compress(&data, 2, 1)), data);
+    }
+
+    #[test]
+    fn compresses_short_and_empty_input() {
+        for data in [Vec::new(), sample(10)] {
+            assert_eq!(inflate(&compress(&data, 2, 6)), data);
+        }
+    }
+
+    #[test]
+    fn level_zero_stores_the_data() {
+        let data = sample(CHUNK_SIZE + 1);
+        let compressed = compress(&data, 2, 0);
+        assert!(compressed.len() > data.len());
+        assert_eq!(inflate(&compressed), data);
+    }
+}
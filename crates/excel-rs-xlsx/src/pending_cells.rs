@@ -0,0 +1,41 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+use crate::cell::cell_ref;
+
+// Cells set by position (merged values, links) ahead of the row they belong
+// to. They are rendered up front and spliced in when their row is written.
+#[derive(Default)]
+pub(crate) struct PendingCells {
+    rows: BTreeMap<u32, BTreeMap<u16, Vec<u8>>>,
+}
+
+impl PendingCells {
+    pub fn ensure_free(&self, row: u32, col: u16) -> Result<()> {
+        if let Some(cells) = self.rows.get(&row) {
+            if cells.contains_key(&col) {
+                bail!("cell {} has already been set", cell_ref(row, col));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn insert(&mut self, row: u32, col: u16, cell_xml: Vec<u8>) -> Result<()> {
+        self.ensure_free(row, col)?;
+        self.rows.entry(row).or_default().insert(col, cell_xml);
+        Ok(())
+    }
+
+    // Cells for the row in column order
+    pub fn take_row(&mut self, row: u32) -> Vec<(u16, Vec<u8>)> {
+        match self.rows.remove(&row) {
+            Some(cells) => cells.into_iter().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn first_row(&self) -> Option<u32> {
+        self.rows.keys().next().copied()
+    }
+}
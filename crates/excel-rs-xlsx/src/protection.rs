@@ -0,0 +1,404 @@
+use std::io::{self, Write};
+
+use anyhow::{bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha512};
+
+const MAX_PASSWORD_LEN: usize = 255;
+const SALT_LEN: usize = 16;
+// What Excel itself uses
+const SPIN_COUNT: u32 = 100_000;
+
+// What users may still do on a protected sheet. By default, like in Excel,
+// they can only select cells and edit the unlocked ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtectionOptions {
+    select_locked_cells: bool,
+    select_unlocked_cells: bool,
+    format_cells: bool,
+    format_columns: bool,
+    format_rows: bool,
+    insert_columns: bool,
+    insert_rows: bool,
+    insert_hyperlinks: bool,
+    delete_columns: bool,
+    delete_rows: bool,
+    sort: bool,
+    autofilter: bool,
+    pivot_tables: bool,
+    edit_objects: bool,
+    edit_scenarios: bool,
+}
+
+impl Default for ProtectionOptions {
+    fn default() -> Self {
+        ProtectionOptions {
+            select_locked_cells: true,
+            select_unlocked_cells: true,
+            format_cells: false,
+            format_columns: false,
+            format_rows: false,
+            insert_columns: false,
+            insert_rows: false,
+            insert_hyperlinks: false,
+            delete_columns: false,
+            delete_rows: false,
+            sort: false,
+            autofilter: false,
+            pivot_tables: false,
+            edit_objects: false,
+            edit_scenarios: false,
+        }
+    }
+}
+
+impl ProtectionOptions {
+    pub fn new() -> Self {
+        ProtectionOptions::default()
+    }
+
+    pub fn deny_select_locked_cells(mut self) -> Self {
+        self.select_locked_cells = false;
+        self
+    }
+
+    pub fn deny_select_unlocked_cells(mut self) -> Self {
+        self.select_unlocked_cells = false;
+        self
+    }
+
+    pub fn allow_format_cells(mut self) -> Self {
+        self.format_cells = true;
+        self
+    }
+
+    pub fn allow_format_columns(mut self) -> Self {
+        self.format_columns = true;
+        self
+    }
+
+    pub fn allow_format_rows(mut self) -> Self {
+        self.format_rows = true;
+        self
+    }
+
+    pub fn allow_insert_columns(mut self) -> Self {
+        self.insert_columns = true;
+        self
+    }
+
+    pub fn allow_insert_rows(mut self) -> Self {
+        self.insert_rows = true;
+        self
+    }
+
+    pub fn allow_insert_hyperlinks(mut self) -> Self {
+        self.insert_hyperlinks = true;
+        self
+    }
+
+    pub fn allow_delete_columns(mut self) -> Self {
+        self.delete_columns = true;
+        self
+    }
+
+    pub fn allow_delete_rows(mut self) -> Self {
+        self.delete_rows = true;
+        self
+    }
+
+    pub fn allow_sort(mut self) -> Self {
+        self.sort = true;
+        self
+    }
+
+    pub fn allow_autofilter(mut self) -> Self {
+        self.autofilter = true;
+        self
+    }
+
+    pub fn allow_pivot_tables(mut self) -> Self {
+        self.pivot_tables = true;
+        self
+    }
+
+    // Charts, images and other drawing objects
+    pub fn allow_edit_objects(mut self) -> Self {
+        self.edit_objects = true;
+        self
+    }
+
+    pub fn allow_edit_scenarios(mut self) -> Self {
+        self.edit_scenarios = true;
+        self
+    }
+}
+
+// A password in both of the forms Excel checks: the legacy 16-bit hash read
+// by older versions, and a salted SHA-512
+pub(crate) struct PasswordHash {
+    // The legacy hash works on the password's single-byte code page form,
+    // which only ASCII is certain to match, so other passwords leave it out
+    // and older versions can't unprotect them
+    legacy: Option<String>,
+    hash: String,
+    salt: String,
+}
+
+impl PasswordHash {
+    pub fn new(password: &str) -> Result<Self> {
+        if password.is_empty() {
+            bail!("password cannot be empty");
+        }
+
+        if password.chars().count() > MAX_PASSWORD_LEN {
+            bail!("password is longer than {MAX_PASSWORD_LEN} characters");
+        }
+
+        let mut salt = [0u8; SALT_LEN];
+        if let Err(err) = getrandom::getrandom(&mut salt) {
+            bail!("could not generate a password salt: {err}");
+        }
+
+        Ok(PasswordHash {
+            legacy: password
+                .is_ascii()
+                .then(|| format!("{:04X}", legacy_hash(password))),
+            hash: STANDARD.encode(sha512_hash(password, &salt)),
+            salt: STANDARD.encode(salt),
+        })
+    }
+
+    // The workbook's attributes are named e.g. workbookHashValue
+    fn write_attributes(&self, buf: &mut Vec<u8>, workbook: bool) -> io::Result<()> {
+        let (password_attr, algorithm_attr, hash_attr, salt_attr, spin_count_attr) = if workbook {
+            (
+                "workbookPassword",
+                "workbookAlgorithmName",
+                "workbookHashValue",
+                "workbookSaltValue",
+                "workbookSpinCount",
+            )
+        } else {
+            (
+                "password",
+                "algorithmName",
+                "hashValue",
+                "saltValue",
+                "spinCount",
+            )
+        };
+
+        if let Some(legacy) = &self.legacy {
+            write!(buf, " {}=\"{}\"", password_attr, legacy)?;
+        }
+        write!(
+            buf,
+            " {}=\"SHA-512\" {}=\"{}\" {}=\"{}\" {}=\"{}\"",
+            algorithm_attr,
+            hash_attr,
+            self.hash,
+            salt_attr,
+            self.salt,
+            spin_count_attr,
+            SPIN_COUNT
+        )?;
+
+        Ok(())
+    }
+}
+
+pub(crate) struct SheetProtection {
+    password: Option<PasswordHash>,
+    options: ProtectionOptions,
+}
+
+impl SheetProtection {
+    pub fn new(password: Option<&str>, options: &ProtectionOptions) -> Result<Self> {
+        Ok(SheetProtection {
+            password: password.map(PasswordHash::new).transpose()?,
+            options: *options,
+        })
+    }
+
+    // Each attribute says whether the action is blocked, and only those that
+    // differ from the schema defaults are written
+    pub fn write_xml(&self, buf: &mut Vec<u8>) -> Result<()> {
+        buf.write_all(b"<sheetProtection")?;
+        if let Some(password) = &self.password {
+            password.write_attributes(buf, false)?;
+        }
+        buf.write_all(b" sheet=\"1\"")?;
+
+        let options = &self.options;
+        for (name, blocked, blocked_by_default) in [
+            ("objects", !options.edit_objects, false),
+            ("scenarios", !options.edit_scenarios, false),
+            ("formatCells", !options.format_cells, true),
+            ("formatColumns", !options.format_columns, true),
+            ("formatRows", !options.format_rows, true),
+            ("insertColumns", !options.insert_columns, true),
+            ("insertRows", !options.insert_rows, true),
+            ("insertHyperlinks", !options.insert_hyperlinks, true),
+            ("deleteColumns", !options.delete_columns, true),
+            ("deleteRows", !options.delete_rows, true),
+            ("selectLockedCells", !options.select_locked_cells, false),
+            ("sort", !options.sort, true),
+            ("autoFilter", !options.autofilter, true),
+            ("pivotTables", !options.pivot_tables, true),
+            ("selectUnlockedCells", !options.select_unlocked_cells, false),
+        ] {
+            if blocked != blocked_by_default {
+                write!(buf, " {}=\"{}\"", name, blocked as u8)?;
+            }
+        }
+        buf.write_all(b"/>")?;
+
+        Ok(())
+    }
+}
+
+// Stops sheets from being added, removed, renamed, moved or unhidden
+pub(crate) struct WorkbookProtection {
+    password: Option<PasswordHash>,
+}
+
+impl WorkbookProtection {
+    pub fn new(password: Option<&str>) -> Result<Self> {
+        Ok(WorkbookProtection {
+            password: password.map(PasswordHash::new).transpose()?,
+        })
+    }
+
+    pub fn write_xml<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(256);
+        buf.write_all(b"<workbookProtection")?;
+        if let Some(password) = &self.password {
+            password.write_attributes(&mut buf, true)?;
+        }
+        buf.write_all(b" lockStructure=\"1\"/>")?;
+
+        writer.write_all(&buf)
+    }
+}
+
+// The hash Excel 2007 and earlier compare passwords with. Only valid for
+// ASCII passwords, whose bytes are the same in every code page.
+fn legacy_hash(password: &str) -> u16 {
+    let bytes = password.as_bytes();
+
+    let mut hash: u16 = 0;
+    for byte in bytes.iter().rev() {
+        hash = ((hash >> 14) & 0x01) | ((hash << 1) & 0x7FFF);
+        hash ^= *byte as u16;
+    }
+    hash = ((hash >> 14) & 0x01) | ((hash << 1) & 0x7FFF);
+    hash ^= bytes.len() as u16;
+    hash ^ 0xCE4B
+}
+
+// The salted hash of the UTF-16LE password, rehashed SPIN_COUNT times with
+// the iteration number appended
+fn sha512_hash(password: &str, salt: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha512::new();
+    hasher.update(salt);
+    for unit in password.encode_utf16() {
+        hasher.update(unit.to_le_bytes());
+    }
+    let mut hash = hasher.finalize();
+
+    for i in 0..SPIN_COUNT {
+        let mut hasher = Sha512::new();
+        hasher.update(hash);
+        hasher.update(i.to_le_bytes());
+        hash = hasher.finalize();
+    }
+
+    hash.to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    use super::{legacy_hash, sha512_hash, PasswordHash, ProtectionOptions, SheetProtection};
+    use crate::test_util::{read_part, write_workbook};
+
+    #[test]
+    fn hashes_like_excel() {
+        // The value Excel writes for "password" in the legacy attribute
+        assert_eq!(legacy_hash("password"), 0x83AF);
+
+        let salt: Vec<u8> = (0..16).collect();
+        assert_eq!(
+            STANDARD.encode(sha512_hash("password", &salt)),
+            "x01qKaF9y9cQwPxHrE46zKhOLAHXLgmWjpZRPwqjkl6tpT1Lq9JXlHzPvHxsy/q0gWkWsUumW+mgF2sVqd4VXQ=="
+        );
+        // Characters outside the BMP are hashed as UTF-16 surrogate pairs
+        assert_eq!(
+            STANDARD.encode(sha512_hash("Grüße 🔒", &salt)),
+            "XG0ILXOO325GMi0z/4f6/BtfCnpc9P9EsPM+8qfziJlNj4twCDl1JhV7XgMP/wQgBdqD3Ox0D17y8VeL8vWxqQ=="
+        );
+    }
+
+    #[test]
+    fn salts_each_password() {
+        let first = PasswordHash::new("password").unwrap();
+        let second = PasswordHash::new("password").unwrap();
+        assert_eq!(first.legacy.as_deref(), Some("83AF"));
+        assert_ne!(first.salt, second.salt);
+        assert_ne!(first.hash, second.hash);
+        assert_eq!(STANDARD.decode(&first.salt).unwrap().len(), 16);
+
+        assert!(PasswordHash::new("Grüße").unwrap().legacy.is_none());
+        assert!(PasswordHash::new("").is_err());
+        assert!(PasswordHash::new(&"p".repeat(256)).is_err());
+    }
+
+    #[test]
+    fn writes_only_non_default_permissions() {
+        let mut buf = Vec::new();
+        SheetProtection::new(None, &ProtectionOptions::new())
+            .unwrap()
+            .write_xml(&mut buf)
+            .unwrap();
+        assert_eq!(
+            buf,
+            b"<sheetProtection sheet=\"1\" objects=\"1\" scenarios=\"1\"/>"
+        );
+
+        let options = ProtectionOptions::new()
+            .deny_select_locked_cells()
+            .allow_sort()
+            .allow_edit_objects()
+            .allow_autofilter();
+        let mut buf = Vec::new();
+        SheetProtection::new(None, &options)
+            .unwrap()
+            .write_xml(&mut buf)
+            .unwrap();
+        assert_eq!(
+            buf,
+            b"<sheetProtection sheet=\"1\" scenarios=\"1\" selectLockedCells=\"1\" sort=\"0\" autoFilter=\"0\"/>"
+        );
+    }
+
+    #[test]
+    fn writes_password_attributes() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Locked"))?;
+            sheet.protect(Some("password"), &ProtectionOptions::new())?;
+            sheet.close()?;
+            workbook.protect_structure(Some("password"))
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml
+            .contains("<sheetProtection password=\"83AF\" algorithmName=\"SHA-512\" hashValue=\""));
+        assert!(sheet_xml.contains("\" spinCount=\"100000\" sheet=\"1\""));
+
+        let workbook_xml = read_part(&archive, "xl/workbook.xml");
+        assert!(workbook_xml.contains("<workbookProtection workbookPassword=\"83AF\" workbookAlgorithmName=\"SHA-512\" workbookHashValue=\""));
+        assert!(workbook_xml.contains("\" workbookSpinCount=\"100000\" lockStructure=\"1\"/>"));
+    }
+}
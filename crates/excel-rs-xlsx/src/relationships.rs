@@ -0,0 +1,69 @@
+use std::io::{Result, Write};
+
+use crate::cell::escape_xml_into;
+
+pub(crate) const HYPERLINK_REL_TYPE: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/hyperlink";
+pub(crate) const TABLE_REL_TYPE: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/table";
+pub(crate) const DRAWING_REL_TYPE: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/drawing";
+pub(crate) const CHART_REL_TYPE: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/chart";
+pub(crate) const IMAGE_REL_TYPE: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/image";
+pub(crate) const COMMENTS_REL_TYPE: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/comments";
+pub(crate) const VML_DRAWING_REL_TYPE: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/vmlDrawing";
+
+struct Relationship {
+    rel_type: &'static str,
+    target: String,
+    external: bool,
+}
+
+// Relationships from one part to others, written to its _rels/*.rels file
+#[derive(Default)]
+pub(crate) struct Relationships {
+    entries: Vec<Relationship>,
+}
+
+impl Relationships {
+    // Returns the new relationship id
+    pub fn add(&mut self, rel_type: &'static str, target: String, external: bool) -> String {
+        self.entries.push(Relationship {
+            rel_type,
+            target,
+            external,
+        });
+        format!("rId{}", self.entries.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn write_xml<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut buf = Vec::with_capacity(256 + 128 * self.entries.len());
+        buf.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">")?;
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            write!(
+                buf,
+                "<Relationship Id=\"rId{}\" Type=\"{}\" Target=\"",
+                i + 1,
+                entry.rel_type
+            )?;
+            escape_xml_into(&mut buf, entry.target.as_bytes());
+            buf.write_all(b"\"")?;
+            if entry.external {
+                buf.write_all(b" TargetMode=\"External\"")?;
+            }
+            buf.write_all(b"/>")?;
+        }
+
+        buf.write_all(b"</Relationships>")?;
+        writer.write_all(&buf)
+    }
+}
@@ -0,0 +1,153 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+// Excel supports eight outline levels, 0 being ungrouped
+pub const MAX_OUTLINE_LEVEL: u8 = 7;
+const MAX_ROW_HEIGHT: f64 = 409.0;
+
+// Height and outline grouping of a row, passed to write_row_with_options
+//
+// To show a group collapsed, hide its rows and mark the summary row below
+// them as collapsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RowOptions {
+    height: Option<f64>,
+    outline_level: u8,
+    hidden: bool,
+    collapsed: bool,
+}
+
+impl RowOptions {
+    pub fn new() -> Self {
+        RowOptions::default()
+    }
+
+    // Height in points
+    pub fn height(mut self, height: f64) -> Self {
+        self.height = Some(height);
+        self
+    }
+
+    pub fn outline_level(mut self, level: u8) -> Self {
+        self.outline_level = level;
+        self
+    }
+
+    pub fn hidden(mut self) -> Self {
+        self.hidden = true;
+        self
+    }
+
+    pub fn collapsed(mut self) -> Self {
+        self.collapsed = true;
+        self
+    }
+
+    pub(crate) fn level(&self) -> u8 {
+        self.outline_level
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Some(height) = self.height {
+            if !(0.0..=MAX_ROW_HEIGHT).contains(&height) {
+                bail!("row height {height} must be between 0 and {MAX_ROW_HEIGHT}");
+            }
+        }
+
+        if self.outline_level > MAX_OUTLINE_LEVEL {
+            bail!(
+                "outline level {} is deeper than {MAX_OUTLINE_LEVEL}",
+                self.outline_level
+            );
+        }
+
+        Ok(())
+    }
+
+    // Attributes of the <row> element after its reference
+    pub(crate) fn write_attributes(&self, buf: &mut Vec<u8>) -> Result<()> {
+        if let Some(height) = self.height {
+            write!(buf, " ht=\"{}\" customHeight=\"1\"", height)?;
+        }
+        if self.hidden {
+            buf.write_all(b" hidden=\"1\"")?;
+        }
+        if self.outline_level > 0 {
+            write!(buf, " outlineLevel=\"{}\"", self.outline_level)?;
+        }
+        if self.collapsed {
+            buf.write_all(b" collapsed=\"1\"")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RowOptions;
+    use crate::columns::ColumnOptions;
+    use crate::test_util::{read_part, write_workbook};
+
+    #[test]
+    fn writes_row_attributes_in_schema_order() {
+        let mut buf = Vec::new();
+        RowOptions::new()
+            .collapsed()
+            .outline_level(2)
+            .hidden()
+            .height(0.0)
+            .write_attributes(&mut buf)
+            .unwrap();
+        assert_eq!(
+            buf,
+            b" ht=\"0\" customHeight=\"1\" hidden=\"1\" outlineLevel=\"2\" collapsed=\"1\""
+        );
+
+        let mut buf = Vec::new();
+        RowOptions::new().write_attributes(&mut buf).unwrap();
+        assert!(buf.is_empty());
+
+        for options in [
+            RowOptions::new().height(409.5),
+            RowOptions::new().height(f64::NAN),
+            RowOptions::new().outline_level(8),
+        ] {
+            assert!(options.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn declares_outline_levels_before_the_rows() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Outline"))?;
+            sheet.set_max_row_outline_level(2)?;
+            sheet.set_column_options(1, 2, &ColumnOptions::new().outline_level(1).hidden())?;
+            sheet.set_column_options(3, 3, &ColumnOptions::new().collapsed())?;
+
+            sheet.write_row_with_options(
+                vec![b"detail"],
+                &RowOptions::new().outline_level(2).hidden(),
+            )?;
+            sheet.write_row_with_options(
+                vec![b"subtotal"],
+                &RowOptions::new().outline_level(1).collapsed(),
+            )?;
+            assert!(sheet
+                .write_row_with_options(vec![b"deeper"], &RowOptions::new().outline_level(3))
+                .is_err());
+            assert!(sheet.set_max_row_outline_level(3).is_err());
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains(concat!(
+            "<sheetFormatPr defaultRowHeight=\"16\" outlineLevelRow=\"2\" outlineLevelCol=\"1\"/>",
+            "<cols><col min=\"2\" max=\"3\" hidden=\"1\" outlineLevel=\"1\"/>",
+            "<col min=\"4\" max=\"4\" collapsed=\"1\"/></cols>"
+        )));
+        assert!(sheet_xml.contains("<row r=\"1\" hidden=\"1\" outlineLevel=\"2\">"));
+        assert!(sheet_xml.contains("<row r=\"2\" outlineLevel=\"1\" collapsed=\"1\">"));
+    }
+}
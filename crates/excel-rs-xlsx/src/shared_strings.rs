@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+
+// Rough per-entry cost of the HashMap slot and boxed key on top of the
+// string bytes themselves
+const ENTRY_OVERHEAD: usize = 48;
+
+pub struct SharedStrings {
+    indices: HashMap<Box<[u8]>, u32>,
+    count: u64,
+    memory_used: usize,
+    memory_limit: usize,
+}
+
+impl SharedStrings {
+    pub fn new(memory_limit: usize) -> Self {
+        SharedStrings {
+            indices: HashMap::new(),
+            count: 0,
+            memory_used: 0,
+            memory_limit,
+        }
+    }
+
+    // Returns None once the table is full and `string` isn't already in it,
+    // in which case the caller should write the string inline instead
+    pub fn get_or_insert(&mut self, string: &[u8]) -> Option<u32> {
+        if let Some(index) = self.indices.get(string) {
+            self.count += 1;
+            return Some(*index);
+        }
+
+        let entry_size = string.len() + ENTRY_OVERHEAD;
+        if self.memory_used + entry_size > self.memory_limit
+            || self.indices.len() >= u32::MAX as usize
+        {
+            return None;
+        }
+
+        let index = self.indices.len() as u32;
+        self.indices.insert(Box::from(string), index);
+        self.memory_used += entry_size;
+        self.count += 1;
+
+        Some(index)
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn unique_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn into_ordered(self) -> Vec<Box<[u8]>> {
+        let mut ordered: Vec<Box<[u8]>> = vec![Box::from([]); self.indices.len()];
+        for (string, index) in self.indices {
+            ordered[index as usize] = string;
+        }
+
+        ordered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SharedStrings, ENTRY_OVERHEAD};
+    use crate::test_util::{read_part, write_workbook};
+
+    #[test]
+    fn stops_adding_strings_at_the_memory_limit() {
+        let mut shared_strings = SharedStrings::new(2 * (5 + ENTRY_OVERHEAD));
+        assert_eq!(shared_strings.get_or_insert(b"alpha"), Some(0));
+        assert_eq!(shared_strings.get_or_insert(b"bravo"), Some(1));
+        assert_eq!(shared_strings.get_or_insert(b"delta"), None);
+        assert_eq!(shared_strings.get_or_insert(b"alpha"), Some(0));
+
+        assert_eq!(shared_strings.count(), 3);
+        assert_eq!(shared_strings.unique_count(), 2);
+        let ordered = shared_strings.into_ordered();
+        assert_eq!(
+            ordered,
+            [Box::from(&b"alpha"[..]), Box::from(&b"bravo"[..])]
+        );
+    }
+
+    #[test]
+    fn writes_text_inline_once_the_table_is_full() {
+        let archive = write_workbook(|workbook| {
+            workbook.enable_shared_strings(2 * (5 + ENTRY_OVERHEAD));
+            let mut sheet = workbook.get_worksheet(String::from("Sheet"))?;
+            sheet.write_row(vec![b"alpha", b"b&<>", b"charlie", b"alpha"])?;
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains(concat!(
+            "<c r=\"A1\" t=\"s\"><v>0</v></c>",
+            "<c r=\"B1\" t=\"s\"><v>1</v></c>",
+            "<c r=\"C1\" t=\"inlineStr\"><is><t>charlie</t></is></c>",
+            "<c r=\"D1\" t=\"s\"><v>0</v></c>"
+        )));
+
+        let shared_strings_xml = read_part(&archive, "xl/sharedStrings.xml");
+        assert!(shared_strings_xml.contains("count=\"3\" uniqueCount=\"2\">"));
+        assert!(shared_strings_xml
+            .contains("<si><t>alpha</t></si><si><t>b&amp;&lt;&gt;</t></si></sst>"));
+    }
+
+    #[test]
+    fn writes_every_string_inline_by_default() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Sheet"))?;
+            sheet.write_row(vec![b"alpha", b"alpha"])?;
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(!sheet_xml.contains("t=\"s\""));
+        assert!(
+            read_part(&archive, "xl/sharedStrings.xml").contains("count=\"0\" uniqueCount=\"0\"")
+        );
+    }
+}
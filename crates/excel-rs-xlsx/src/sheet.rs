@@ -1,121 +1,1147 @@
 use std::{
-    collections::VecDeque,
-    io::{Seek, Write},
+    io::Write,
+    iter::Peekable,
+    mem,
+    ops::{Deref, DerefMut},
+    vec::IntoIter,
 };
 
-use anyhow::Result;
-use zip::{write::SimpleFileOptions, ZipWriter};
+use anyhow::{anyhow, bail, Result};
 
-pub struct Sheet<'a, W: Write + Seek> {
-    pub sheet_buf: &'a mut ZipWriter<W>,
+use crate::autofilter::AutoFilter;
+use crate::cell::{
+    cell_ref, date_time_to_serial, date_to_serial, display_len, escape_xml_into, header_text,
+    range_ref, text_len, time_to_serial, write_text_element, CellError, CellRange, CellValue,
+    FormulaResult, MAX_ROWS,
+};
+use crate::chart::Chart;
+use crate::columns::{ColumnOptions, Columns, MAX_COLUMNS};
+use crate::comments::{Comments, VML_CONTENT_TYPE};
+use crate::conditional_format::{ConditionalFormat, ConditionalFormats};
+use crate::data_validation::{DataValidation, DataValidations};
+use crate::defined_names::{FILTER_DATABASE, PRINT_TITLES};
+use crate::drawing::Drawing;
+use crate::format::XlsxFormatter;
+use crate::hyperlinks::Hyperlinks;
+use crate::image::{Image, ImageOptions};
+use crate::merges::Merges;
+use crate::page_setup::PageSetup;
+use crate::pending_cells::PendingCells;
+use crate::protection::{ProtectionOptions, SheetProtection};
+use crate::relationships::{
+    Relationships, COMMENTS_REL_TYPE, DRAWING_REL_TYPE, TABLE_REL_TYPE, VML_DRAWING_REL_TYPE,
+};
+use crate::rows::{RowOptions, MAX_OUTLINE_LEVEL};
+use crate::styles::{StyleId, DATE_STYLE_ID, DATE_TIME_STYLE_ID, TIME_STYLE_ID};
+use crate::tables::{validate_table_name, Table, TABLE_CONTENT_TYPE};
+use crate::views::SheetView;
+
+// Sheets added to a WorkBook write through its formatter, while sheet
+// builders own one of their own
+enum FormatterRef<'a, W: Write> {
+    Borrowed(&'a mut XlsxFormatter<W>),
+    Owned(Box<XlsxFormatter<W>>),
+}
+
+impl<W: Write> Deref for FormatterRef<'_, W> {
+    type Target = XlsxFormatter<W>;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            FormatterRef::Borrowed(formatter) => formatter,
+            FormatterRef::Owned(formatter) => formatter,
+        }
+    }
+}
+
+impl<W: Write> DerefMut for FormatterRef<'_, W> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            FormatterRef::Borrowed(formatter) => formatter,
+            FormatterRef::Owned(formatter) => formatter,
+        }
+    }
+}
+
+pub struct Sheet<'a, W: Write> {
+    formatter: FormatterRef<'a, W>,
     pub _name: String,
-    // pub id: u16,
+    id: u16,
     // pub is_closed: bool,
     col_num_to_letter: Vec<Vec<u8>>,
-    current_row_num: u32
+    current_row_num: u32,
+    // Widest row written so far
+    col_count: u16,
+    // Everything before <sheetData> depends on options that can be set until
+    // the first row is written, so the header is written lazily
+    header_written: bool,
+    // Rows held back while auto-fit is still measuring column widths
+    pending_rows: Vec<u8>,
+    // Reused for every row, so writing a row doesn't allocate
+    row_buf: Vec<u8>,
+    columns: Columns,
+    // Deepest row outline level allowed, declared up front for <sheetFormatPr>
+    max_row_outline_level: u8,
+    view: SheetView,
+    autofilter: Option<AutoFilter>,
+    merges: Merges,
+    hyperlinks: Hyperlinks,
+    conditional_formats: ConditionalFormats,
+    data_validations: DataValidations,
+    page_setup: Option<PageSetup>,
+    protection: Option<SheetProtection>,
+    pending_cells: PendingCells,
+    rels: Relationships,
+    tables: Vec<Table>,
+    drawing: Option<Drawing>,
+    comments: Option<Comments>,
 }
 
+impl<W: Write> Sheet<'static, W> {
+    pub(crate) fn new_owned(name: String, id: u16, formatter: XlsxFormatter<W>) -> Result<Self> {
+        Self::start(name, id, FormatterRef::Owned(Box::new(formatter)))
+    }
+}
 
-impl<'a, W: Write + Seek> Sheet<'a, W> {
-    pub fn new(name: String, id: u16, writer: &'a mut ZipWriter<W>) -> Self {
-        let options = SimpleFileOptions::default()
-            .compression_method(zip::CompressionMethod::Deflated)
-            .compression_level(Some(1))
-            .large_file(true);
-
-        writer
-            .start_file(format!("xl/worksheets/sheet{}.xml", id), options)
-            .ok();
+impl<'a, W: Write> Sheet<'a, W> {
+    pub(crate) fn new(
+        name: String,
+        id: u16,
+        formatter: &'a mut XlsxFormatter<W>,
+    ) -> Result<Self> {
+        Self::start(name, id, FormatterRef::Borrowed(formatter))
+    }
 
-        // Writes Sheet Header
-        writer.write(b"<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<worksheet xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\" xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\">\n<sheetData>\n").ok();
+    fn start(name: String, id: u16, mut formatter: FormatterRef<'a, W>) -> Result<Self> {
+        let options = formatter.options.sheet_part_options();
 
+        formatter
+            .zip_writer
+            .start_file(format!("xl/worksheets/sheet{}.xml", id), options)?;
 
-        Sheet {
-            sheet_buf: writer,
-            // id,
+        Ok(Sheet {
+            formatter,
+            id,
             _name: name,
             // is_closed: false,
             col_num_to_letter: Vec::with_capacity(64),
-            current_row_num: 0
+            current_row_num: 0,
+            col_count: 0,
+            header_written: false,
+            pending_rows: Vec::new(),
+            row_buf: Vec::new(),
+            columns: Columns::default(),
+            max_row_outline_level: 0,
+            view: SheetView::default(),
+            autofilter: None,
+            merges: Merges::default(),
+            hyperlinks: Hyperlinks::default(),
+            conditional_formats: ConditionalFormats::default(),
+            data_validations: DataValidations::default(),
+            page_setup: None,
+            protection: None,
+            pending_cells: PendingCells::default(),
+            rels: Relationships::default(),
+            tables: Vec::new(),
+            drawing: None,
+            comments: None,
+        })
+    }
+
+    // Hands the formatter back once a sheet that owns one is closed
+    pub(crate) fn into_formatter(self) -> Option<XlsxFormatter<W>> {
+        match self.formatter {
+            FormatterRef::Borrowed(_) => None,
+            FormatterRef::Owned(formatter) => Some(*formatter),
         }
     }
 
-    // TOOD: Use ShortVec over Vec for cell ID
-    pub fn write_row(&mut self, data: Vec<&[u8]>) -> Result<()> {
-        let mut final_vec = Vec::with_capacity(512 * data.len());
+    // Columns are zero-based, like the cells of a row
+    pub fn set_column_width(&mut self, col: u16, width: f64) -> Result<()> {
+        self.set_column_range_width(col, col, width)
+    }
 
-        // TODO: Proper Error Handling
-        let (row_in_chars_arr, digits) = self.num_to_bytes(self.current_row_num);
+    pub fn set_column_range_width(
+        &mut self,
+        first_col: u16,
+        last_col: u16,
+        width: f64,
+    ) -> Result<()> {
+        self.ensure_header_pending("column widths")?;
+        self.columns.set_width(first_col, last_col, width)
+    }
 
-        final_vec.write(b"<row r=\"")?;
-        final_vec.write(&row_in_chars_arr[9 - digits..])?;
-        final_vec.write(b"\">")?;
+    // Replaces the width and grouping of the columns, e.g. to put them in a
+    // collapsible outline group
+    pub fn set_column_options(
+        &mut self,
+        first_col: u16,
+        last_col: u16,
+        options: &ColumnOptions,
+    ) -> Result<()> {
+        self.ensure_header_pending("column options")?;
+        self.columns.set_options(first_col, last_col, options)
+    }
 
-        let mut col = 0;
-        for datum in data {
-            let (ref_id, pos) = self.ref_id(col, (row_in_chars_arr, digits))?;
+    // Excel sizes the outline margin from the deepest row level, which is
+    // written before any rows, so grouped rows need it declared first
+    pub fn set_max_row_outline_level(&mut self, level: u8) -> Result<()> {
+        self.ensure_header_pending("the row outline level")?;
+        if level > MAX_OUTLINE_LEVEL {
+            bail!("outline level {level} is deeper than {MAX_OUTLINE_LEVEL}");
+        }
+        self.max_row_outline_level = level;
+        Ok(())
+    }
 
-            final_vec.write(b"<c r=\"")?;
-            final_vec.write(&ref_id.as_slice()[0..pos])?;
-            final_vec.write(b"\" t=\"str\"><v>")?;
+    // Sizes columns without an explicit width to fit their longest value.
+    // With `sample_rows` set only the first rows are measured; otherwise the
+    // whole sheet is held in memory until close() so every row counts.
+    pub fn autofit_columns(&mut self, sample_rows: Option<u32>) -> Result<()> {
+        self.ensure_header_pending("auto-fit")?;
+        self.columns.enable_autofit(sample_rows);
+        Ok(())
+    }
 
-            let (mut chars, chars_pos) = self.escape_in_place(datum);
-            let mut current_pos = 0;
-            for char_pos in chars_pos {
-                final_vec.write(&datum[current_pos..char_pos])?;
-                final_vec.write(chars.pop_front().unwrap())?;
-                current_pos = char_pos + 1;
-            }
+    // Keeps the first `row` rows and `col` columns in view while scrolling.
+    // freeze_panes(0, 0) removes the split.
+    pub fn freeze_panes(&mut self, row: u32, col: u16) -> Result<()> {
+        self.ensure_header_pending("frozen panes")?;
+        self.view.freeze_panes(row, col)
+    }
+
+    pub fn freeze_header(&mut self) -> Result<()> {
+        self.freeze_panes(1, 0)
+    }
+
+    // Adds filter buttons to the first row of the range. Unlike the layout
+    // options, the filter is written on close() and can be set at any time.
+    pub fn set_autofilter(
+        &mut self,
+        first_row: u32,
+        first_col: u16,
+        last_row: u32,
+        last_col: u16,
+    ) -> Result<()> {
+        let range = CellRange::new(first_row, first_col, last_row, last_col);
+        range.to_ref()?;
+        self.autofilter = Some(AutoFilter::Range(range));
+        Ok(())
+    }
+
+    // Filters on the first row, covering every row and column written by the
+    // time the sheet is closed
+    pub fn autofilter_header(&mut self) {
+        self.autofilter = Some(AutoFilter::Header);
+    }
 
-            final_vec.write(&datum[current_pos..])?;
-            final_vec.write(b"</v></c>")?;
+    // Merges the range into one cell showing `value`. Rows are streamed, so
+    // the range must start at or after the next row to be written. The value
+    // is placed in the top-left cell when its row is written (replacing any
+    // cell written in the same column) or on close() if that row never is.
+    pub fn merge_range(
+        &mut self,
+        first_row: u32,
+        first_col: u16,
+        last_row: u32,
+        last_col: u16,
+        value: CellValue,
+    ) -> Result<()> {
+        self.ensure_row_pending(first_row)?;
+        self.pending_cells.ensure_free(first_row, first_col)?;
 
-            col += 1;
+        self.merges
+            .add(CellRange::new(first_row, first_col, last_row, last_col))?;
+
+        if value != CellValue::Blank {
+            self.place_cell(first_row, first_col, &value, None)?;
         }
 
-        final_vec.write(b"</row>")?;
+        Ok(())
+    }
+
+    // Writes a link showing `text`, or the URL itself without it. URLs
+    // starting with '#' point into the workbook, e.g. "#'Sheet 2'!A1". Like
+    // merge_range, the cell replaces any cell written at the same position.
+    pub fn write_url(&mut self, row: u32, col: u16, url: &str, text: Option<&str>) -> Result<()> {
+        self.ensure_row_pending(row)?;
+        self.pending_cells.ensure_free(row, col)?;
+        range_ref(row, col, row, col)?;
 
-        self.sheet_buf.write(&final_vec)?;
+        self.hyperlinks.add(row, col, url, &mut self.rels)?;
 
+        let style = self.formatter.styles.hyperlink_style()?;
+        let text = text.unwrap_or_else(|| url.strip_prefix('#').unwrap_or(url));
+        self.place_cell(row, col, &CellValue::String(text), Some(style))
+    }
+
+    // Rules apply in the order they are added; the first one that matches a
+    // cell wins unless later ones can be combined with it
+    pub fn add_conditional_format(
+        &mut self,
+        range: CellRange,
+        rule: &ConditionalFormat,
+    ) -> Result<()> {
+        self.conditional_formats
+            .add(range, rule, &mut self.formatter.styles)
+    }
+
+    // Constrains what can be typed into the range. Values already written
+    // are not checked.
+    pub fn add_data_validation(
+        &mut self,
+        range: CellRange,
+        validation: &DataValidation,
+    ) -> Result<()> {
+        self.data_validations.add(range, validation)
+    }
+
+    // Replaces any earlier page setup. Fitting to pages is flagged at the top
+    // of the sheet, so a setup that does must be set before the first row is
+    // written.
+    pub fn set_page_setup(&mut self, setup: &PageSetup) -> Result<()> {
+        setup.validate()?;
+        if setup.fits_to_page() {
+            self.ensure_header_pending("fit-to-page printing")?;
+        }
+
+        self.page_setup = Some(setup.clone());
         Ok(())
     }
 
-    fn escape_in_place(&self, bytes: &[u8]) -> (VecDeque<&[u8]>, VecDeque<usize>) {
-        let mut special_chars: VecDeque<&[u8]> = VecDeque::new();
-        let mut special_char_pos: VecDeque<usize> = VecDeque::new();
-        let len = bytes.len();
-        for x in 0..len {
-            let _ = match bytes[x] {
-                b'<' => {
-                    special_chars.push_back(b"&lt;".as_slice());
-                    special_char_pos.push_back(x);
-                }
-                b'>' => {
-                    special_chars.push_back(b"&gt;".as_slice());
-                    special_char_pos.push_back(x);
+    // Locks every cell whose format isn't unlocked, optionally behind a
+    // password. `options` lists what users may still do.
+    pub fn protect(&mut self, password: Option<&str>, options: &ProtectionOptions) -> Result<()> {
+        self.protection = Some(SheetProtection::new(password, options)?);
+        Ok(())
+    }
+
+    // Formats the range as a table, TableStyleMedium9 unless `style` names
+    // another built-in table style. Column names are taken from the header
+    // row as it is written, so that row must not have been written yet.
+    // Blank and repeated headers are numbered to keep the names unique (a
+    // second "Total" becomes "Total2"), and the header cells show the names
+    // the table uses, filling in any the row leaves out. Overlaps with other
+    // tables, merges and the autofilter are rejected on close().
+    pub fn add_table(
+        &mut self,
+        range: CellRange,
+        name: &str,
+        style: Option<&str>,
+        header_row: bool,
+    ) -> Result<()> {
+        self.register_table(name, Some(range), style, header_row)
+    }
+
+    // A table with a header row that covers every row written by the time
+    // the sheet is closed, across the columns of its header row. Cells of
+    // wider rows fall outside the table.
+    pub fn add_header_table(&mut self, name: &str, style: Option<&str>) -> Result<()> {
+        self.register_table(name, None, style, true)
+    }
+
+    // Places the chart with its top-left corner at the cell. Series may refer
+    // to any sheet in the workbook, including ones added later.
+    pub fn insert_chart(&mut self, row: u32, col: u16, chart: &Chart) -> Result<()> {
+        range_ref(row, col, row, col)?;
+        let chart_xml = chart.to_xml()?;
+
+        self.formatter.chart_count += 1;
+        let chart_id = self.formatter.chart_count;
+        self.drawing().add_chart(
+            row,
+            col,
+            (chart.width(), chart.height()),
+            chart_id,
+            chart_xml,
+        )
+    }
+
+    // Places a PNG or JPEG image with its top-left corner at the cell, at its
+    // natural size unless `options` scales it
+    pub fn insert_image(
+        &mut self,
+        row: u32,
+        col: u16,
+        data: &[u8],
+        options: &ImageOptions,
+    ) -> Result<()> {
+        range_ref(row, col, row, col)?;
+        let image = Image::parse(data)?;
+        let size = options.scaled_size(&image)?;
+
+        self.formatter.image_count += 1;
+        let image_id = self.formatter.image_count;
+
+        let image_type = (image.format.extension(), image.format.content_type());
+        self.formatter.add_default_type(image_type);
+
+        self.drawing().add_image(
+            row,
+            col,
+            options.offset_px(),
+            size,
+            image_id,
+            image.format,
+            options.alt_text_str(),
+            data,
+        )
+    }
+
+    // Adds a note shown when hovering over the cell. Unlike write_url, this
+    // leaves the cell's value alone, so any cell can have one.
+    pub fn write_comment(
+        &mut self,
+        row: u32,
+        col: u16,
+        text: &str,
+        author: Option<&str>,
+    ) -> Result<()> {
+        if self.comments.is_none() {
+            self.formatter.comments_count += 1;
+            let id = self.formatter.comments_count;
+            self.rels
+                .add(COMMENTS_REL_TYPE, format!("../comments{}.xml", id), false);
+            let vml_rel_id = self.rels.add(
+                VML_DRAWING_REL_TYPE,
+                format!("../drawings/vmlDrawing{}.vml", id),
+                false,
+            );
+            self.formatter.add_default_type(("vml", VML_CONTENT_TYPE));
+            self.comments = Some(Comments::new(id, vml_rel_id));
+        }
+
+        self.comments
+            .as_mut()
+            .expect("comments were just created")
+            .add(row, col, text, author.unwrap_or_default())
+    }
+
+    // TOOD: Use ShortVec over Vec for cell ID
+    pub fn write_row(&mut self, data: Vec<&[u8]>) -> Result<()> {
+        self.write_row_iter_with_options(data, &RowOptions::default())
+    }
+
+    pub fn write_row_with_options(&mut self, data: Vec<&[u8]>, options: &RowOptions) -> Result<()> {
+        self.write_row_iter_with_options(data, options)
+    }
+
+    // Writes a row from any iterator of cells, such as fields borrowed from
+    // a reader's own buffer, without collecting them into a Vec first
+    pub fn write_row_iter<I>(&mut self, cells: I) -> Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        self.write_row_iter_with_options(cells, &RowOptions::default())
+    }
+
+    pub fn write_row_iter_with_options<I>(&mut self, cells: I, options: &RowOptions) -> Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        self.validate_row_options(options)?;
+        self.ensure_row_available()?;
+        self.current_row_num += 1;
+
+        let mut buf = self.start_row(options)?;
+        let (row_in_chars_arr, digits) = self.num_to_bytes(self.current_row_num);
+
+        let row_index = self.current_row_num - 1;
+        let header_row = self.is_table_header_row(row_index);
+
+        let mut pending_cells = self
+            .pending_cells
+            .take_row(row_index)
+            .into_iter()
+            .peekable();
+
+        let measuring = self.columns.is_measuring();
+        let mut cell_count = 0;
+        for (col, datum) in cells.into_iter().enumerate() {
+            if col >= MAX_COLUMNS as usize {
+                bail!(
+                    "row {} has more than {MAX_COLUMNS} cells",
+                    self.current_row_num
+                );
+            }
+
+            let datum = datum.as_ref();
+            cell_count += 1;
+
+            let column_name = match header_row {
+                true => {
+                    let text = String::from_utf8_lossy(datum);
+                    self.name_table_column(row_index, col, Some(text.as_ref()))
                 }
-                b'\'' => {
-                    special_chars.push_back(b"&apos;".as_slice());
-                    special_char_pos.push_back(x);
+                false => None,
+            };
+
+            if Self::write_pending_cells(&mut buf, &mut pending_cells, col)? {
+                if column_name.is_some() {
+                    return Err(header_cell_taken(row_index, col));
                 }
-                b'&' => {
-                    special_chars.push_back(b"&amp;".as_slice());
-                    special_char_pos.push_back(x);
+                continue;
+            }
+
+            let datum = column_name.as_ref().map_or(datum, |name| name.as_bytes());
+
+            if measuring {
+                self.columns.measure(col, text_len(datum));
+            }
+
+            let (ref_id, pos) = self.ref_id(col, (row_in_chars_arr, digits))?;
+
+            buf.write_all(b"<c r=\"")?;
+            buf.write_all(&ref_id[0..pos])?;
+            buf.write_all(b"\"")?;
+            self.write_string(&mut buf, datum)?;
+            buf.write_all(b"</c>")?;
+        }
+
+        self.track_col_count(cell_count);
+        if header_row {
+            let row_chars = (row_in_chars_arr, digits);
+            self.write_missing_headers(&mut buf, &mut pending_cells, row_index, row_chars)?;
+        }
+
+        Self::write_pending_cells(&mut buf, &mut pending_cells, usize::MAX)?;
+        self.finish_row(buf)
+    }
+
+    // Each cell takes the style at the same position in `styles`, if any.
+    // An explicit style replaces the built-in date formats, so styles for
+    // date cells should set their own num_format.
+    pub(crate) fn write_cells(
+        &mut self,
+        cells: &[CellValue],
+        styles: &[Option<StyleId>],
+        options: &RowOptions,
+    ) -> Result<()> {
+        self.validate_row_options(options)?;
+        self.ensure_row_available()?;
+        if cells.len() > MAX_COLUMNS as usize {
+            bail!(
+                "row {} has more than {MAX_COLUMNS} cells",
+                self.current_row_num + 1
+            );
+        }
+        self.current_row_num += 1;
+        self.track_col_count(cells.len());
+
+        let mut final_vec = self.start_row(options)?;
+        let (row_in_chars_arr, digits) = self.num_to_bytes(self.current_row_num);
+
+        let row_index = self.current_row_num - 1;
+        let header_row = self.is_table_header_row(row_index);
+
+        let mut pending_cells = self
+            .pending_cells
+            .take_row(row_index)
+            .into_iter()
+            .peekable();
+
+        let measuring = self.columns.is_measuring();
+        for (col, cell) in cells.iter().enumerate() {
+            let column_name = match header_row {
+                true => self.name_table_column(row_index, col, header_text(cell).as_deref()),
+                false => None,
+            };
+
+            if Self::write_pending_cells(&mut final_vec, &mut pending_cells, col)? {
+                if column_name.is_some() {
+                    return Err(header_cell_taken(row_index, col));
                 }
-                b'"' => {
-                    special_chars.push_back(b"&quot;".as_slice());
-                    special_char_pos.push_back(x);
+                continue;
+            }
+
+            let named;
+            let cell = match &column_name {
+                Some(name) => {
+                    named = CellValue::String(name);
+                    &named
                 }
-                _ => (),
+                None => cell,
             };
+
+            if measuring {
+                self.columns.measure(col, display_len(cell));
+            }
+
+            let style = styles.get(col).copied().flatten();
+
+            if let (CellValue::Blank, None) = (cell, style) {
+                continue;
+            }
+
+            let (ref_id, pos) = self.ref_id(col, (row_in_chars_arr, digits))?;
+            if let CellValue::Formula(..) = cell {
+                self.record_formula(&ref_id[0..pos]);
+            }
+
+            final_vec.write_all(b"<c r=\"")?;
+            final_vec.write_all(&ref_id[0..pos])?;
+            final_vec.write_all(b"\"")?;
+            self.write_cell_value(&mut final_vec, cell, style)?;
+            final_vec.write_all(b"</c>")?;
+        }
+
+        if header_row {
+            let row_chars = (row_in_chars_arr, digits);
+            self.write_missing_headers(&mut final_vec, &mut pending_cells, row_index, row_chars)?;
+        }
+
+        Self::write_pending_cells(&mut final_vec, &mut pending_cells, usize::MAX)?;
+        self.finish_row(final_vec)
+    }
+
+    fn register_table(
+        &mut self,
+        name: &str,
+        range: Option<CellRange>,
+        style: Option<&str>,
+        header_row: bool,
+    ) -> Result<()> {
+        validate_table_name(name)?;
+
+        let lowercase_name = name.to_lowercase();
+        if self
+            .formatter
+            .table_names
+            .iter()
+            .any(|existing| existing.to_lowercase() == lowercase_name)
+        {
+            bail!("table name {name:?} is already in use");
+        }
+
+        if self.formatter.defined_names.is_defined(name) {
+            bail!("table name {name:?} is already used by a defined name");
+        }
+
+        let id = self.formatter.table_names.len() as u32 + 1;
+        let mut table = Table::new(id, name, range, style, header_row)?;
+        if let Some(row) = table.header_row() {
+            self.ensure_row_pending(row)?;
+        }
+
+        table.set_rel_id(self.rels.add(
+            TABLE_REL_TYPE,
+            format!("../tables/table{}.xml", id),
+            false,
+        ));
+        self.formatter.table_names.push(name.to_owned());
+        self.tables.push(table);
+
+        Ok(())
+    }
+
+    fn is_table_header_row(&self, row: u32) -> bool {
+        self.tables
+            .iter()
+            .any(|table| table.header_row() == Some(row))
+    }
+
+    // The name a table gives the column of its header cell, which the cell
+    // shows in place of its own text
+    fn name_table_column(&mut self, row: u32, col: usize, text: Option<&str>) -> Option<String> {
+        self.tables
+            .iter_mut()
+            .filter(|table| table.header_row() == Some(row))
+            .find_map(|table| table.name_column(col, text))
+    }
+
+    // Adds the header cells a row left out of its tables' ranges
+    fn write_missing_headers(
+        &mut self,
+        buf: &mut Vec<u8>,
+        pending_cells: &mut Peekable<IntoIter<(u16, Vec<u8>)>>,
+        row: u32,
+        row_chars: ([u8; 9], usize),
+    ) -> Result<()> {
+        let mut missing: Vec<(u16, String)> = self
+            .tables
+            .iter_mut()
+            .filter(|table| table.header_row() == Some(row))
+            .flat_map(|table| table.finish_header())
+            .collect();
+        missing.sort_by_key(|(col, _)| *col);
+
+        let measuring = self.columns.is_measuring();
+        for (col, name) in missing {
+            let col = col as usize;
+            if Self::write_pending_cells(buf, pending_cells, col)? {
+                return Err(header_cell_taken(row, col));
+            }
+
+            if measuring {
+                self.columns.measure(col, text_len(name.as_bytes()));
+            }
+
+            let (ref_id, pos) = self.ref_id(col, row_chars)?;
+            buf.write_all(b"<c r=\"")?;
+            buf.write_all(&ref_id[0..pos])?;
+            buf.write_all(b"\"")?;
+            self.write_string(buf, name.as_bytes())?;
+            buf.write_all(b"</c>")?;
+            self.track_col_count(col + 1);
+        }
+
+        Ok(())
+    }
+
+    // Tables may not overlap each other, merged cells or the sheet autofilter
+    fn table_ranges(&self) -> Result<Vec<CellRange>> {
+        let ranges: Vec<CellRange> = self
+            .tables
+            .iter()
+            .map(|table| table.range(self.current_row_num))
+            .collect();
+
+        let autofilter = self
+            .autofilter
+            .as_ref()
+            .and_then(|autofilter| autofilter.range(self.current_row_num, self.col_count));
+
+        for (i, range) in ranges.iter().enumerate() {
+            let overlap = ranges[..i]
+                .iter()
+                .find(|other| other.overlaps(range))
+                .or_else(|| self.merges.find_overlap(range))
+                .or_else(|| autofilter.as_ref().filter(|other| other.overlaps(range)));
+
+            if let Some(other) = overlap {
+                bail!(
+                    "table range {} overlaps {}",
+                    range.to_ref()?,
+                    other.to_ref()?
+                );
+            }
+        }
+
+        Ok(ranges)
+    }
+
+    // Excel names the filtered range of every sheet with an autofilter
+    fn define_filter_database(&mut self) -> Result<()> {
+        let range = match self
+            .autofilter
+            .as_ref()
+            .and_then(|autofilter| autofilter.range(self.current_row_num, self.col_count))
+        {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+
+        let scope = Some(self.id - 1);
+        if self
+            .formatter
+            .defined_names
+            .contains(FILTER_DATABASE, scope)
+        {
+            return Ok(());
+        }
+
+        let formula = range.to_formula(&self._name)?;
+        self.formatter
+            .defined_names
+            .add(FILTER_DATABASE, &formula, scope)
+    }
+
+    fn define_print_titles(&mut self) -> Result<()> {
+        let formula = match self
+            .page_setup
+            .as_ref()
+            .and_then(|setup| setup.print_titles(&self._name))
+        {
+            Some(formula) => formula,
+            None => return Ok(()),
+        };
+
+        self.formatter
+            .defined_names
+            .add(PRINT_TITLES, &formula, Some(self.id - 1))
+    }
+
+    // The sheet's drawing, created on first use
+    fn drawing(&mut self) -> &mut Drawing {
+        if self.drawing.is_none() {
+            self.formatter.drawing_count += 1;
+            let id = self.formatter.drawing_count;
+            let rel_id = self.rels.add(
+                DRAWING_REL_TYPE,
+                format!("../drawings/drawing{}.xml", id),
+                false,
+            );
+            self.drawing = Some(Drawing::new(id, rel_id));
+        }
+
+        self.drawing.as_mut().expect("drawing was just created")
+    }
+
+    fn ensure_row_pending(&self, row: u32) -> Result<()> {
+        if row < self.current_row_num {
+            bail!("row {row} has already been written");
+        }
+        Ok(())
+    }
+
+    // Renders a cell now and holds it until its row is written
+    fn place_cell(
+        &mut self,
+        row: u32,
+        col: u16,
+        value: &CellValue,
+        style: Option<StyleId>,
+    ) -> Result<()> {
+        let cell_ref = cell_ref(row, col);
+        if let CellValue::Formula(..) = value {
+            self.record_formula(cell_ref.as_bytes());
+        }
+
+        let mut cell_xml = Vec::with_capacity(64);
+        write!(cell_xml, "<c r=\"{}\"", cell_ref)?;
+        self.write_cell_value(&mut cell_xml, value, style)?;
+        cell_xml.write_all(b"</c>")?;
+
+        self.pending_cells.insert(row, col, cell_xml)
+    }
+
+    // Writes pending cells up to and including `col`, returning true if one of
+    // them takes the place of the cell at `col`
+    fn write_pending_cells(
+        buf: &mut Vec<u8>,
+        pending_cells: &mut Peekable<IntoIter<(u16, Vec<u8>)>>,
+        col: usize,
+    ) -> Result<bool> {
+        let mut replaced = false;
+        while let Some((pending_col, cell_xml)) =
+            pending_cells.next_if(|(pending_col, _)| *pending_col as usize <= col)
+        {
+            buf.write_all(&cell_xml)?;
+            replaced |= pending_col as usize == col;
+        }
+        Ok(replaced)
+    }
+
+    // Takes the row buffer and opens the current row in it
+    fn start_row(&mut self, options: &RowOptions) -> Result<Vec<u8>> {
+        let mut buf = mem::take(&mut self.row_buf);
+        buf.clear();
+
+        let (row_in_chars_arr, digits) = self.num_to_bytes(self.current_row_num);
+        buf.write_all(b"<row r=\"")?;
+        buf.write_all(&row_in_chars_arr[9 - digits..])?;
+        buf.write_all(b"\"")?;
+        options.write_attributes(&mut buf)?;
+        buf.write_all(b">")?;
+
+        Ok(buf)
+    }
+
+    // Closes the row, writes it and hands the buffer back for the next one
+    fn finish_row(&mut self, mut buf: Vec<u8>) -> Result<()> {
+        buf.write_all(b"</row>")?;
+        let result = self.write_row_xml(&buf);
+        self.row_buf = buf;
+        result
+    }
+
+    fn write_row_xml(&mut self, row: &[u8]) -> Result<()> {
+        if self.columns.is_measuring() {
+            self.pending_rows.extend_from_slice(row);
+            if self.columns.finish_row() {
+                self.write_header()?;
+            }
+            return Ok(());
+        }
+
+        if !self.header_written {
+            self.write_header()?;
+        }
+
+        self.formatter.zip_writer.write_all(row)?;
+
+        Ok(())
+    }
+
+    fn track_col_count(&mut self, len: usize) {
+        self.col_count = self.col_count.max(len.min(u16::MAX as usize) as u16);
+    }
+
+    fn validate_row_options(&self, options: &RowOptions) -> Result<()> {
+        options.validate()?;
+        if options.level() > self.max_row_outline_level {
+            bail!(
+                "row outline level {} is deeper than {}; call set_max_row_outline_level before the first row",
+                options.level(),
+                self.max_row_outline_level
+            );
+        }
+        Ok(())
+    }
+
+    fn ensure_row_available(&self) -> Result<()> {
+        if self.current_row_num >= MAX_ROWS {
+            bail!("a sheet cannot have more than {MAX_ROWS} rows");
+        }
+        Ok(())
+    }
+
+    fn ensure_header_pending(&self, option: &str) -> Result<()> {
+        if self.header_written || self.current_row_num > 0 {
+            bail!("{option} must be set before the first row is written");
+        }
+        Ok(())
+    }
+
+    // Writes everything up to and including <sheetData>, followed by any rows
+    // held back for auto-fit
+    fn write_header(&mut self) -> Result<()> {
+        let mut header = Vec::with_capacity(512);
+        header.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<worksheet xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\" xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\">\n")?;
+        if self
+            .page_setup
+            .as_ref()
+            .is_some_and(|setup| setup.fits_to_page())
+        {
+            header.write_all(b"<sheetPr><pageSetUpPr fitToPage=\"1\"/></sheetPr>")?;
+        }
+        self.view.write_xml(&mut header)?;
+        let col_outline_level = self.columns.outline_level();
+        if self.max_row_outline_level > 0 || col_outline_level > 0 {
+            // 16 points fits the default 12 point font
+            write!(
+                header,
+                "<sheetFormatPr defaultRowHeight=\"16\" outlineLevelRow=\"{}\" outlineLevelCol=\"{}\"/>",
+                self.max_row_outline_level, col_outline_level
+            )?;
+        }
+        self.columns.write_xml(&mut header)?;
+        header.write_all(b"\n<sheetData>\n")?;
+
+        self.formatter.zip_writer.write_all(&header)?;
+        self.formatter.zip_writer.write_all(&self.pending_rows)?;
+        self.pending_rows = Vec::new();
+        self.header_written = true;
+
+        Ok(())
+    }
+
+    // Writes the attributes following the cell reference, then the value
+    fn write_cell_value(
+        &mut self,
+        buf: &mut Vec<u8>,
+        cell: &CellValue,
+        style: Option<StyleId>,
+    ) -> Result<()> {
+        let default_style = match cell {
+            CellValue::DateTime(_) => Some(DATE_TIME_STYLE_ID),
+            CellValue::Date(_) => Some(DATE_STYLE_ID),
+            CellValue::Time(_) => Some(TIME_STYLE_ID),
+            _ => None,
+        };
+
+        if let Some(StyleId(id)) = style.or(default_style) {
+            write!(buf, " s=\"{}\"", id)?;
+        }
+
+        match *cell {
+            CellValue::Number(num) if num.is_finite() => write!(buf, "><v>{}</v>", num)?,
+            CellValue::Number(_) => Self::write_error(buf, CellError::Num)?,
+            CellValue::Int(num) => write!(buf, "><v>{}</v>", num)?,
+            CellValue::Bool(b) => write!(buf, " t=\"b\"><v>{}</v>", b as u8)?,
+            CellValue::String(s) => self.write_string(buf, s.as_bytes())?,
+            CellValue::DateTime(date_time) => match date_time_to_serial(date_time) {
+                Some(serial) => write!(buf, "><v>{}</v>", serial)?,
+                None => Self::write_error(buf, CellError::Num)?,
+            },
+            CellValue::Date(date) => match date_to_serial(date) {
+                Some(serial) => write!(buf, "><v>{}</v>", serial)?,
+                None => Self::write_error(buf, CellError::Num)?,
+            },
+            CellValue::Time(time) => write!(buf, "><v>{}</v>", time_to_serial(time))?,
+            CellValue::Blank => buf.write_all(b">")?,
+            CellValue::Error(error) => Self::write_error(buf, error)?,
+            CellValue::Formula(formula, result) => Self::write_formula(buf, formula, result)?,
+        }
+
+        Ok(())
+    }
+
+    fn write_formula(
+        buf: &mut Vec<u8>,
+        formula: &str,
+        result: Option<FormulaResult>,
+    ) -> Result<()> {
+        let formula = formula.strip_prefix('=').unwrap_or(formula);
+
+        // A cached number Excel can't store is left for Excel to calculate
+        let result = match result {
+            Some(FormulaResult::Number(num)) if !num.is_finite() => None,
+            result => result,
+        };
+
+        match result {
+            Some(FormulaResult::String(_)) => buf.write_all(b" t=\"str\"")?,
+            Some(FormulaResult::Bool(_)) => buf.write_all(b" t=\"b\"")?,
+            Some(FormulaResult::Error(_)) => buf.write_all(b" t=\"e\"")?,
+            _ => {}
+        }
+
+        buf.write_all(b"><f>")?;
+        escape_xml_into(buf, formula.as_bytes());
+        buf.write_all(b"</f>")?;
+
+        match result {
+            Some(FormulaResult::Number(num)) => write!(buf, "<v>{}</v>", num)?,
+            Some(FormulaResult::String(s)) => {
+                buf.write_all(b"<v>")?;
+                escape_xml_into(buf, s.as_bytes());
+                buf.write_all(b"</v>")?;
+            }
+            Some(FormulaResult::Bool(b)) => write!(buf, "<v>{}</v>", b as u8)?,
+            Some(FormulaResult::Error(error)) => write!(buf, "<v>{}</v>", error.as_str())?,
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    fn record_formula(&mut self, cell_ref: &[u8]) {
+        if let Some(calc_chain) = &mut self.formatter.calc_chain {
+            calc_chain.push((self.id, String::from_utf8_lossy(cell_ref).into_owned()));
+        }
+    }
+
+    fn write_string(&mut self, buf: &mut Vec<u8>, bytes: &[u8]) -> Result<()> {
+        let shared_index = self
+            .formatter
+            .shared_strings
+            .as_mut()
+            .and_then(|shared_strings| shared_strings.get_or_insert(bytes));
+
+        match shared_index {
+            Some(index) => write!(buf, " t=\"s\"><v>{}</v>", index)?,
+            None => {
+                buf.write_all(b" t=\"inlineStr\"><is>")?;
+                write_text_element(buf, bytes);
+                buf.write_all(b"</is>")?;
+            }
         }
 
-        (special_chars, special_char_pos)
+        Ok(())
+    }
+
+    fn write_error(buf: &mut Vec<u8>, error: CellError) -> Result<()> {
+        write!(buf, " t=\"e\"><v>{}</v>", error.as_str())?;
+        Ok(())
     }
 
     pub fn close(&mut self) -> Result<()> {
-        self.sheet_buf.write(b"\n</sheetData>\n</worksheet>\n")?;
+        // A table header row that was never written still needs its names
+        let mut missing_headers = Vec::new();
+        for table in &mut self.tables {
+            match table.header_row() {
+                Some(row) if !table.is_header_named() => missing_headers.extend(
+                    table
+                        .finish_header()
+                        .into_iter()
+                        .map(|(col, name)| (row, col, name)),
+                ),
+                _ => {}
+            }
+        }
+        for (row, col, name) in missing_headers {
+            self.place_cell(row, col, &CellValue::String(&name), None)?;
+        }
+
+        // Cells set on rows past the last one written get rows of their own
+        while let Some(row) = self.pending_cells.first_row() {
+            self.current_row_num = row + 1;
+
+            let mut row_xml = Vec::with_capacity(128);
+            write!(row_xml, "<row r=\"{}\">", self.current_row_num)?;
+            for (_, cell_xml) in self.pending_cells.take_row(row) {
+                row_xml.write_all(&cell_xml)?;
+            }
+            row_xml.write_all(b"</row>")?;
+
+            self.write_row_xml(&row_xml)?;
+        }
+
+        if !self.header_written {
+            self.write_header()?;
+        }
+
+        let table_ranges = self.table_ranges()?;
+
+        let mut footer = Vec::with_capacity(128);
+        footer.write_all(b"\n</sheetData>\n")?;
+        if let Some(protection) = &self.protection {
+            protection.write_xml(&mut footer)?;
+        }
+        if let Some(autofilter) = &self.autofilter {
+            autofilter.write_xml(&mut footer, self.current_row_num, self.col_count)?;
+        }
+        self.define_filter_database()?;
+        self.define_print_titles()?;
+        self.merges.write_xml(&mut footer)?;
+        self.conditional_formats.write_xml(&mut footer)?;
+        self.data_validations.write_xml(&mut footer)?;
+        self.hyperlinks.write_xml(&mut footer)?;
+        if let Some(page_setup) = &self.page_setup {
+            page_setup.write_xml(&mut footer)?;
+        }
+        if let Some(drawing) = &self.drawing {
+            write!(footer, "<drawing r:id=\"{}\"/>", drawing.rel_id())?;
+        }
+        if let Some(comments) = &self.comments {
+            write!(
+                footer,
+                "<legacyDrawing r:id=\"{}\"/>",
+                comments.vml_rel_id()
+            )?;
+        }
+        if !self.tables.is_empty() {
+            write!(footer, "<tableParts count=\"{}\">", self.tables.len())?;
+            for table in &self.tables {
+                write!(footer, "<tablePart r:id=\"{}\"/>", table.rel_id())?;
+            }
+            footer.write_all(b"</tableParts>")?;
+        }
+        footer.write_all(b"</worksheet>\n")?;
+
+        self.formatter.zip_writer.write_all(&footer)?;
+
+        let part_options = self.formatter.options.part_options();
+        for (table, range) in self.tables.iter().zip(table_ranges) {
+            let part_name = format!("xl/tables/table{}.xml", table.id());
+            self.formatter
+                .zip_writer
+                .start_file(part_name.as_str(), part_options)?;
+            table.write_xml(&mut self.formatter.zip_writer, range)?;
+            self.formatter
+                .content_types
+                .push((format!("/{}", part_name), TABLE_CONTENT_TYPE));
+        }
+
+        if let Some(drawing) = &self.drawing {
+            drawing.write_parts(&mut self.formatter)?;
+        }
+
+        if let Some(comments) = &self.comments {
+            comments.write_parts(&mut self.formatter)?;
+        }
+
+        if !self.rels.is_empty() {
+            self.formatter.zip_writer.start_file(
+                format!("xl/worksheets/_rels/sheet{}.xml.rels", self.id),
+                part_options,
+            )?;
+            self.rels.write_xml(&mut self.formatter.zip_writer)?;
+        }
+
         Ok(())
     }
 
@@ -127,7 +1153,7 @@ impl<'a, W: Write + Seek> Sheet<'a, W> {
         let mut digits = 0;
         while row > 0 {
             row_in_chars_arr[char_pos] = b'0' + (row % 10) as u8;
-            row = row / 10;
+            row /= 10;
             char_pos -= 1;
             digits += 1;
         }
@@ -155,18 +1181,17 @@ impl<'a, W: Write + Seek> Sheet<'a, W> {
         Ok((final_arr, pos))
     }
 
-    fn col_to_letter(& mut self, col: usize) -> &[u8] {
-
-        if self.col_num_to_letter.len() < col + 1 as usize {
+    fn col_to_letter(&mut self, col: usize) -> &[u8] {
+        while self.col_num_to_letter.len() < col + 1 {
             let mut result = Vec::with_capacity(2);
-            let mut col = col as i16;
+            let mut col = self.col_num_to_letter.len();
 
             loop {
                 result.push(b'A' + (col % 26) as u8);
-                col = col / 26 - 1;
-                if col < 0 {
+                if col < 26 {
                     break;
                 }
+                col = col / 26 - 1;
             }
 
             result.reverse();
@@ -176,3 +1201,128 @@ impl<'a, W: Write + Seek> Sheet<'a, W> {
         &self.col_num_to_letter[col]
     }
 }
+
+// Links and merged values can't take the place of a table's column name
+fn header_cell_taken(row: u32, col: usize) -> anyhow::Error {
+    anyhow!(
+        "cell {} is a table header and can only hold its column name",
+        cell_ref(row, col as u16)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveTime};
+
+    use crate::cell::{CellError, CellValue, FormulaResult, MAX_ROWS};
+    use crate::columns::MAX_COLUMNS;
+    use crate::styles::Format;
+    use crate::test_util::{read_part, write_workbook};
+
+    #[test]
+    fn writes_each_value_type() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        let archive = write_workbook(|workbook| {
+            let bold = workbook.add_format(&Format::new().bold())?;
+            let mut styles = vec![None; 13];
+            styles[9] = Some(bold);
+            styles[12] = Some(bold);
+
+            let mut sheet = workbook.get_typed_worksheet(String::from("Typed"))?;
+            sheet.write_styled_row(
+                &[
+                    CellValue::Number(1.5),
+                    CellValue::Number(f64::NAN),
+                    CellValue::Int(-3),
+                    CellValue::Bool(true),
+                    CellValue::String("a < b & \"c\""),
+                    CellValue::DateTime(date.and_hms_opt(12, 0, 0).unwrap()),
+                    CellValue::Date(date),
+                    CellValue::Time(NaiveTime::from_hms_opt(18, 0, 0).unwrap()),
+                    CellValue::Blank,
+                    CellValue::Blank,
+                    CellValue::Error(CellError::Div0),
+                    CellValue::Formula("=SUM(A1:C1)", Some(FormulaResult::Number(-1.5))),
+                    CellValue::Int(7),
+                ],
+                &styles,
+            )?;
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        let row = concat!(
+            "<row r=\"1\">",
+            "<c r=\"A1\"><v>1.5</v></c>",
+            "<c r=\"B1\" t=\"e\"><v>#NUM!</v></c>",
+            "<c r=\"C1\"><v>-3</v></c>",
+            "<c r=\"D1\" t=\"b\"><v>1</v></c>",
+            "<c r=\"E1\" t=\"inlineStr\"><is><t>a &lt; b &amp; \"c\"</t></is></c>",
+            "<c r=\"F1\" s=\"2\"><v>45293.5</v></c>",
+            "<c r=\"G1\" s=\"1\"><v>45293</v></c>",
+            "<c r=\"H1\" s=\"3\"><v>0.75</v></c>",
+            "<c r=\"J1\" s=\"4\"></c>",
+            "<c r=\"K1\" t=\"e\"><v>#DIV/0!</v></c>",
+            "<c r=\"L1\"><f>SUM(A1:C1)</f><v>-1.5</v></c>",
+            "<c r=\"M1\" s=\"4\"><v>7</v></c>",
+            "</row>"
+        );
+        assert!(sheet_xml.contains(row), "{sheet_xml}");
+    }
+
+    #[test]
+    fn writes_up_to_the_last_column() {
+        let texts = vec![&b"x"[..]; MAX_COLUMNS as usize];
+        let values = vec![CellValue::Int(1); MAX_COLUMNS as usize];
+
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Text"))?;
+            sheet.write_row(texts.clone())?;
+            let mut wider = texts.clone();
+            wider.push(b"x");
+            assert!(sheet.write_row(wider).is_err());
+            sheet.close()?;
+
+            let mut sheet = workbook.get_typed_worksheet(String::from("Typed"))?;
+            sheet.write_row(&values)?;
+            let mut wider = values.clone();
+            wider.push(CellValue::Blank);
+            assert!(sheet.write_row(&wider).is_err());
+            sheet.close()
+        });
+
+        for part in ["xl/worksheets/sheet1.xml", "xl/worksheets/sheet2.xml"] {
+            let sheet_xml = read_part(&archive, part);
+            for cell_ref in ["A1", "Z1", "AA1", "ZZ1", "AAA1", "XFD1"] {
+                assert!(
+                    sheet_xml.contains(&format!("<c r=\"{cell_ref}\"")),
+                    "{cell_ref}"
+                );
+            }
+            assert!(!sheet_xml.contains("r=\"A2\""));
+        }
+    }
+
+    #[test]
+    fn writes_up_to_the_last_row() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Text"))?;
+            sheet.current_row_num = MAX_ROWS - 1;
+            sheet.write_row(vec![b"last"])?;
+            assert!(sheet.write_row(vec![b"past"]).is_err());
+            sheet.close()?;
+
+            let mut sheet = workbook.get_typed_worksheet(String::from("Typed"))?;
+            sheet.current_row_num = MAX_ROWS - 1;
+            sheet.write_row(&[CellValue::Int(1)])?;
+            assert!(sheet.write_row(&[CellValue::Int(2)]).is_err());
+            sheet.close()
+        });
+
+        for part in ["xl/worksheets/sheet1.xml", "xl/worksheets/sheet2.xml"] {
+            let sheet_xml = read_part(&archive, part);
+            assert!(sheet_xml.contains("<row r=\"1048576\"><c r=\"A1048576\""));
+            assert!(!sheet_xml.contains("1048577"));
+        }
+    }
+}
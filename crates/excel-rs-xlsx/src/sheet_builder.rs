@@ -0,0 +1,324 @@
+use anyhow::{bail, Result};
+
+use crate::cell::{CellRange, CellValue};
+use crate::columns::ColumnOptions;
+use crate::data_validation::DataValidation;
+use crate::format::XlsxFormatter;
+use crate::page_setup::PageSetup;
+use crate::protection::ProtectionOptions;
+use crate::rows::RowOptions;
+use crate::sheet::Sheet;
+use crate::styles::StyleId;
+use crate::workbook_options::WorkBookOptions;
+use crate::zip_stream::ZipStream;
+
+// A sheet that can be filled on another thread while the workbook writes
+// other sheets. Created with WorkBook::sheet_builder, it compresses into
+// memory until finish() turns it into a SheetPart for
+// WorkBook::append_sheet. The compressed sheet stays in memory until it is
+// appended; there is no temporary file behind it.
+//
+// It keeps to what a sheet can do on its own: strings are written inline
+// rather than shared, styles must be added to the workbook beforehand, and
+// tables, links, comments, charts, images and conditional formats need a
+// Sheet from the workbook itself.
+pub struct SheetBuilder {
+    id: u16,
+    sheet: Sheet<'static, Vec<u8>>,
+}
+
+// A finished SheetBuilder, waiting to be appended to its workbook
+pub struct SheetPart {
+    pub(crate) id: u16,
+    pub(crate) name: String,
+    pub(crate) formatter: XlsxFormatter<Vec<u8>>,
+}
+
+impl SheetPart {
+    // Tables, drawings, charts, images, comments and styles are numbered
+    // across the workbook. A builder numbers them on its own, and its parts
+    // can't be renumbered once written, so a part holding any is refused
+    // rather than clashing with the workbook's.
+    pub(crate) fn check_mergeable(&self) -> Result<()> {
+        let formatter = &self.formatter;
+        if !formatter.table_names.is_empty()
+            || formatter.drawing_count > 0
+            || formatter.chart_count > 0
+            || formatter.image_count > 0
+            || formatter.comments_count > 0
+            || !formatter.styles.is_unused()
+        {
+            bail!(
+                "sheet {:?} holds parts numbered apart from the workbook",
+                self.name
+            );
+        }
+        Ok(())
+    }
+}
+
+impl SheetBuilder {
+    pub(crate) fn new(
+        name: String,
+        id: u16,
+        options: WorkBookOptions,
+        recalculate_on_open: bool,
+    ) -> Result<Self> {
+        let mut formatter = XlsxFormatter::new(ZipStream::in_memory(), options);
+        if recalculate_on_open {
+            formatter.calc_chain = Some(Vec::new());
+        }
+
+        Ok(SheetBuilder {
+            id,
+            sheet: Sheet::new_owned(name, id, formatter)?,
+        })
+    }
+
+    pub fn write_row(&mut self, data: Vec<&[u8]>) -> Result<()> {
+        self.sheet.write_row(data)
+    }
+
+    pub fn write_row_with_options(&mut self, data: Vec<&[u8]>, options: &RowOptions) -> Result<()> {
+        self.sheet.write_row_with_options(data, options)
+    }
+
+    pub fn write_row_iter<I>(&mut self, cells: I) -> Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        self.sheet.write_row_iter(cells)
+    }
+
+    pub fn write_row_iter_with_options<I>(&mut self, cells: I, options: &RowOptions) -> Result<()>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        self.sheet.write_row_iter_with_options(cells, options)
+    }
+
+    pub fn write_typed_row(&mut self, data: &[CellValue]) -> Result<()> {
+        self.sheet.write_cells(data, &[], &RowOptions::default())
+    }
+
+    pub fn write_styled_row(
+        &mut self,
+        data: &[CellValue],
+        styles: &[Option<StyleId>],
+    ) -> Result<()> {
+        self.sheet.write_cells(data, styles, &RowOptions::default())
+    }
+
+    pub fn write_styled_row_with_options(
+        &mut self,
+        data: &[CellValue],
+        styles: &[Option<StyleId>],
+        options: &RowOptions,
+    ) -> Result<()> {
+        self.sheet.write_cells(data, styles, options)
+    }
+
+    pub fn set_column_width(&mut self, col: u16, width: f64) -> Result<()> {
+        self.sheet.set_column_width(col, width)
+    }
+
+    pub fn set_column_range_width(
+        &mut self,
+        first_col: u16,
+        last_col: u16,
+        width: f64,
+    ) -> Result<()> {
+        self.sheet
+            .set_column_range_width(first_col, last_col, width)
+    }
+
+    pub fn set_column_options(
+        &mut self,
+        first_col: u16,
+        last_col: u16,
+        options: &ColumnOptions,
+    ) -> Result<()> {
+        self.sheet.set_column_options(first_col, last_col, options)
+    }
+
+    pub fn set_max_row_outline_level(&mut self, level: u8) -> Result<()> {
+        self.sheet.set_max_row_outline_level(level)
+    }
+
+    pub fn autofit_columns(&mut self, sample_rows: Option<u32>) -> Result<()> {
+        self.sheet.autofit_columns(sample_rows)
+    }
+
+    pub fn freeze_panes(&mut self, row: u32, col: u16) -> Result<()> {
+        self.sheet.freeze_panes(row, col)
+    }
+
+    pub fn freeze_header(&mut self) -> Result<()> {
+        self.sheet.freeze_header()
+    }
+
+    pub fn set_autofilter(
+        &mut self,
+        first_row: u32,
+        first_col: u16,
+        last_row: u32,
+        last_col: u16,
+    ) -> Result<()> {
+        self.sheet
+            .set_autofilter(first_row, first_col, last_row, last_col)
+    }
+
+    pub fn autofilter_header(&mut self) {
+        self.sheet.autofilter_header()
+    }
+
+    pub fn merge_range(
+        &mut self,
+        first_row: u32,
+        first_col: u16,
+        last_row: u32,
+        last_col: u16,
+        value: CellValue,
+    ) -> Result<()> {
+        self.sheet
+            .merge_range(first_row, first_col, last_row, last_col, value)
+    }
+
+    pub fn add_data_validation(
+        &mut self,
+        range: CellRange,
+        validation: &DataValidation,
+    ) -> Result<()> {
+        self.sheet.add_data_validation(range, validation)
+    }
+
+    pub fn set_page_setup(&mut self, setup: &PageSetup) -> Result<()> {
+        self.sheet.set_page_setup(setup)
+    }
+
+    pub fn protect(&mut self, password: Option<&str>, options: &ProtectionOptions) -> Result<()> {
+        self.sheet.protect(password, options)
+    }
+
+    // Closes the sheet. The part still has to be passed to
+    // WorkBook::append_sheet before the workbook is finished.
+    pub fn finish(mut self) -> Result<SheetPart> {
+        self.sheet.close()?;
+
+        let name = self.sheet._name.clone();
+        let formatter = self
+            .sheet
+            .into_formatter()
+            .expect("sheet builders own their formatter");
+
+        Ok(SheetPart {
+            id: self.id,
+            name,
+            formatter,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::thread;
+
+    use zip::ZipArchive;
+
+    use crate::page_setup::PageSetup;
+    use crate::test_util::read_part;
+    use crate::workbook::WorkBook;
+
+    #[test]
+    fn appends_builders_out_of_order() {
+        let mut workbook = WorkBook::new(Cursor::new(Vec::new()));
+        let second = workbook.sheet_builder(String::from("Second")).unwrap();
+        let third = workbook.sheet_builder(String::from("Third")).unwrap();
+
+        let handles: Vec<_> = [(second, "second"), (third, "third")]
+            .into_iter()
+            .map(|(mut builder, text)| {
+                thread::spawn(move || {
+                    for i in 0..5000 {
+                        let row = format!("{text} {i}");
+                        builder.write_row(vec![row.as_bytes()]).unwrap();
+                    }
+                    builder.autofilter_header();
+                    builder.finish().unwrap()
+                })
+            })
+            .collect();
+
+        let mut first = workbook.get_worksheet(String::from("First")).unwrap();
+        first.write_row(vec![b"first"]).unwrap();
+        first.close().unwrap();
+
+        let mut parts: Vec<_> = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+        workbook.append_sheet(parts.pop().unwrap()).unwrap();
+        workbook.append_sheet(parts.pop().unwrap()).unwrap();
+        let archive = workbook.finish().unwrap().into_inner();
+
+        // Every part inflates, and each sheet keeps the id it was given
+        let mut zip = ZipArchive::new(Cursor::new(&archive)).unwrap();
+        for i in 0..zip.len() {
+            let mut part = zip.by_index(i).unwrap();
+            std::io::copy(&mut part, &mut std::io::sink()).unwrap();
+        }
+
+        let workbook_xml = read_part(&archive, "xl/workbook.xml");
+        let first_pos = workbook_xml.find("name=\"First\" sheetId=\"3\"").unwrap();
+        let second_pos = workbook_xml.find("name=\"Second\" sheetId=\"1\"").unwrap();
+        let third_pos = workbook_xml.find("name=\"Third\" sheetId=\"2\"").unwrap();
+        assert!(second_pos < third_pos && third_pos < first_pos);
+
+        let second_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(second_xml.contains("<t>second 4999</t>"));
+        assert!(second_xml.contains("<autoFilter ref=\"A1:A5000\"/>"));
+        assert!(read_part(&archive, "xl/worksheets/sheet2.xml").contains("<t>third 0</t>"));
+        assert!(read_part(&archive, "xl/worksheets/sheet3.xml").contains("<t>first</t>"));
+        assert!(workbook_xml.contains("localSheetId=\"0\""));
+        assert!(workbook_xml.contains("localSheetId=\"1\""));
+    }
+
+    #[test]
+    fn rejects_foreign_and_missing_parts() {
+        let mut workbook = WorkBook::new(Cursor::new(Vec::new()));
+        let mut other = WorkBook::new(Cursor::new(Vec::new()));
+        other.sheet_builder(String::from("Other")).unwrap();
+
+        let builder = workbook.sheet_builder(String::from("Sheet")).unwrap();
+        let part = builder.finish().unwrap();
+        assert!(other.append_sheet(part).is_err());
+
+        let unappended = workbook.sheet_builder(String::from("Later")).unwrap();
+        drop(unappended);
+        assert!(workbook.finish().is_err());
+    }
+
+    #[test]
+    fn leaves_the_workbook_unchanged_when_names_clash() {
+        let mut workbook = WorkBook::new(Cursor::new(Vec::new()));
+        let mut builder = workbook.sheet_builder(String::from("Sheet")).unwrap();
+        builder
+            .set_page_setup(&PageSetup::new().repeat_rows(0, 0))
+            .unwrap();
+        builder.write_row(vec![b"header"]).unwrap();
+        let part = builder.finish().unwrap();
+
+        workbook
+            .define_name("_xlnm.Print_Titles", "Sheet!$1:$2", Some("Sheet"))
+            .unwrap();
+        let error = workbook.append_sheet(part).unwrap_err();
+        assert!(error.to_string().contains("already defined"));
+
+        // The sheet is still waiting for its part
+        let error = workbook.finish().unwrap_err();
+        assert!(error.to_string().contains("never appended"));
+    }
+}
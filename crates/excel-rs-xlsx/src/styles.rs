@@ -0,0 +1,791 @@
+use std::io::{Result, Write};
+
+use anyhow::bail;
+
+use crate::cell::escape_xml_into;
+
+// cellXfs 1-3 are the built-in date, date time and time styles used by
+// CellValue dates, so registered formats start after them
+const BUILTIN_XF_COUNT: usize = 4;
+const FIRST_CUSTOM_NUM_FMT_ID: u32 = 164;
+const MAX_XF_COUNT: usize = 64000;
+const HYPERLINK_COLOR: u32 = 0x0563C1;
+const MAX_COLOR: u32 = 0xFFFFFF;
+// The range Excel's font size box accepts, in points
+const MIN_FONT_SIZE: f64 = 1.0;
+const MAX_FONT_SIZE: f64 = 409.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StyleId(pub(crate) u32);
+
+pub(crate) const DATE_STYLE_ID: StyleId = StyleId(1);
+pub(crate) const DATE_TIME_STYLE_ID: StyleId = StyleId(2);
+pub(crate) const TIME_STYLE_ID: StyleId = StyleId(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BorderStyle {
+    Thin,
+    Medium,
+    Thick,
+    Dashed,
+    Dotted,
+    Double,
+    Hair,
+}
+
+impl BorderStyle {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BorderStyle::Thin => "thin",
+            BorderStyle::Medium => "medium",
+            BorderStyle::Thick => "thick",
+            BorderStyle::Dashed => "dashed",
+            BorderStyle::Dotted => "dotted",
+            BorderStyle::Double => "double",
+            BorderStyle::Hair => "hair",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HorizontalAlign {
+    General,
+    Left,
+    Center,
+    Right,
+    Fill,
+    Justify,
+    CenterContinuous,
+    Distributed,
+}
+
+impl HorizontalAlign {
+    fn as_str(&self) -> &'static str {
+        match self {
+            HorizontalAlign::General => "general",
+            HorizontalAlign::Left => "left",
+            HorizontalAlign::Center => "center",
+            HorizontalAlign::Right => "right",
+            HorizontalAlign::Fill => "fill",
+            HorizontalAlign::Justify => "justify",
+            HorizontalAlign::CenterContinuous => "centerContinuous",
+            HorizontalAlign::Distributed => "distributed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerticalAlign {
+    Top,
+    Center,
+    Bottom,
+    Justify,
+    Distributed,
+}
+
+impl VerticalAlign {
+    fn as_str(&self) -> &'static str {
+        match self {
+            VerticalAlign::Top => "top",
+            VerticalAlign::Center => "center",
+            VerticalAlign::Bottom => "bottom",
+            VerticalAlign::Justify => "justify",
+            VerticalAlign::Distributed => "distributed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Font {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    size: f64,
+    // RGB, or the theme text colour when None
+    color: Option<u32>,
+    name: String,
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Font {
+            bold: false,
+            italic: false,
+            underline: false,
+            size: 12.0,
+            color: None,
+            name: String::from("Calibri"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fill {
+    None,
+    Gray125,
+    Solid(u32),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct BorderSide {
+    style: Option<BorderStyle>,
+    color: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Border {
+    left: BorderSide,
+    right: BorderSide,
+    top: BorderSide,
+    bottom: BorderSide,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Alignment {
+    horizontal: Option<HorizontalAlign>,
+    vertical: Option<VerticalAlign>,
+    wrap_text: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Protection {
+    locked: bool,
+    // Hides the cell's formula while the sheet is protected
+    hidden: bool,
+}
+
+// Cells are locked unless their format says otherwise, though that only takes
+// effect once the sheet is protected
+impl Default for Protection {
+    fn default() -> Self {
+        Protection {
+            locked: true,
+            hidden: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Xf {
+    num_fmt_id: u32,
+    font_id: usize,
+    fill_id: usize,
+    border_id: usize,
+    alignment: Alignment,
+    protection: Protection,
+}
+
+// Cell formatting, registered with WorkBook::add_format
+//
+// Colours are 0xRRGGBB.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Format {
+    font: Font,
+    fill_color: Option<u32>,
+    border: Border,
+    alignment: Alignment,
+    num_format: Option<String>,
+    protection: Protection,
+}
+
+impl Format {
+    pub fn new() -> Self {
+        Format::default()
+    }
+
+    pub fn bold(mut self) -> Self {
+        self.font.bold = true;
+        self
+    }
+
+    pub fn italic(mut self) -> Self {
+        self.font.italic = true;
+        self
+    }
+
+    pub fn underline(mut self) -> Self {
+        self.font.underline = true;
+        self
+    }
+
+    pub fn font_size(mut self, size: f64) -> Self {
+        self.font.size = size;
+        self
+    }
+
+    pub fn font_color(mut self, color: u32) -> Self {
+        self.font.color = Some(color);
+        self
+    }
+
+    pub fn font_name(mut self, name: &str) -> Self {
+        self.font.name = String::from(name);
+        self
+    }
+
+    pub fn fill_color(mut self, color: u32) -> Self {
+        self.fill_color = Some(color);
+        self
+    }
+
+    pub fn border(self, style: BorderStyle) -> Self {
+        self.border_left(style)
+            .border_right(style)
+            .border_top(style)
+            .border_bottom(style)
+    }
+
+    pub fn border_left(mut self, style: BorderStyle) -> Self {
+        self.border.left.style = Some(style);
+        self
+    }
+
+    pub fn border_right(mut self, style: BorderStyle) -> Self {
+        self.border.right.style = Some(style);
+        self
+    }
+
+    pub fn border_top(mut self, style: BorderStyle) -> Self {
+        self.border.top.style = Some(style);
+        self
+    }
+
+    pub fn border_bottom(mut self, style: BorderStyle) -> Self {
+        self.border.bottom.style = Some(style);
+        self
+    }
+
+    pub fn border_color(mut self, color: u32) -> Self {
+        for side in [
+            &mut self.border.left,
+            &mut self.border.right,
+            &mut self.border.top,
+            &mut self.border.bottom,
+        ] {
+            side.color = Some(color);
+        }
+        self
+    }
+
+    pub fn align(mut self, align: HorizontalAlign) -> Self {
+        self.alignment.horizontal = Some(align);
+        self
+    }
+
+    pub fn vertical_align(mut self, align: VerticalAlign) -> Self {
+        self.alignment.vertical = Some(align);
+        self
+    }
+
+    pub fn wrap_text(mut self) -> Self {
+        self.alignment.wrap_text = true;
+        self
+    }
+
+    // Leaves the cells editable when the sheet is protected
+    pub fn unlocked(mut self) -> Self {
+        self.protection.locked = false;
+        self
+    }
+
+    // Hides the cells' formulas when the sheet is protected
+    pub fn hide_formulas(mut self) -> Self {
+        self.protection.hidden = true;
+        self
+    }
+
+    // Excel number format code, e.g. "0.00%" or "yyyy-mm-dd"
+    pub fn num_format(mut self, format: &str) -> Self {
+        self.num_format = Some(String::from(format));
+        self
+    }
+
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        let size = self.font.size;
+        if !(MIN_FONT_SIZE..=MAX_FONT_SIZE).contains(&size) {
+            bail!("font size {size} must be between {MIN_FONT_SIZE} and {MAX_FONT_SIZE} points");
+        }
+
+        let border = &self.border;
+        let colors = [
+            self.font.color,
+            self.fill_color,
+            border.left.color,
+            border.right.color,
+            border.top.color,
+            border.bottom.color,
+        ];
+        for color in colors.into_iter().flatten() {
+            validate_color(color)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) struct StyleRegistry {
+    num_fmts: Vec<String>,
+    fonts: Vec<Font>,
+    fills: Vec<Fill>,
+    borders: Vec<Border>,
+    xfs: Vec<Xf>,
+    // Differential formats applied by conditional formatting
+    dxfs: Vec<Format>,
+}
+
+impl StyleRegistry {
+    pub fn new() -> Self {
+        StyleRegistry {
+            num_fmts: Vec::new(),
+            fonts: vec![Font::default()],
+            fills: vec![Fill::None, Fill::Gray125],
+            borders: vec![Border::default()],
+            xfs: Vec::new(),
+            dxfs: Vec::new(),
+        }
+    }
+
+    // Whether no format has been registered
+    pub fn is_unused(&self) -> bool {
+        self.xfs.is_empty() && self.dxfs.is_empty()
+    }
+
+    pub fn add_format(&mut self, format: &Format) -> anyhow::Result<StyleId> {
+        format.validate()?;
+
+        let num_fmt_id = match &format.num_format {
+            Some(code) => {
+                let index = position_or_push(&mut self.num_fmts, code);
+                FIRST_CUSTOM_NUM_FMT_ID + index as u32
+            }
+            None => 0,
+        };
+
+        let fill = match format.fill_color {
+            Some(color) => Fill::Solid(color),
+            None => Fill::None,
+        };
+
+        let xf = Xf {
+            num_fmt_id,
+            font_id: position_or_push(&mut self.fonts, &format.font),
+            fill_id: position_or_push(&mut self.fills, &fill),
+            border_id: position_or_push(&mut self.borders, &format.border),
+            alignment: format.alignment,
+            protection: format.protection,
+        };
+
+        if let Some(index) = self.xfs.iter().position(|existing| *existing == xf) {
+            return Ok(StyleId((BUILTIN_XF_COUNT + index) as u32));
+        }
+
+        if BUILTIN_XF_COUNT + self.xfs.len() >= MAX_XF_COUNT {
+            bail!("workbook cannot hold more than {MAX_XF_COUNT} cell formats");
+        }
+
+        self.xfs.push(xf);
+        Ok(StyleId((BUILTIN_XF_COUNT + self.xfs.len() - 1) as u32))
+    }
+
+    // Blue and underlined, like Excel's Hyperlink cell style
+    pub fn hyperlink_style(&mut self) -> anyhow::Result<StyleId> {
+        self.add_format(&Format::new().underline().font_color(HYPERLINK_COLOR))
+    }
+
+    // Only the parts of the format that differ from a plain cell are applied
+    pub fn add_dxf(&mut self, format: &Format) -> u32 {
+        if let Some(code) = &format.num_format {
+            position_or_push(&mut self.num_fmts, code);
+        }
+        position_or_push(&mut self.dxfs, format) as u32
+    }
+
+    pub fn write_xml<W: Write>(&self, writer: &mut W) -> Result<()> {
+        write!(
+            writer,
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">"#
+        )?;
+
+        if !self.num_fmts.is_empty() {
+            let mut buf = Vec::new();
+            write!(buf, "<numFmts count=\"{}\">", self.num_fmts.len())?;
+            for (i, code) in self.num_fmts.iter().enumerate() {
+                write!(
+                    buf,
+                    "<numFmt numFmtId=\"{}\" formatCode=\"",
+                    FIRST_CUSTOM_NUM_FMT_ID + i as u32
+                )?;
+                escape_xml_into(&mut buf, code.as_bytes());
+                buf.extend_from_slice(b"\"/>");
+            }
+            buf.extend_from_slice(b"</numFmts>");
+            writer.write_all(&buf)?;
+        }
+
+        write!(writer, "<fonts count=\"{}\">", self.fonts.len())?;
+        for font in &self.fonts {
+            let mut buf = Vec::new();
+            buf.extend_from_slice(b"<font>");
+            if font.bold {
+                buf.extend_from_slice(b"<b/>");
+            }
+            if font.italic {
+                buf.extend_from_slice(b"<i/>");
+            }
+            if font.underline {
+                buf.extend_from_slice(b"<u/>");
+            }
+            write!(buf, "<sz val=\"{}\"/>", font.size)?;
+            match font.color {
+                Some(color) => write!(buf, "<color rgb=\"FF{:06X}\"/>", color)?,
+                None => buf.extend_from_slice(b"<color theme=\"1\"/>"),
+            }
+            buf.extend_from_slice(b"<name val=\"");
+            escape_xml_into(&mut buf, font.name.as_bytes());
+            buf.extend_from_slice(b"\"/><family val=\"2\"/>");
+            if font.name == "Calibri" {
+                buf.extend_from_slice(b"<scheme val=\"minor\"/>");
+            }
+            buf.extend_from_slice(b"</font>");
+            writer.write_all(&buf)?;
+        }
+        write!(writer, "</fonts>")?;
+
+        write!(writer, "<fills count=\"{}\">", self.fills.len())?;
+        for fill in &self.fills {
+            match fill {
+                Fill::None => write!(writer, "<fill><patternFill patternType=\"none\"/></fill>")?,
+                Fill::Gray125 => {
+                    write!(writer, "<fill><patternFill patternType=\"gray125\"/></fill>")?
+                }
+                Fill::Solid(color) => write!(
+                    writer,
+                    "<fill><patternFill patternType=\"solid\"><fgColor rgb=\"FF{:06X}\"/><bgColor indexed=\"64\"/></patternFill></fill>",
+                    color
+                )?,
+            }
+        }
+        write!(writer, "</fills>")?;
+
+        write!(writer, "<borders count=\"{}\">", self.borders.len())?;
+        for border in &self.borders {
+            write!(writer, "<border>")?;
+            for (tag, side) in [
+                ("left", &border.left),
+                ("right", &border.right),
+                ("top", &border.top),
+                ("bottom", &border.bottom),
+            ] {
+                match (side.style, side.color) {
+                    (None, _) => write!(writer, "<{}/>", tag)?,
+                    (Some(style), None) => {
+                        write!(writer, "<{} style=\"{}\"/>", tag, style.as_str())?
+                    }
+                    (Some(style), Some(color)) => write!(
+                        writer,
+                        "<{} style=\"{}\"><color rgb=\"FF{:06X}\"/></{}>",
+                        tag,
+                        style.as_str(),
+                        color,
+                        tag
+                    )?,
+                }
+            }
+            write!(writer, "<diagonal/></border>")?;
+        }
+        write!(writer, "</borders>")?;
+
+        write!(
+            writer,
+            r#"<cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs>"#
+        )?;
+
+        write!(
+            writer,
+            r#"<cellXfs count="{}"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/><xf numFmtId="14" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/><xf numFmtId="22" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/><xf numFmtId="21" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/>"#,
+            BUILTIN_XF_COUNT + self.xfs.len()
+        )?;
+        for xf in &self.xfs {
+            write!(
+                writer,
+                "<xf numFmtId=\"{}\" fontId=\"{}\" fillId=\"{}\" borderId=\"{}\" xfId=\"0\"",
+                xf.num_fmt_id, xf.font_id, xf.fill_id, xf.border_id
+            )?;
+            if xf.num_fmt_id != 0 {
+                write!(writer, " applyNumberFormat=\"1\"")?;
+            }
+            if xf.font_id != 0 {
+                write!(writer, " applyFont=\"1\"")?;
+            }
+            if xf.fill_id != 0 {
+                write!(writer, " applyFill=\"1\"")?;
+            }
+            if xf.border_id != 0 {
+                write!(writer, " applyBorder=\"1\"")?;
+            }
+
+            let alignment = &xf.alignment;
+            let protection = &xf.protection;
+            let has_alignment = *alignment != Alignment::default();
+            let has_protection = *protection != Protection::default();
+            if !has_alignment && !has_protection {
+                write!(writer, "/>")?;
+                continue;
+            }
+
+            if has_alignment {
+                write!(writer, " applyAlignment=\"1\"")?;
+            }
+            if has_protection {
+                write!(writer, " applyProtection=\"1\"")?;
+            }
+            write!(writer, ">")?;
+
+            if has_alignment {
+                write!(writer, "<alignment")?;
+                if let Some(horizontal) = alignment.horizontal {
+                    write!(writer, " horizontal=\"{}\"", horizontal.as_str())?;
+                }
+                if let Some(vertical) = alignment.vertical {
+                    write!(writer, " vertical=\"{}\"", vertical.as_str())?;
+                }
+                if alignment.wrap_text {
+                    write!(writer, " wrapText=\"1\"")?;
+                }
+                write!(writer, "/>")?;
+            }
+            if has_protection {
+                write!(writer, "<protection")?;
+                if !protection.locked {
+                    write!(writer, " locked=\"0\"")?;
+                }
+                if protection.hidden {
+                    write!(writer, " hidden=\"1\"")?;
+                }
+                write!(writer, "/>")?;
+            }
+            write!(writer, "</xf>")?;
+        }
+        write!(writer, "</cellXfs>")?;
+
+        write!(
+            writer,
+            r#"<cellStyles count="1"><cellStyle name="Normal" xfId="0" builtinId="0"/></cellStyles>"#
+        )?;
+
+        write!(writer, "<dxfs count=\"{}\">", self.dxfs.len())?;
+        for format in &self.dxfs {
+            let mut buf = Vec::new();
+            self.write_dxf(&mut buf, format)?;
+            writer.write_all(&buf)?;
+        }
+        write!(writer, "</dxfs>")?;
+
+        write!(
+            writer,
+            r#"<tableStyles count="0" defaultTableStyle="TableStyleMedium9" defaultPivotStyle="PivotStyleMedium4"/></styleSheet>"#
+        )
+    }
+
+    fn write_dxf(&self, buf: &mut Vec<u8>, format: &Format) -> Result<()> {
+        buf.extend_from_slice(b"<dxf>");
+
+        let font = &format.font;
+        let default_font = Font::default();
+        if *font != default_font {
+            buf.extend_from_slice(b"<font>");
+            if font.bold {
+                buf.extend_from_slice(b"<b/>");
+            }
+            if font.italic {
+                buf.extend_from_slice(b"<i/>");
+            }
+            if font.underline {
+                buf.extend_from_slice(b"<u/>");
+            }
+            if font.size != default_font.size {
+                write!(buf, "<sz val=\"{}\"/>", font.size)?;
+            }
+            if let Some(color) = font.color {
+                write!(buf, "<color rgb=\"FF{:06X}\"/>", color)?;
+            }
+            if font.name != default_font.name {
+                buf.extend_from_slice(b"<name val=\"");
+                escape_xml_into(buf, font.name.as_bytes());
+                buf.extend_from_slice(b"\"/>");
+            }
+            buf.extend_from_slice(b"</font>");
+        }
+
+        if let Some(code) = &format.num_format {
+            let index = self.num_fmts.iter().position(|existing| existing == code);
+            write!(
+                buf,
+                "<numFmt numFmtId=\"{}\" formatCode=\"",
+                FIRST_CUSTOM_NUM_FMT_ID + index.unwrap_or_default() as u32
+            )?;
+            escape_xml_into(buf, code.as_bytes());
+            buf.extend_from_slice(b"\"/>");
+        }
+
+        // Differential fills take the background colour of the pattern
+        if let Some(color) = format.fill_color {
+            write!(
+                buf,
+                "<fill><patternFill patternType=\"solid\"><bgColor rgb=\"FF{:06X}\"/></patternFill></fill>",
+                color
+            )?;
+        }
+
+        let alignment = &format.alignment;
+        if *alignment != Alignment::default() {
+            buf.extend_from_slice(b"<alignment");
+            if let Some(horizontal) = alignment.horizontal {
+                write!(buf, " horizontal=\"{}\"", horizontal.as_str())?;
+            }
+            if let Some(vertical) = alignment.vertical {
+                write!(buf, " vertical=\"{}\"", vertical.as_str())?;
+            }
+            if alignment.wrap_text {
+                buf.extend_from_slice(b" wrapText=\"1\"");
+            }
+            buf.extend_from_slice(b"/>");
+        }
+
+        let border = &format.border;
+        if *border != Border::default() {
+            buf.extend_from_slice(b"<border>");
+            for (tag, side) in [
+                ("left", &border.left),
+                ("right", &border.right),
+                ("top", &border.top),
+                ("bottom", &border.bottom),
+            ] {
+                match (side.style, side.color) {
+                    (None, _) => {}
+                    (Some(style), None) => write!(buf, "<{} style=\"{}\"/>", tag, style.as_str())?,
+                    (Some(style), Some(color)) => write!(
+                        buf,
+                        "<{} style=\"{}\"><color rgb=\"FF{:06X}\"/></{}>",
+                        tag,
+                        style.as_str(),
+                        color,
+                        tag
+                    )?,
+                }
+            }
+            buf.extend_from_slice(b"</border>");
+        }
+
+        buf.extend_from_slice(b"</dxf>");
+
+        Ok(())
+    }
+}
+
+pub(crate) fn validate_color(color: u32) -> anyhow::Result<()> {
+    if color > MAX_COLOR {
+        bail!("colour {color:#08X} is not an 0xRRGGBB value");
+    }
+    Ok(())
+}
+
+fn position_or_push<T: PartialEq + Clone>(items: &mut Vec<T>, item: &T) -> usize {
+    match items.iter().position(|existing| existing == item) {
+        Some(index) => index,
+        None => {
+            items.push(item.clone());
+            items.len() - 1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BorderStyle, Format, HorizontalAlign, StyleId, StyleRegistry};
+
+    fn styles_xml(styles: &StyleRegistry) -> String {
+        let mut buf = Vec::new();
+        styles.write_xml(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn shares_identical_formats_and_their_parts() {
+        let mut styles = StyleRegistry::new();
+        let bold = styles.add_format(&Format::new().bold()).unwrap();
+        let percent = Format::new().bold().num_format("0.00%");
+        let bold_percent = styles.add_format(&percent).unwrap();
+        let boxed = Format::new()
+            .border(BorderStyle::Thin)
+            .fill_color(0xFF0000)
+            .align(HorizontalAlign::Center)
+            .unlocked();
+        let boxed_id = styles.add_format(&boxed).unwrap();
+
+        assert_eq!(bold, StyleId(4));
+        assert_eq!(bold_percent, StyleId(5));
+        assert_eq!(boxed_id, StyleId(6));
+        assert_eq!(styles.add_format(&Format::new().bold()).unwrap(), bold);
+        assert_eq!(styles.add_format(&percent).unwrap(), bold_percent);
+        assert_eq!(styles.add_format(&boxed).unwrap(), boxed_id);
+
+        let xml = styles_xml(&styles);
+        assert!(xml.contains(
+            "<numFmts count=\"1\"><numFmt numFmtId=\"164\" formatCode=\"0.00%\"/></numFmts>"
+        ));
+        assert!(xml.contains("<fonts count=\"2\">"));
+        assert!(xml.contains("<fills count=\"3\">"));
+        assert!(xml.contains("<fgColor rgb=\"FFFF0000\"/>"));
+        assert!(xml.contains("<borders count=\"2\">"));
+        assert!(xml.contains("<cellXfs count=\"7\">"));
+        assert!(xml.contains(concat!(
+            "<xf numFmtId=\"0\" fontId=\"1\" fillId=\"0\" borderId=\"0\" xfId=\"0\" applyFont=\"1\"/>",
+            "<xf numFmtId=\"164\" fontId=\"1\" fillId=\"0\" borderId=\"0\" xfId=\"0\" applyNumberFormat=\"1\" applyFont=\"1\"/>",
+            "<xf numFmtId=\"0\" fontId=\"0\" fillId=\"2\" borderId=\"1\" xfId=\"0\" applyFill=\"1\" applyBorder=\"1\" applyAlignment=\"1\" applyProtection=\"1\">",
+            "<alignment horizontal=\"center\"/><protection locked=\"0\"/></xf></cellXfs>"
+        )));
+    }
+
+    #[test]
+    fn numbers_differential_formats_separately() {
+        let mut styles = StyleRegistry::new();
+        assert_eq!(styles.add_dxf(&Format::new().bold()), 0);
+        assert_eq!(styles.add_dxf(&Format::new().fill_color(0x00FF00)), 1);
+        assert_eq!(styles.add_dxf(&Format::new().bold()), 0);
+        assert_eq!(styles.add_dxf(&Format::new().num_format("0.0")), 2);
+        assert!(!styles.is_unused());
+
+        let xml = styles_xml(&styles);
+        assert!(xml.contains("<cellXfs count=\"4\">"));
+        assert!(xml.contains(concat!(
+            "<dxfs count=\"3\">",
+            "<dxf><font><b/></font></dxf>",
+            "<dxf><fill><patternFill patternType=\"solid\"><bgColor rgb=\"FF00FF00\"/></patternFill></fill></dxf>",
+            "<dxf><numFmt numFmtId=\"164\" formatCode=\"0.0\"/></dxf>",
+            "</dxfs>"
+        )));
+
+        // A cell format after the dxf shares its number format id
+        let id = styles.add_format(&Format::new().num_format("0.0")).unwrap();
+        assert_eq!(id, StyleId(4));
+        assert!(styles_xml(&styles).contains("<xf numFmtId=\"164\" fontId=\"0\""));
+    }
+
+    #[test]
+    fn rejects_font_sizes_excel_cannot_show() {
+        let mut styles = StyleRegistry::new();
+        for size in [f64::NAN, f64::INFINITY, -12.0, 0.0, 0.5, 409.5] {
+            assert!(
+                styles.add_format(&Format::new().font_size(size)).is_err(),
+                "{size}"
+            );
+        }
+
+        styles.add_format(&Format::new().font_size(1.0)).unwrap();
+        styles.add_format(&Format::new().font_size(409.0)).unwrap();
+        assert!(styles
+            .add_format(&Format::new().fill_color(0x1000000))
+            .is_err());
+    }
+}
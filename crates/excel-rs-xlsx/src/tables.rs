@@ -0,0 +1,429 @@
+use std::{borrow::Cow, collections::HashSet, io::Write};
+
+use anyhow::{bail, Result};
+
+use crate::cell::{escape_xml_into, CellRange};
+
+pub(crate) const TABLE_CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.table+xml";
+const DEFAULT_TABLE_STYLE: &str = "TableStyleMedium9";
+const MAX_TABLE_NAME_LEN: usize = 255;
+
+enum TableRange {
+    Fixed(CellRange),
+    // From A1 down to the last row written, across the columns of the header
+    // row. Resolved when the sheet is closed.
+    Header,
+}
+
+pub(crate) struct Table {
+    id: u32,
+    name: String,
+    range: TableRange,
+    style: String,
+    header_row: bool,
+    rel_id: String,
+    // Column names by column offset, worked out as the header row is written
+    // so the header cells can show them
+    column_names: Vec<String>,
+    // Lowercase column names, as Excel compares them without case
+    used_names: HashSet<String>,
+    header_named: bool,
+}
+
+impl Table {
+    pub fn new(
+        id: u32,
+        name: &str,
+        range: Option<CellRange>,
+        style: Option<&str>,
+        header_row: bool,
+    ) -> Result<Self> {
+        let style = style.unwrap_or(DEFAULT_TABLE_STYLE);
+        if !is_builtin_table_style(style) {
+            bail!("unknown table style {style:?}");
+        }
+
+        let range = match range {
+            Some(range) => {
+                range.to_ref()?;
+                if header_row && range.first_row == range.last_row {
+                    bail!("a table with a header row needs at least two rows");
+                }
+                TableRange::Fixed(range)
+            }
+            None => TableRange::Header,
+        };
+
+        Ok(Table {
+            id,
+            name: name.to_owned(),
+            range,
+            style: style.to_owned(),
+            header_row,
+            rel_id: String::new(),
+            column_names: Vec::new(),
+            used_names: HashSet::new(),
+            header_named: false,
+        })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn rel_id(&self) -> &str {
+        &self.rel_id
+    }
+
+    pub fn set_rel_id(&mut self, rel_id: String) {
+        self.rel_id = rel_id;
+    }
+
+    pub fn header_row(&self) -> Option<u32> {
+        match (&self.range, self.header_row) {
+            (_, false) => None,
+            (TableRange::Fixed(range), true) => Some(range.first_row),
+            (TableRange::Header, true) => Some(0),
+        }
+    }
+
+    // Names the column from the text of its header cell, which must then show
+    // the name instead. Cells are named left to right; None means the cell is
+    // outside the table.
+    pub fn name_column(&mut self, col: usize, text: Option<&str>) -> Option<String> {
+        let (first_col, last_col) = match &self.range {
+            TableRange::Fixed(range) => (range.first_col as usize, range.last_col as usize),
+            TableRange::Header => (0, usize::MAX),
+        };
+
+        if col < first_col || col > last_col {
+            return None;
+        }
+
+        let offset = col - first_col;
+        while self.column_names.len() < offset {
+            self.push_column_name(None);
+        }
+        match self.column_names.get(offset) {
+            Some(name) => Some(name.clone()),
+            None => Some(self.push_column_name(text).to_owned()),
+        }
+    }
+
+    // Names the columns the header row left out, which need header cells of
+    // their own. A header table gets at least one column.
+    pub fn finish_header(&mut self) -> Vec<(u16, String)> {
+        self.header_named = true;
+
+        let (first_col, col_count) = match &self.range {
+            TableRange::Fixed(range) => (range.first_col, range.last_col - range.first_col + 1),
+            TableRange::Header => (0, 1),
+        };
+
+        let mut missing = Vec::new();
+        while self.column_names.len() < col_count as usize {
+            let col = first_col + self.column_names.len() as u16;
+            missing.push((col, self.push_column_name(None).to_owned()));
+        }
+        missing
+    }
+
+    pub fn is_header_named(&self) -> bool {
+        self.header_named
+    }
+
+    // Header tables always keep a data row, even an empty one
+    pub fn range(&self, row_count: u32) -> CellRange {
+        match &self.range {
+            TableRange::Fixed(range) => *range,
+            TableRange::Header => {
+                let col_count = self.column_names.len().max(1) as u16;
+                CellRange::new(0, 0, row_count.max(2) - 1, col_count - 1)
+            }
+        }
+    }
+
+    pub fn write_xml<W: Write>(&self, writer: &mut W, range: CellRange) -> Result<()> {
+        let range_ref = range.to_ref()?;
+        let col_count = (range.last_col - range.first_col) as usize + 1;
+
+        let mut buf = Vec::with_capacity(512 + 64 * col_count);
+        buf.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<table xmlns=\"http://schemas.openxmlformats.org/spreadsheetml/2006/main\"")?;
+        write!(buf, " id=\"{}\" name=\"", self.id)?;
+        escape_xml_into(&mut buf, self.name.as_bytes());
+        buf.write_all(b"\" displayName=\"")?;
+        escape_xml_into(&mut buf, self.name.as_bytes());
+        write!(buf, "\" ref=\"{}\"", range_ref)?;
+        if !self.header_row {
+            buf.write_all(b" headerRowCount=\"0\"")?;
+        }
+        buf.write_all(b" totalsRowShown=\"0\">")?;
+
+        if self.header_row {
+            write!(buf, "<autoFilter ref=\"{}\"/>", range_ref)?;
+        }
+
+        write!(buf, "<tableColumns count=\"{}\">", col_count)?;
+        for i in 0..col_count {
+            // Tables without a header row keep Excel's default names
+            let name = match self.column_names.get(i) {
+                Some(name) => Cow::from(name),
+                None => Cow::from(format!("Column{}", i + 1)),
+            };
+            write!(buf, "<tableColumn id=\"{}\" name=\"", i + 1)?;
+            escape_xml_into(&mut buf, name.as_bytes());
+            buf.write_all(b"\"/>")?;
+        }
+        buf.write_all(b"</tableColumns>")?;
+
+        write!(
+            buf,
+            "<tableStyleInfo name=\"{}\" showFirstColumn=\"0\" showLastColumn=\"0\" showRowStripes=\"1\" showColumnStripes=\"0\"/></table>",
+            self.style
+        )?;
+
+        writer.write_all(&buf)?;
+        Ok(())
+    }
+
+    // Column names must be unique, so blank or repeated headers get numbered
+    fn push_column_name(&mut self, text: Option<&str>) -> &str {
+        let base = match text {
+            Some(text) if !text.is_empty() => text.to_owned(),
+            _ => format!("Column{}", self.column_names.len() + 1),
+        };
+
+        let mut name = base.clone();
+        let mut suffix = 2;
+        while !self.used_names.insert(name.to_lowercase()) {
+            name = format!("{}{}", base, suffix);
+            suffix += 1;
+        }
+
+        self.column_names.push(name);
+        self.column_names.last().expect("a name was just pushed")
+    }
+}
+
+pub(crate) fn validate_table_name(name: &str) -> Result<()> {
+    let mut chars = name.chars();
+
+    let valid_start = match chars.next() {
+        Some(c) => c.is_alphabetic() || c == '_' || c == '\\',
+        None => bail!("table name cannot be empty"),
+    };
+
+    if !valid_start || !chars.all(|c| c.is_alphanumeric() || c == '_' || c == '.') {
+        bail!("table name {name:?} must start with a letter or underscore and contain only letters, digits, underscores and periods");
+    }
+
+    if name.chars().count() > MAX_TABLE_NAME_LEN {
+        bail!("table name {name:?} is longer than {MAX_TABLE_NAME_LEN} characters");
+    }
+
+    if looks_like_cell_ref(name) {
+        bail!("table name {name:?} cannot look like a cell reference");
+    }
+
+    Ok(())
+}
+
+// A1 and R1C1 style references, including the bare "R" and "C"
+pub(crate) fn looks_like_cell_ref(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    if upper == "R" || upper == "C" {
+        return true;
+    }
+
+    let letters = upper.bytes().take_while(u8::is_ascii_alphabetic).count();
+    let a1 = (1..=3).contains(&letters)
+        && upper.len() > letters
+        && upper.bytes().skip(letters).all(|b| b.is_ascii_digit());
+
+    let r1c1 = upper
+        .strip_prefix('R')
+        .map(|rest| rest.trim_start_matches(|c: char| c.is_ascii_digit()))
+        .and_then(|rest| rest.strip_prefix('C'))
+        .is_some_and(|rest| rest.bytes().all(|b| b.is_ascii_digit()));
+
+    a1 || r1c1
+}
+
+fn is_builtin_table_style(style: &str) -> bool {
+    let (count, number) = if let Some(number) = style.strip_prefix("TableStyleLight") {
+        (21, number)
+    } else if let Some(number) = style.strip_prefix("TableStyleMedium") {
+        (28, number)
+    } else if let Some(number) = style.strip_prefix("TableStyleDark") {
+        (11, number)
+    } else {
+        return false;
+    };
+
+    matches!(number.parse::<u32>(), Ok(n) if (1..=count).contains(&n) && !number.starts_with('0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cell::{CellRange, CellValue};
+    use crate::test_util::{read_part, write_workbook};
+
+    #[test]
+    fn writes_unique_column_names_into_the_header_row() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Csv"))?;
+            sheet.add_header_table("Csv", None)?;
+            sheet.write_row(vec![&b"id"[..], b"", b"Name", b"name", b"Column2"])?;
+            sheet.write_row(vec![b"1", b"2", b"3", b"4", b"5", b"wider"])?;
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains(concat!(
+            "<row r=\"1\"><c r=\"A1\" t=\"inlineStr\"><is><t>id</t></is></c>",
+            "<c r=\"B1\" t=\"inlineStr\"><is><t>Column2</t></is></c>",
+            "<c r=\"C1\" t=\"inlineStr\"><is><t>Name</t></is></c>",
+            "<c r=\"D1\" t=\"inlineStr\"><is><t>name2</t></is></c>",
+            "<c r=\"E1\" t=\"inlineStr\"><is><t>Column22</t></is></c></row>"
+        )));
+
+        // The wider row's last cell is left outside the table
+        let table_xml = read_part(&archive, "xl/tables/table1.xml");
+        assert!(
+            table_xml.contains(" ref=\"A1:E2\" totalsRowShown=\"0\"><autoFilter ref=\"A1:E2\"/>")
+        );
+        assert!(table_xml.contains(concat!(
+            "<tableColumns count=\"5\"><tableColumn id=\"1\" name=\"id\"/>",
+            "<tableColumn id=\"2\" name=\"Column2\"/><tableColumn id=\"3\" name=\"Name\"/>",
+            "<tableColumn id=\"4\" name=\"name2\"/><tableColumn id=\"5\" name=\"Column22\"/>",
+            "</tableColumns>"
+        )));
+    }
+
+    #[test]
+    fn names_typed_and_missing_header_cells() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_typed_worksheet(String::from("Typed"))?;
+            sheet.add_table(
+                CellRange::new(1, 1, 3, 4),
+                "Fixed",
+                Some("TableStyleLight2"),
+                true,
+            )?;
+            sheet.write_row(&[CellValue::String("title")])?;
+            sheet.write_row(&[
+                CellValue::String("outside"),
+                CellValue::Int(2024),
+                CellValue::Blank,
+                CellValue::String("2024"),
+            ])?;
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains(concat!(
+            "<row r=\"2\"><c r=\"A2\" t=\"inlineStr\"><is><t>outside</t></is></c>",
+            "<c r=\"B2\" t=\"inlineStr\"><is><t>2024</t></is></c>",
+            "<c r=\"C2\" t=\"inlineStr\"><is><t>Column2</t></is></c>",
+            "<c r=\"D2\" t=\"inlineStr\"><is><t>20242</t></is></c>",
+            "<c r=\"E2\" t=\"inlineStr\"><is><t>Column4</t></is></c></row>"
+        )));
+
+        let table_xml = read_part(&archive, "xl/tables/table1.xml");
+        assert!(table_xml.contains(concat!(
+            "<tableColumns count=\"4\"><tableColumn id=\"1\" name=\"2024\"/>",
+            "<tableColumn id=\"2\" name=\"Column2\"/><tableColumn id=\"3\" name=\"20242\"/>",
+            "<tableColumn id=\"4\" name=\"Column4\"/></tableColumns>",
+            "<tableStyleInfo name=\"TableStyleLight2\""
+        )));
+    }
+
+    #[test]
+    fn names_header_rows_that_are_never_written() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Empty"))?;
+            sheet.add_header_table("Empty", None)?;
+            sheet.close()?;
+
+            let mut sheet = workbook.get_worksheet(String::from("Short"))?;
+            sheet.add_table(CellRange::new(4, 0, 6, 1), "Later", None, true)?;
+            sheet.add_table(CellRange::new(7, 0, 8, 1), "Plain", None, false)?;
+            sheet.write_row(vec![b"only row"])?;
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains(
+            "<row r=\"1\"><c r=\"A1\" t=\"inlineStr\"><is><t>Column1</t></is></c></row>"
+        ));
+        let table_xml = read_part(&archive, "xl/tables/table1.xml");
+        assert!(table_xml.contains(" ref=\"A1:A2\""));
+        assert!(table_xml.contains(
+            "<tableColumns count=\"1\"><tableColumn id=\"1\" name=\"Column1\"/></tableColumns>"
+        ));
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet2.xml");
+        assert!(sheet_xml.contains(concat!(
+            "<row r=\"5\"><c r=\"A5\" t=\"inlineStr\"><is><t>Column1</t></is></c>",
+            "<c r=\"B5\" t=\"inlineStr\"><is><t>Column2</t></is></c></row>"
+        )));
+        assert!(!sheet_xml.contains("<row r=\"8\">"));
+        let table_xml = read_part(&archive, "xl/tables/table3.xml");
+        assert!(table_xml.contains(" ref=\"A8:B9\" headerRowCount=\"0\""));
+        assert!(table_xml.contains("<tableColumn id=\"2\" name=\"Column2\"/>"));
+    }
+
+    #[test]
+    fn keeps_other_cells_out_of_the_header_row() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Sheet"))?;
+            sheet.add_header_table("Links", None)?;
+            sheet.write_url(0, 1, "https://example.com", None)?;
+            let err = sheet.write_row(vec![b"a", b"b"]).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "cell B1 is a table header and can only hold its column name"
+            );
+            sheet.close()
+        });
+
+        let rels_xml = read_part(&archive, "xl/worksheets/_rels/sheet1.xml.rels");
+        assert!(rels_xml.contains("Target=\"../tables/table1.xml\""));
+    }
+
+    #[test]
+    fn declares_table_parts() {
+        let archive = write_workbook(|workbook| {
+            for name in ["First", "Second"] {
+                let mut sheet = workbook.get_worksheet(String::from(name))?;
+                sheet.add_header_table(name, None)?;
+                sheet.write_row(vec![b"a"])?;
+                sheet.close()?;
+            }
+            Ok(())
+        });
+
+        let content_types_xml = read_part(&archive, "[Content_Types].xml");
+        for i in [1, 2] {
+            assert!(content_types_xml.contains(&format!(
+                "<Override PartName=\"/xl/tables/table{i}.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.table+xml\"/>"
+            )));
+
+            let rels_xml = read_part(&archive, &format!("xl/worksheets/_rels/sheet{i}.xml.rels"));
+            assert!(rels_xml.contains(&format!(
+                "<Relationship Id=\"rId1\" Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/table\" Target=\"../tables/table{i}.xml\"/>"
+            )));
+
+            let sheet_xml = read_part(&archive, &format!("xl/worksheets/sheet{i}.xml"));
+            assert!(sheet_xml.contains(
+                "<tableParts count=\"1\"><tablePart r:id=\"rId1\"/></tableParts></worksheet>"
+            ));
+        }
+
+        let table_xml = read_part(&archive, "xl/tables/table2.xml");
+        assert!(
+            table_xml.contains(" id=\"2\" name=\"Second\" displayName=\"Second\" ref=\"A1:A2\"")
+        );
+    }
+}
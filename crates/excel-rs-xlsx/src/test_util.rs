@@ -0,0 +1,23 @@
+use std::io::{Cursor, Read};
+
+use anyhow::Result;
+use zip::ZipArchive;
+
+use crate::workbook::WorkBook;
+
+pub(crate) type TestWorkBook = WorkBook<Cursor<Vec<u8>>>;
+
+// Builds a workbook in memory and returns the finished archive
+pub(crate) fn write_workbook(build: impl FnOnce(&mut TestWorkBook) -> Result<()>) -> Vec<u8> {
+    let mut workbook = WorkBook::new(Cursor::new(Vec::new()));
+    build(&mut workbook).unwrap();
+    workbook.finish().unwrap().into_inner()
+}
+
+pub(crate) fn read_part(archive: &[u8], name: &str) -> String {
+    let mut archive = ZipArchive::new(Cursor::new(archive)).unwrap();
+    let mut part = archive.by_name(name).unwrap();
+    let mut text = String::new();
+    part.read_to_string(&mut text).unwrap();
+    text
+}
@@ -0,0 +1,104 @@
+use std::io::Write;
+
+use anyhow::{bail, Result};
+
+use crate::cell::{cell_ref, MAX_ROWS};
+use crate::columns::MAX_COLUMNS;
+
+#[derive(Default)]
+pub(crate) struct SheetView {
+    // Number of frozen rows and columns
+    frozen: Option<(u32, u16)>,
+}
+
+impl SheetView {
+    pub fn freeze_panes(&mut self, row: u32, col: u16) -> Result<()> {
+        if row >= MAX_ROWS || col >= MAX_COLUMNS {
+            bail!("cannot freeze panes at row {row}, column {col}");
+        }
+
+        self.frozen = match (row, col) {
+            (0, 0) => None,
+            frozen => Some(frozen),
+        };
+
+        Ok(())
+    }
+
+    pub fn write_xml(&self, buf: &mut Vec<u8>) -> Result<()> {
+        let (row, col) = match self.frozen {
+            Some(frozen) => frozen,
+            None => return Ok(()),
+        };
+
+        let active_pane = match (row, col) {
+            (0, _) => "topRight",
+            (_, 0) => "bottomLeft",
+            _ => "bottomRight",
+        };
+
+        buf.write_all(b"<sheetViews><sheetView workbookViewId=\"0\"><pane")?;
+        if col > 0 {
+            write!(buf, " xSplit=\"{}\"", col)?;
+        }
+        if row > 0 {
+            write!(buf, " ySplit=\"{}\"", row)?;
+        }
+        write!(
+            buf,
+            " topLeftCell=\"{}\" activePane=\"{}\" state=\"frozen\"/><selection pane=\"{}\"/></sheetView></sheetViews>",
+            cell_ref(row, col),
+            active_pane,
+            active_pane
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SheetView;
+    use crate::test_util::{read_part, write_workbook};
+
+    fn view_xml(row: u32, col: u16) -> String {
+        let mut view = SheetView::default();
+        view.freeze_panes(row, col).unwrap();
+        let mut buf = Vec::new();
+        view.write_xml(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn picks_the_pane_from_the_split() {
+        assert_eq!(
+            view_xml(2, 3),
+            "<sheetViews><sheetView workbookViewId=\"0\"><pane xSplit=\"3\" ySplit=\"2\" topLeftCell=\"D3\" activePane=\"bottomRight\" state=\"frozen\"/><selection pane=\"bottomRight\"/></sheetView></sheetViews>"
+        );
+        assert_eq!(
+            view_xml(0, 1),
+            "<sheetViews><sheetView workbookViewId=\"0\"><pane xSplit=\"1\" topLeftCell=\"B1\" activePane=\"topRight\" state=\"frozen\"/><selection pane=\"topRight\"/></sheetView></sheetViews>"
+        );
+        assert_eq!(view_xml(0, 0), "");
+
+        let mut view = SheetView::default();
+        assert!(view.freeze_panes(1_048_576, 0).is_err());
+        assert!(view.freeze_panes(0, 16384).is_err());
+    }
+
+    #[test]
+    fn freezes_the_header_before_the_first_row() {
+        let archive = write_workbook(|workbook| {
+            let mut sheet = workbook.get_worksheet(String::from("Sheet"))?;
+            sheet.freeze_header()?;
+            sheet.write_row(vec![b"header"])?;
+            assert!(sheet.freeze_panes(2, 0).is_err());
+            sheet.close()
+        });
+
+        let sheet_xml = read_part(&archive, "xl/worksheets/sheet1.xml");
+        assert!(sheet_xml.contains(
+            "<sheetViews><sheetView workbookViewId=\"0\"><pane ySplit=\"1\" topLeftCell=\"A2\" activePane=\"bottomLeft\" state=\"frozen\"/><selection pane=\"bottomLeft\"/></sheetView></sheetViews>\n<sheetData>"
+        ));
+    }
+}
@@ -1,38 +1,291 @@
 use super::format::XlsxFormatter;
-use std::{io::Seek, io::Write};
-use anyhow::Result;
-use zip::ZipWriter;
+use std::io::{Seek, Write};
+use anyhow::{bail, Result};
 
+use super::shared_strings::SharedStrings;
 use super::sheet::Sheet;
+use super::sheet_builder::{SheetBuilder, SheetPart};
+use super::protection::WorkbookProtection;
+use super::styles::{Format, StyleId};
 use super::typed_sheet::TypedSheet;
+use super::workbook_options::WorkBookOptions;
+use super::zip_stream::ZipStream;
 
-pub struct WorkBook<W: Write + Seek> {
+// Excel refuses to open workbooks with sheet names that break these rules
+const MAX_SHEET_NAME_LEN: usize = 31;
+const INVALID_SHEET_NAME_CHARS: [char; 7] = ['[', ']', ':', '*', '?', '/', '\\'];
+
+pub const DEFAULT_SHARED_STRINGS_LIMIT: usize = 256 * 1024 * 1024;
+
+pub struct WorkBook<W: Write> {
     formatter: XlsxFormatter<W>,
-    num_of_sheets: u16,
+    sheet_names: Vec<String>,
+    // Ids of sheet builders whose parts have not been appended yet
+    pending_builders: Vec<u16>,
 }
 
 impl<W: Write + Seek> WorkBook<W> {
+    // The workbook is written front to back as sheets are filled, going back
+    // only to fill in each part's sizes once it is done
     pub fn new(writer: W) -> Self {
-        let zip_writer = ZipWriter::new(writer);
+        Self::build(ZipStream::seekable(writer), WorkBookOptions::default())
+    }
+
+    // Like new(), with control over how the archive is compressed
+    pub fn with_options(writer: W, options: &WorkBookOptions) -> Result<Self> {
+        options.validate()?;
+        Ok(Self::build(ZipStream::seekable(writer), *options))
+    }
+}
+
+impl<W: Write> WorkBook<W> {
+    // For writers that cannot seek, such as stdout or a socket. Each part's
+    // sizes follow it in a data descriptor, which costs a few bytes per part.
+    pub fn streaming(writer: W) -> Self {
+        Self::build(ZipStream::new(writer), WorkBookOptions::default())
+    }
 
+    // Like streaming(), with control over how the archive is compressed
+    pub fn streaming_with_options(writer: W, options: &WorkBookOptions) -> Result<Self> {
+        options.validate()?;
+        Ok(Self::build(ZipStream::new(writer), *options))
+    }
+
+    fn build(zip_writer: ZipStream<W>, options: WorkBookOptions) -> Self {
         WorkBook {
-            formatter: XlsxFormatter::new(zip_writer),
-            num_of_sheets: 0,
+            formatter: XlsxFormatter::new(zip_writer, options),
+            sheet_names: Vec::new(),
+            pending_builders: Vec::new(),
+        }
+    }
+
+    // Deduplicates text across every sheet written after this call. Once the
+    // table holds roughly `memory_limit` bytes, new strings are written inline.
+    pub fn enable_shared_strings(&mut self, memory_limit: usize) {
+        self.formatter.shared_strings = Some(SharedStrings::new(memory_limit));
+    }
+
+    // Writes a calcChain.xml listing every formula cell written after this
+    // call, and asks Excel to recalculate the whole workbook when it opens
+    pub fn recalculate_on_open(&mut self) {
+        self.formatter.calc_chain.get_or_insert_with(Vec::new);
+    }
+
+    // Names a formula, e.g. "'Sales'!$B$2:$B$100", for use in other formulas.
+    // A name scoped to a sheet only applies within it and must be defined
+    // once the sheet has been added. The built-in names _xlnm.Print_Area,
+    // _xlnm.Print_Titles and _xlnm._FilterDatabase need a scope.
+    pub fn define_name(&mut self, name: &str, formula: &str, scope: Option<&str>) -> Result<()> {
+        let scope = match scope {
+            Some(sheet_name) => match self.sheet_names.iter().position(|existing| existing == sheet_name) {
+                Some(index) => Some(index as u16),
+                None => bail!("there is no sheet named {sheet_name:?}"),
+            },
+            None => None,
+        };
+
+        let lowercase_name = name.to_lowercase();
+        if self
+            .formatter
+            .table_names
+            .iter()
+            .any(|existing| existing.to_lowercase() == lowercase_name)
+        {
+            bail!("name {name:?} is already used by a table");
+        }
+
+        self.formatter.defined_names.add(name, formula, scope)
+    }
+
+    // Stops users adding, removing, renaming, moving or unhiding sheets
+    pub fn protect_structure(&mut self, password: Option<&str>) -> Result<()> {
+        self.formatter.workbook_protection = Some(WorkbookProtection::new(password)?);
+        Ok(())
+    }
+
+    // Compresses sheets added after this call on `threads` threads, in
+    // independent chunks like pigz. One thread, the default, compresses as
+    // rows are written.
+    pub fn set_compression_threads(&mut self, threads: usize) -> Result<()> {
+        if threads == 0 {
+            bail!("compression needs at least one thread");
         }
+        self.formatter.zip_writer.set_threads(threads)?;
+        Ok(())
     }
 
-    pub fn get_worksheet(&mut self, name: String) -> Sheet<W> {
-        self.num_of_sheets += 1;
-        Sheet::new(name, self.num_of_sheets, &mut self.formatter.zip_writer)
+    pub fn add_format(&mut self, format: &Format) -> Result<StyleId> {
+        self.formatter.styles.add_format(format)
     }
 
-    pub fn get_typed_worksheet(&mut self, name: String) -> TypedSheet<W> {
-        self.num_of_sheets += 1;
-        TypedSheet::new(name, self.num_of_sheets, &mut self.formatter.zip_writer)
+    pub fn get_worksheet(&mut self, name: String) -> Result<Sheet<'_, W>> {
+        let id = self.register_sheet(&name)?;
+        Sheet::new(name, id, &mut self.formatter)
+    }
+
+    pub fn get_typed_worksheet(&mut self, name: String) -> Result<TypedSheet<'_, W>> {
+        let id = self.register_sheet(&name)?;
+        TypedSheet::new(name, id, &mut self.formatter)
+    }
+
+    // Reserves the next sheet for a builder that can be filled on another
+    // thread. The sheet keeps its place in the workbook, however late its
+    // part is appended.
+    //
+    // A builder holds its whole compressed sheet in memory until appended,
+    // and compresses on the thread filling it whatever set_compression_threads
+    // says. Its strings are always inline, even with shared strings on. It
+    // can't take tables, links, comments, charts, images or conditional
+    // formats, and its styles must come from the workbook's add_format.
+    pub fn sheet_builder(&mut self, name: String) -> Result<SheetBuilder> {
+        let id = self.register_sheet(&name)?;
+        let builder = SheetBuilder::new(
+            name,
+            id,
+            self.formatter.options,
+            self.formatter.calc_chain.is_some(),
+        )?;
+        self.pending_builders.push(id);
+        Ok(builder)
+    }
+
+    // Copies a finished builder's compressed parts into the workbook. The
+    // workbook is left as it was if the part can't be appended.
+    pub fn append_sheet(&mut self, part: SheetPart) -> Result<()> {
+        let position = self
+            .pending_builders
+            .iter()
+            .position(|id| *id == part.id);
+        let position = match position {
+            Some(position) if self.sheet_names[part.id as usize - 1] == part.name => position,
+            _ => bail!(
+                "sheet {:?} was not built for this workbook or was already appended",
+                part.name
+            ),
+        };
+
+        part.check_mergeable()?;
+        let formatter = part.formatter;
+        self.formatter
+            .defined_names
+            .check_append(&formatter.defined_names)?;
+        self.formatter.zip_writer.append(formatter.zip_writer)?;
+
+        self.formatter.defined_names.append(formatter.defined_names);
+        if let (Some(calc_chain), Some(part_calc_chain)) =
+            (&mut self.formatter.calc_chain, formatter.calc_chain)
+        {
+            calc_chain.extend(part_calc_chain);
+        }
+        for default_type in formatter.default_types {
+            self.formatter.add_default_type(default_type);
+        }
+        self.formatter.content_types.extend(formatter.content_types);
+
+        self.pending_builders.remove(position);
+        Ok(())
     }
-    
+
     pub fn finish(self) -> Result<W> {
-        let result = self.formatter.finish(self.num_of_sheets)?;
+        if let Some(id) = self.pending_builders.first() {
+            bail!(
+                "sheet {:?} was never appended",
+                self.sheet_names[*id as usize - 1]
+            );
+        }
+
+        let result = self.formatter.finish(&self.sheet_names)?;
         Ok(result)
     }
+
+    fn register_sheet(&mut self, name: &str) -> Result<u16> {
+        if name.is_empty() {
+            bail!("sheet name cannot be empty");
+        }
+
+        if name.chars().count() > MAX_SHEET_NAME_LEN {
+            bail!("sheet name {name:?} is longer than {MAX_SHEET_NAME_LEN} characters");
+        }
+
+        if let Some(c) = name.chars().find(|c| INVALID_SHEET_NAME_CHARS.contains(c)) {
+            bail!("sheet name {name:?} contains invalid character {c:?}");
+        }
+
+        if name.starts_with('\'') || name.ends_with('\'') {
+            bail!("sheet name {name:?} cannot start or end with an apostrophe");
+        }
+
+        let lowercase_name = name.to_lowercase();
+        if self
+            .sheet_names
+            .iter()
+            .any(|existing| existing.to_lowercase() == lowercase_name)
+        {
+            bail!("sheet name {name:?} is already in use");
+        }
+
+        if self.sheet_names.len() >= u16::MAX as usize {
+            bail!("workbook cannot hold more than {} sheets", u16::MAX);
+        }
+
+        self.sheet_names.push(name.to_owned());
+        Ok(self.sheet_names.len() as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::WorkBook;
+    use crate::test_util::{read_part, write_workbook};
+
+    #[test]
+    fn rejects_names_excel_refuses() {
+        let mut workbook = WorkBook::new(Cursor::new(Vec::new()));
+        workbook.get_worksheet(String::from("Sales")).unwrap();
+
+        for name in [
+            "",
+            "A name that is 32 characters lon",
+            "Q1/Q2",
+            "What?",
+            "[Draft]",
+            "'Quoted",
+            "Quoted'",
+            "SALES",
+        ] {
+            assert!(
+                workbook.get_worksheet(String::from(name)).is_err(),
+                "{name:?}"
+            );
+        }
+
+        workbook
+            .get_worksheet(String::from("A name that's 31 characters lon"))
+            .unwrap();
+        workbook.get_worksheet(String::from("It's Sales")).unwrap();
+    }
+
+    #[test]
+    fn escapes_names_in_workbook_and_app_xml() {
+        let archive = write_workbook(|workbook| {
+            workbook.get_worksheet(String::from("Plain"))?.close()?;
+            workbook
+                .get_worksheet(String::from("R&D <\"North\">"))?
+                .close()
+        });
+
+        let workbook_xml = read_part(&archive, "xl/workbook.xml");
+        assert!(workbook_xml.contains("<sheet name=\"Plain\" sheetId=\"1\" r:id=\"rId3\"/>"));
+        assert!(workbook_xml.contains(
+            "<sheet name=\"R&amp;D &lt;&quot;North&quot;&gt;\" sheetId=\"2\" r:id=\"rId4\"/>"
+        ));
+
+        let app_xml = read_part(&archive, "docProps/app.xml");
+        assert!(app_xml.contains("<vt:i4>2</vt:i4>"));
+        assert!(app_xml.contains("<vt:vector size=\"2\" baseType=\"lpstr\">"));
+        assert!(app_xml.contains("<vt:lpstr>Plain</vt:lpstr>"));
+        assert!(app_xml.contains("<vt:lpstr>R&amp;D &lt;&quot;North&quot;&gt;</vt:lpstr>"));
+    }
 }
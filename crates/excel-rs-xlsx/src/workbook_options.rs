@@ -0,0 +1,225 @@
+use anyhow::{bail, Result};
+
+use crate::zip_stream::PartOptions;
+
+const MAX_COMPRESSION_LEVEL: u32 = 9;
+// Worksheets favour speed, as they make up nearly all of a large workbook
+const DEFAULT_SHEET_LEVEL: u32 = 1;
+pub(crate) const DEFAULT_LEVEL: u32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionMethod {
+    // No compression, for the fastest possible writes
+    Stored,
+    Deflated,
+    // Deflate at its smallest, found by the Zopfli algorithm. It is many
+    // times slower than Deflated at level 9, ignores the compression level
+    // and compresses on a single thread, so it suits workbooks that are
+    // written once and downloaded often.
+    #[cfg(feature = "zopfli")]
+    Zopfli,
+}
+
+impl CompressionMethod {
+    pub(crate) fn code(&self) -> u16 {
+        match self {
+            CompressionMethod::Stored => 0,
+            CompressionMethod::Deflated => 8,
+            #[cfg(feature = "zopfli")]
+            CompressionMethod::Zopfli => 8,
+        }
+    }
+}
+
+// Which parts carry zip64 sizes. They are written before the part's size is
+// known, so a part without them cannot grow past 4 GiB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zip64Policy {
+    // Only worksheets, the parts that can outgrow 4 GiB
+    Worksheets,
+    // Saves 20 bytes per sheet and suits older unzip tools, but fails on
+    // sheets of 4 GiB or more
+    Never,
+}
+
+// How WorkBook::with_options and streaming_with_options compress the
+// archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkBookOptions {
+    method: CompressionMethod,
+    // Applies to every part when set. Otherwise worksheets are compressed at
+    // level 1 and everything else at level 6.
+    level: Option<u32>,
+    zip64: Zip64Policy,
+}
+
+impl Default for WorkBookOptions {
+    fn default() -> Self {
+        WorkBookOptions {
+            method: CompressionMethod::Deflated,
+            level: None,
+            zip64: Zip64Policy::Worksheets,
+        }
+    }
+}
+
+impl WorkBookOptions {
+    pub fn new() -> Self {
+        WorkBookOptions::default()
+    }
+
+    pub fn compression_method(mut self, method: CompressionMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    // From 0 (none) to 9 (smallest). Ignored when storing parts.
+    pub fn compression_level(mut self, level: u32) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    pub fn zip64(mut self, policy: Zip64Policy) -> Self {
+        self.zip64 = policy;
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Some(level) = self.level {
+            if level > MAX_COMPRESSION_LEVEL {
+                bail!("compression level {level} must be between 0 and {MAX_COMPRESSION_LEVEL}");
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn sheet_part_options(&self) -> PartOptions {
+        PartOptions::default()
+            .compression_method(self.method)
+            .compression_level(self.level.unwrap_or(DEFAULT_SHEET_LEVEL))
+            .large_file(self.zip64 == Zip64Policy::Worksheets)
+            .parallel(true)
+    }
+
+    pub(crate) fn part_options(&self) -> PartOptions {
+        PartOptions::default()
+            .compression_method(self.method)
+            .compression_level(self.level.unwrap_or(DEFAULT_LEVEL))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use zip::ZipArchive;
+
+    use super::{CompressionMethod, WorkBookOptions, Zip64Policy};
+    use crate::workbook::WorkBook;
+
+    const SHEET: &str = "xl/worksheets/sheet1.xml";
+
+    fn write_workbook(options: &WorkBookOptions, streaming: bool, rows: usize) -> Vec<u8> {
+        let writer = Cursor::new(Vec::new());
+        let mut workbook = if streaming {
+            WorkBook::streaming_with_options(writer, options).unwrap()
+        } else {
+            WorkBook::with_options(writer, options).unwrap()
+        };
+        let mut sheet = workbook.get_worksheet(String::from("Sheet")).unwrap();
+        for i in 0..rows {
+            let row = format!("row {i}");
+            sheet.write_row(vec![row.as_bytes(), b"text"]).unwrap();
+        }
+        sheet.close().unwrap();
+        workbook.finish().unwrap().into_inner()
+    }
+
+    fn read_sheet(archive: &[u8]) -> String {
+        let mut archive = ZipArchive::new(Cursor::new(archive)).unwrap();
+        let mut part = archive.by_name(SHEET).unwrap();
+        let mut text = String::new();
+        part.read_to_string(&mut text).unwrap();
+        text
+    }
+
+    // Local headers come before the central directory, so the first copy of
+    // the name is the one in the sheet's local header
+    fn local_extra_len(archive: &[u8], name: &str) -> u16 {
+        let name_pos = archive
+            .windows(name.len())
+            .position(|window| window == name.as_bytes())
+            .unwrap();
+        u16::from_le_bytes([archive[name_pos - 2], archive[name_pos - 1]])
+    }
+
+    #[test]
+    fn compresses_every_part_with_the_chosen_method() {
+        for (method, code) in [
+            (CompressionMethod::Stored, zip::CompressionMethod::Stored),
+            (
+                CompressionMethod::Deflated,
+                zip::CompressionMethod::Deflated,
+            ),
+        ] {
+            for streaming in [false, true] {
+                let options = WorkBookOptions::new().compression_method(method);
+                let archive = write_workbook(&options, streaming, 2000);
+
+                let mut zip = ZipArchive::new(Cursor::new(&archive)).unwrap();
+                for i in 0..zip.len() {
+                    let mut part = zip.by_index(i).unwrap();
+                    assert_eq!(part.compression(), code, "{}", part.name());
+                    std::io::copy(&mut part, &mut std::io::sink()).unwrap();
+                }
+                assert!(read_sheet(&archive).contains("<t>row 1999</t>"));
+            }
+        }
+    }
+
+    #[test]
+    fn applies_one_level_to_every_part() {
+        let fastest = write_workbook(&WorkBookOptions::new().compression_level(0), false, 2000);
+        let smallest = write_workbook(&WorkBookOptions::new().compression_level(9), false, 2000);
+        assert!(smallest.len() < fastest.len());
+        assert_eq!(read_sheet(&fastest), read_sheet(&smallest));
+    }
+
+    #[test]
+    fn rejects_levels_above_9() {
+        let options = WorkBookOptions::new().compression_level(10);
+        assert!(WorkBook::with_options(Cursor::new(Vec::new()), &options).is_err());
+        assert!(WorkBook::streaming_with_options(Cursor::new(Vec::new()), &options).is_err());
+    }
+
+    #[test]
+    fn leaves_zip64_fields_off_sheets_when_never() {
+        let worksheets = write_workbook(&WorkBookOptions::new(), false, 2000);
+        assert_eq!(local_extra_len(&worksheets, SHEET), 20);
+        assert_eq!(local_extra_len(&worksheets, "xl/workbook.xml"), 0);
+
+        let never = WorkBookOptions::new().zip64(Zip64Policy::Never);
+        let archive = write_workbook(&never, false, 2000);
+        assert_eq!(local_extra_len(&archive, SHEET), 0);
+        assert_eq!(read_sheet(&archive), read_sheet(&worksheets));
+    }
+
+    #[cfg(feature = "zopfli")]
+    #[test]
+    fn zopfli_inflates_and_beats_level_9() {
+        let level_9 = write_workbook(&WorkBookOptions::new().compression_level(9), false, 200);
+        for streaming in [false, true] {
+            let options = WorkBookOptions::new().compression_method(CompressionMethod::Zopfli);
+            let archive = write_workbook(&options, streaming, 200);
+            assert!(archive.len() <= level_9.len());
+
+            let mut zip = ZipArchive::new(Cursor::new(&archive)).unwrap();
+            for i in 0..zip.len() {
+                let mut part = zip.by_index(i).unwrap();
+                assert_eq!(part.compression(), zip::CompressionMethod::Deflated);
+                std::io::copy(&mut part, &mut std::io::sink()).unwrap();
+            }
+            assert_eq!(read_sheet(&archive), read_sheet(&level_9));
+        }
+    }
+}
@@ -0,0 +1,845 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+use crc32fast::Hasher;
+use flate2::{write::DeflateEncoder, Compression};
+
+use crate::parallel_deflate::{DeflatePool, ParallelDeflate};
+use crate::workbook_options::{CompressionMethod, DEFAULT_LEVEL};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const ZIP64_END_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const END_SIGNATURE: u32 = 0x06054b50;
+
+// The CRC and sizes follow the data in a data descriptor
+const DATA_DESCRIPTOR_FLAG: u16 = 1 << 3;
+const UTF8_NAMES_FLAG: u16 = 1 << 11;
+const VERSION: u16 = 20;
+const ZIP64_VERSION: u16 = 45;
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+// Sizes and offsets from here on only fit in zip64 fields, as 0xFFFFFFFF
+// marks a value that has been moved to one
+const ZIP64_LIMIT: u64 = u32::MAX as u64;
+const ZIP64_ENTRY_LIMIT: usize = u16::MAX as usize;
+// 1980-01-01 00:00, the earliest date a zip entry can carry
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = 1 << 5 | 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PartOptions {
+    method: CompressionMethod,
+    level: u32,
+    // Whether the part may grow past 4 GiB. The sizes are only known once the
+    // part is written, so this has to be decided up front.
+    large_file: bool,
+    // Whether to compress on the stream's thread pool, if it has one
+    parallel: bool,
+}
+
+impl Default for PartOptions {
+    fn default() -> Self {
+        PartOptions {
+            method: CompressionMethod::Deflated,
+            level: DEFAULT_LEVEL,
+            large_file: false,
+            parallel: false,
+        }
+    }
+}
+
+impl PartOptions {
+    pub fn compression_method(mut self, method: CompressionMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn compression_level(mut self, level: u32) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn large_file(mut self, large_file: bool) -> Self {
+        self.large_file = large_file;
+        self
+    }
+
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+}
+
+// Each compresses into a buffer that is drained into the stream after each
+// write
+enum Encoder {
+    Inline(DeflateEncoder<Vec<u8>>),
+    Parallel(ParallelDeflate),
+    // Zopfli's encoder doesn't share its output until it is finished, so
+    // the buffer it drains into stays empty
+    #[cfg(feature = "zopfli")]
+    Zopfli(io::BufWriter<zopfli::DeflateEncoder<Vec<u8>>>, Vec<u8>),
+}
+
+impl Encoder {
+    fn new(method: CompressionMethod, level: u32, pool: Option<&DeflatePool>) -> Option<Self> {
+        match method {
+            CompressionMethod::Stored => None,
+            CompressionMethod::Deflated => Some(match pool {
+                Some(pool) => Encoder::Parallel(ParallelDeflate::new(pool, level)),
+                None => Encoder::Inline(DeflateEncoder::new(
+                    Vec::with_capacity(64 * 1024),
+                    Compression::new(level),
+                )),
+            }),
+            #[cfg(feature = "zopfli")]
+            CompressionMethod::Zopfli => Some(Encoder::Zopfli(
+                zopfli::DeflateEncoder::new_buffered(
+                    zopfli::Options::default(),
+                    zopfli::BlockType::Dynamic,
+                    Vec::new(),
+                ),
+                Vec::new(),
+            )),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            Encoder::Inline(encoder) => encoder.write_all(buf),
+            Encoder::Parallel(encoder) => encoder.write_all(buf),
+            #[cfg(feature = "zopfli")]
+            Encoder::Zopfli(encoder, _) => encoder.write_all(buf),
+        }
+    }
+
+    fn get_mut(&mut self) -> &mut Vec<u8> {
+        match self {
+            Encoder::Inline(encoder) => encoder.get_mut(),
+            Encoder::Parallel(encoder) => encoder.get_mut(),
+            #[cfg(feature = "zopfli")]
+            Encoder::Zopfli(_, drained) => drained,
+        }
+    }
+
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Inline(encoder) => encoder.finish(),
+            Encoder::Parallel(encoder) => encoder.finish(),
+            #[cfg(feature = "zopfli")]
+            Encoder::Zopfli(encoder, _) => encoder
+                .into_inner()
+                .map_err(|err| err.into_error())?
+                .finish(),
+        }
+    }
+}
+
+struct Entry {
+    name: String,
+    method: CompressionMethod,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    header_offset: u64,
+    // Whether the local header carries zip64 sizes, which the central header
+    // then has to carry as well
+    large_file: bool,
+    data_descriptor: bool,
+}
+
+impl Entry {
+    fn flags(&self) -> u16 {
+        if self.data_descriptor {
+            DATA_DESCRIPTOR_FLAG | UTF8_NAMES_FLAG
+        } else {
+            UTF8_NAMES_FLAG
+        }
+    }
+
+    fn version(&self) -> u16 {
+        if self.large_file {
+            ZIP64_VERSION
+        } else {
+            VERSION
+        }
+    }
+
+    // Sizes in a large file's header are always moved to its zip64 field,
+    // so the local and central headers agree on the layout
+    fn local_header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(50 + self.name.len());
+        put_u32(&mut header, LOCAL_HEADER_SIGNATURE);
+        put_u16(&mut header, self.version());
+        put_u16(&mut header, self.flags());
+        put_u16(&mut header, self.method.code());
+        put_u16(&mut header, DOS_TIME);
+        put_u16(&mut header, DOS_DATE);
+        put_u32(&mut header, self.crc);
+        if self.large_file {
+            put_u32(&mut header, u32::MAX);
+            put_u32(&mut header, u32::MAX);
+        } else {
+            put_u32(&mut header, self.compressed_size as u32);
+            put_u32(&mut header, self.size as u32);
+        }
+        put_u16(&mut header, self.name.len() as u16);
+        if self.large_file {
+            put_u16(&mut header, 20);
+            header.extend_from_slice(self.name.as_bytes());
+            put_u16(&mut header, ZIP64_EXTRA_ID);
+            put_u16(&mut header, 16);
+            put_u64(&mut header, self.size);
+            put_u64(&mut header, self.compressed_size);
+        } else {
+            put_u16(&mut header, 0);
+            header.extend_from_slice(self.name.as_bytes());
+        }
+        header
+    }
+}
+
+struct OpenEntry {
+    entry: Entry,
+    data_start: u64,
+    hasher: Hasher,
+}
+
+// Rewrites bytes already written to the writer, the given distance back from
+// its current position, and returns to where it was
+type Patch<W> = fn(&mut W, u64, &[u8]) -> io::Result<()>;
+
+// Writes a zip archive front to back. On a seekable writer, each local header
+// is rewritten with its part's CRC and sizes once the part is done. Otherwise
+// nothing is ever rewritten, so any writer will do, including stdout or a
+// socket: each part is followed by a data descriptor holding its CRC and
+// sizes instead. The central directory is written by finish().
+pub(crate) struct ZipStream<W: Write> {
+    inner: W,
+    // Bytes written to `inner` so far
+    position: u64,
+    patch: Option<Patch<W>>,
+    encoder: Option<Encoder>,
+    pool: Option<DeflatePool>,
+    current: Option<OpenEntry>,
+    entries: Vec<Entry>,
+}
+
+impl<W: Write + Seek> ZipStream<W> {
+    pub fn seekable(inner: W) -> Self {
+        Self::build(inner, Some(patch_seekable::<W>))
+    }
+}
+
+impl ZipStream<Vec<u8>> {
+    // Parts kept in memory can always have their headers rewritten
+    pub fn in_memory() -> Self {
+        Self::build(Vec::new(), Some(patch_vec))
+    }
+}
+
+impl<W: Write> ZipStream<W> {
+    // Streams to a writer that cannot seek, using data descriptors
+    pub fn new(inner: W) -> Self {
+        Self::build(inner, None)
+    }
+
+    fn build(inner: W, patch: Option<Patch<W>>) -> Self {
+        ZipStream {
+            inner,
+            position: 0,
+            patch,
+            encoder: None,
+            pool: None,
+            current: None,
+            entries: Vec::new(),
+        }
+    }
+
+    // Finishes the current part and starts the next. Everything written
+    // until the next call goes into `name`.
+    pub fn start_file<S: Into<String>>(&mut self, name: S, options: PartOptions) -> io::Result<()> {
+        self.finish_part()?;
+
+        let name = name.into();
+        if self.entries.iter().any(|entry| entry.name == name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("part {name} has already been written"),
+            ));
+        }
+
+        // The CRC and sizes are zero until the part is done. With a data
+        // descriptor, a zip64 field in the local header tells readers that
+        // the descriptor holds 8 byte sizes.
+        let entry = Entry {
+            name,
+            method: options.method,
+            crc: 0,
+            compressed_size: 0,
+            size: 0,
+            header_offset: self.position,
+            large_file: options.large_file,
+            data_descriptor: self.patch.is_none(),
+        };
+        self.write_raw(&entry.local_header())?;
+
+        let pool = self.pool.as_ref().filter(|_| options.parallel);
+        self.encoder = Encoder::new(options.method, options.level, pool);
+
+        self.current = Some(OpenEntry {
+            entry,
+            data_start: self.position,
+            hasher: Hasher::new(),
+        });
+
+        Ok(())
+    }
+
+    // Parts started with the parallel option after this call are compressed
+    // on `threads` threads. One thread compresses inline.
+    pub fn set_threads(&mut self, threads: usize) -> io::Result<()> {
+        self.pool = if threads > 1 {
+            Some(DeflatePool::new(threads)?)
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    // Copies every part of a stream written to memory, such as a sheet built
+    // on another thread. Local headers hold no offsets, so the parts are
+    // copied byte for byte and only the central directory is adjusted. Each
+    // part keeps its own headers, so either kind of stream can take them.
+    pub fn append(&mut self, mut other: ZipStream<Vec<u8>>) -> io::Result<()> {
+        self.finish_part()?;
+        other.finish_part()?;
+
+        if let Some(entry) = other.entries.iter().find(|entry| {
+            self.entries
+                .iter()
+                .any(|existing| existing.name == entry.name)
+        }) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("part {} has already been written", entry.name),
+            ));
+        }
+
+        let base = self.position;
+        self.write_raw(&other.inner)?;
+        for mut entry in other.entries {
+            entry.header_offset += base;
+            self.entries.push(entry);
+        }
+
+        Ok(())
+    }
+
+    // Writes the central directory and returns the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finish_part()?;
+
+        let directory_start = self.position;
+        let entries = std::mem::take(&mut self.entries);
+        for entry in &entries {
+            self.write_central_header(entry)?;
+        }
+        let directory_size = self.position - directory_start;
+        self.write_end_of_directory(entries.len(), directory_start, directory_size)?;
+
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn finish_part(&mut self) -> io::Result<()> {
+        let Some(part) = self.current.take() else {
+            return Ok(());
+        };
+
+        if let Some(encoder) = self.encoder.take() {
+            let compressed = encoder.finish()?;
+            self.write_raw(&compressed)?;
+        }
+
+        let mut entry = part.entry;
+        entry.crc = part.hasher.finalize();
+        entry.compressed_size = self.position - part.data_start;
+        if !entry.large_file && (needs_zip64(entry.size) || needs_zip64(entry.compressed_size)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("part {} is larger than 4 GiB", entry.name),
+            ));
+        }
+
+        match self.patch {
+            Some(patch) => {
+                let header = entry.local_header();
+                patch(
+                    &mut self.inner,
+                    self.position - entry.header_offset,
+                    &header,
+                )?;
+            }
+            None => {
+                let mut descriptor = Vec::with_capacity(24);
+                put_u32(&mut descriptor, DATA_DESCRIPTOR_SIGNATURE);
+                put_u32(&mut descriptor, entry.crc);
+                if entry.large_file {
+                    put_u64(&mut descriptor, entry.compressed_size);
+                    put_u64(&mut descriptor, entry.size);
+                } else {
+                    put_u32(&mut descriptor, entry.compressed_size as u32);
+                    put_u32(&mut descriptor, entry.size as u32);
+                }
+                self.write_raw(&descriptor)?;
+            }
+        }
+
+        self.entries.push(entry);
+
+        Ok(())
+    }
+
+    // Values too large for their field are replaced with 0xFFFFFFFF and
+    // moved to a zip64 field, in the order the spec lists them. A large
+    // file's sizes are always moved, as they are in its local header.
+    fn write_central_header(&mut self, entry: &Entry) -> io::Result<()> {
+        let large_sizes = entry.large_file;
+        let large_offset = needs_zip64(entry.header_offset);
+
+        let mut extra = Vec::with_capacity(28);
+        if large_sizes || large_offset {
+            put_u16(&mut extra, ZIP64_EXTRA_ID);
+            put_u16(&mut extra, 0);
+            if large_sizes {
+                put_u64(&mut extra, entry.size);
+                put_u64(&mut extra, entry.compressed_size);
+            }
+            if large_offset {
+                put_u64(&mut extra, entry.header_offset);
+            }
+            let data_len = (extra.len() - 4) as u16;
+            extra[2..4].copy_from_slice(&data_len.to_le_bytes());
+        }
+
+        let version = if large_offset {
+            ZIP64_VERSION
+        } else {
+            entry.version()
+        };
+
+        let mut header = Vec::with_capacity(46 + entry.name.len() + extra.len());
+        put_u32(&mut header, CENTRAL_HEADER_SIGNATURE);
+        // Made by and needed to extract
+        put_u16(&mut header, version);
+        put_u16(&mut header, version);
+        put_u16(&mut header, entry.flags());
+        put_u16(&mut header, entry.method.code());
+        put_u16(&mut header, DOS_TIME);
+        put_u16(&mut header, DOS_DATE);
+        put_u32(&mut header, entry.crc);
+        if large_sizes {
+            put_u32(&mut header, u32::MAX);
+            put_u32(&mut header, u32::MAX);
+        } else {
+            put_u32(&mut header, entry.compressed_size as u32);
+            put_u32(&mut header, entry.size as u32);
+        }
+        put_u16(&mut header, entry.name.len() as u16);
+        put_u16(&mut header, extra.len() as u16);
+        // Comment length, disk number and file attributes
+        put_u16(&mut header, 0);
+        put_u16(&mut header, 0);
+        put_u16(&mut header, 0);
+        put_u32(&mut header, 0);
+        if large_offset {
+            put_u32(&mut header, u32::MAX);
+        } else {
+            put_u32(&mut header, entry.header_offset as u32);
+        }
+        header.extend_from_slice(entry.name.as_bytes());
+        header.extend_from_slice(&extra);
+
+        self.write_raw(&header)
+    }
+
+    fn write_end_of_directory(
+        &mut self,
+        entry_count: usize,
+        directory_start: u64,
+        directory_size: u64,
+    ) -> io::Result<()> {
+        let zip64 = entry_count >= ZIP64_ENTRY_LIMIT
+            || needs_zip64(directory_start)
+            || needs_zip64(directory_size);
+
+        let mut end = Vec::with_capacity(98);
+        if zip64 {
+            let zip64_end_offset = self.position;
+            put_u32(&mut end, ZIP64_END_SIGNATURE);
+            // Size of the rest of the record
+            put_u64(&mut end, 44);
+            put_u16(&mut end, ZIP64_VERSION);
+            put_u16(&mut end, ZIP64_VERSION);
+            put_u32(&mut end, 0);
+            put_u32(&mut end, 0);
+            put_u64(&mut end, entry_count as u64);
+            put_u64(&mut end, entry_count as u64);
+            put_u64(&mut end, directory_size);
+            put_u64(&mut end, directory_start);
+
+            put_u32(&mut end, ZIP64_LOCATOR_SIGNATURE);
+            put_u32(&mut end, 0);
+            put_u64(&mut end, zip64_end_offset);
+            put_u32(&mut end, 1);
+        }
+
+        put_u32(&mut end, END_SIGNATURE);
+        put_u16(&mut end, 0);
+        put_u16(&mut end, 0);
+        let short_count = entry_count.min(ZIP64_ENTRY_LIMIT) as u16;
+        put_u16(&mut end, short_count);
+        put_u16(&mut end, short_count);
+        put_u32(&mut end, directory_size.min(ZIP64_LIMIT) as u32);
+        put_u32(&mut end, directory_start.min(ZIP64_LIMIT) as u32);
+        // Comment length
+        put_u16(&mut end, 0);
+
+        self.write_raw(&end)
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.inner.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ZipStream<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(part) = &mut self.current else {
+            return Err(io::Error::other("no part has been started"));
+        };
+
+        part.hasher.update(buf);
+        part.entry.size += buf.len() as u64;
+
+        match &mut self.encoder {
+            Some(encoder) => {
+                encoder.write_all(buf)?;
+                let compressed = encoder.get_mut();
+                self.inner.write_all(compressed)?;
+                self.position += compressed.len() as u64;
+                compressed.clear();
+            }
+            None => {
+                self.inner.write_all(buf)?;
+                self.position += buf.len() as u64;
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn needs_zip64(value: u64) -> bool {
+    value >= ZIP64_LIMIT
+}
+
+fn patch_seekable<W: Write + Seek>(writer: &mut W, distance: u64, bytes: &[u8]) -> io::Result<()> {
+    writer.seek(SeekFrom::Current(-(distance as i64)))?;
+    writer.write_all(bytes)?;
+    writer.seek(SeekFrom::Current(distance as i64 - bytes.len() as i64))?;
+    Ok(())
+}
+
+// Takes the Vec itself to fit Patch<Vec<u8>>
+#[allow(clippy::ptr_arg)]
+fn patch_vec(buf: &mut Vec<u8>, distance: u64, bytes: &[u8]) -> io::Result<()> {
+    let start = buf.len() - distance as usize;
+    buf[start..start + bytes.len()].copy_from_slice(bytes);
+    Ok(())
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use zip::ZipArchive;
+
+    use super::*;
+
+    fn parts() -> Vec<(&'static str, PartOptions, Vec<u8>)> {
+        let text = b"<row r=\"1\"><c r=\"A1\"/></row>".repeat(2000);
+        vec![
+            ("deflated.xml", PartOptions::default(), text.clone()),
+            (
+                "stored.xml",
+                PartOptions::default().compression_method(CompressionMethod::Stored),
+                text.clone(),
+            ),
+            (
+                "large.xml",
+                PartOptions::default().large_file(true),
+                text.clone(),
+            ),
+            (
+                "large-stored.xml",
+                PartOptions::default()
+                    .compression_method(CompressionMethod::Stored)
+                    .large_file(true),
+                text,
+            ),
+            ("empty.xml", PartOptions::default(), Vec::new()),
+        ]
+    }
+
+    fn write_parts<W: Write>(stream: &mut ZipStream<W>) {
+        for (name, options, data) in parts() {
+            stream.start_file(name, options).unwrap();
+            stream.write_all(&data).unwrap();
+        }
+    }
+
+    fn read_parts<R: Read + Seek>(reader: R) -> Vec<(String, Vec<u8>)> {
+        let mut archive = ZipArchive::new(reader).unwrap();
+        (0..archive.len())
+            .map(|i| {
+                let mut part = archive.by_index(i).unwrap();
+                let mut data = Vec::new();
+                part.read_to_end(&mut data).unwrap();
+                (part.name().to_string(), data)
+            })
+            .collect()
+    }
+
+    fn expected_parts() -> Vec<(String, Vec<u8>)> {
+        parts()
+            .into_iter()
+            .map(|(name, _, data)| (name.to_string(), data))
+            .collect()
+    }
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    // Checks that each central header describes its local header the same
+    // way, and returns the flags of each part. Only for archives without
+    // zip64 offsets.
+    fn check_headers(archive: &[u8]) -> Vec<u16> {
+        let end = archive.len() - 22;
+        assert_eq!(u32_at(archive, end), END_SIGNATURE);
+        let count = u16_at(archive, end + 10) as usize;
+        let mut central = u32_at(archive, end + 16) as usize;
+
+        let mut flags = Vec::new();
+        for _ in 0..count {
+            assert_eq!(u32_at(archive, central), CENTRAL_HEADER_SIGNATURE);
+            let name_len = u16_at(archive, central + 28) as usize;
+            let extra_len = u16_at(archive, central + 30) as usize;
+            let local = u32_at(archive, central + 42) as usize;
+            assert_eq!(u32_at(archive, local), LOCAL_HEADER_SIGNATURE);
+
+            // Version, flags and method, then the CRC and sizes
+            assert_eq!(
+                archive[local + 4..local + 10],
+                archive[central + 6..central + 12]
+            );
+            let part_flags = u16_at(archive, local + 6);
+            if part_flags & DATA_DESCRIPTOR_FLAG == 0 {
+                assert_eq!(
+                    archive[local + 14..local + 26],
+                    archive[central + 16..central + 28]
+                );
+            }
+
+            let name = &archive[central + 46..central + 46 + name_len];
+            assert_eq!(&archive[local + 30..local + 30 + name_len], name);
+
+            // A zip64 field on one side only means the two disagree on
+            // where the sizes are
+            let local_extra_len = u16_at(archive, local + 28) as usize;
+            assert_eq!(local_extra_len == 0, extra_len == 0);
+            if extra_len > 0 && part_flags & DATA_DESCRIPTOR_FLAG == 0 {
+                let local_extra = local + 30 + name_len;
+                let central_extra = central + 46 + name_len;
+                assert_eq!(
+                    archive[local_extra..local_extra + 20],
+                    archive[central_extra..central_extra + 20]
+                );
+            }
+
+            flags.push(part_flags);
+            central += 46 + name_len + extra_len;
+        }
+        flags
+    }
+
+    #[test]
+    fn streams_parts_with_data_descriptors() {
+        let mut stream = ZipStream::new(Vec::new());
+        write_parts(&mut stream);
+        let archive = stream.finish().unwrap();
+
+        let flags = check_headers(&archive);
+        assert!(flags.iter().all(|flags| flags & DATA_DESCRIPTOR_FLAG != 0));
+        assert_eq!(read_parts(Cursor::new(archive)), expected_parts());
+    }
+
+    #[test]
+    fn writes_sizes_into_local_headers_when_seekable() {
+        let mut stream = ZipStream::seekable(Cursor::new(Vec::new()));
+        write_parts(&mut stream);
+        let archive = stream.finish().unwrap().into_inner();
+
+        let flags = check_headers(&archive);
+        assert!(flags.iter().all(|flags| flags & DATA_DESCRIPTOR_FLAG == 0));
+        assert_eq!(read_parts(Cursor::new(archive)), expected_parts());
+    }
+
+    #[test]
+    fn compresses_parallel_parts_on_threads() {
+        let mut stream = ZipStream::new(Vec::new());
+        stream.set_threads(3).unwrap();
+        // Three chunks
+        let data: Vec<u8> = (0..3_000_000u32).map(|i| (i % 251) as u8).collect();
+        let options = PartOptions::default().parallel(true).large_file(true);
+        stream.start_file("parallel.xml", options).unwrap();
+        stream.write_all(&data).unwrap();
+        stream
+            .start_file("inline.xml", PartOptions::default())
+            .unwrap();
+        stream.write_all(b"inline").unwrap();
+        let archive = stream.finish().unwrap();
+
+        check_headers(&archive);
+        assert_eq!(
+            read_parts(Cursor::new(archive)),
+            [
+                (String::from("parallel.xml"), data),
+                (String::from("inline.xml"), b"inline".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn appends_parts_written_in_memory() {
+        let mut first = ZipStream::in_memory();
+        first.start_file("b.xml", PartOptions::default()).unwrap();
+        first.write_all(b"second").unwrap();
+        let mut second = ZipStream::in_memory();
+        second
+            .start_file(
+                "c.xml",
+                PartOptions::default().compression_method(CompressionMethod::Stored),
+            )
+            .unwrap();
+        second.write_all(b"third").unwrap();
+
+        let mut stream = ZipStream::new(Vec::new());
+        stream.start_file("a.xml", PartOptions::default()).unwrap();
+        stream.write_all(b"first").unwrap();
+        stream.append(first).unwrap();
+        stream.append(second).unwrap();
+        stream.start_file("d.xml", PartOptions::default()).unwrap();
+        stream.write_all(b"fourth").unwrap();
+        let archive = stream.finish().unwrap();
+
+        check_headers(&archive);
+        let names_and_data: Vec<(String, Vec<u8>)> = ["first", "second", "third", "fourth"]
+            .iter()
+            .zip(["a.xml", "b.xml", "c.xml", "d.xml"])
+            .map(|(data, name)| (name.to_string(), data.as_bytes().to_vec()))
+            .collect();
+        assert_eq!(read_parts(Cursor::new(archive)), names_and_data);
+    }
+
+    #[test]
+    fn rejects_duplicate_parts() {
+        let mut stream = ZipStream::new(Vec::new());
+        stream.start_file("a.xml", PartOptions::default()).unwrap();
+        assert!(stream.start_file("a.xml", PartOptions::default()).is_err());
+
+        let mut other = ZipStream::in_memory();
+        other.start_file("a.xml", PartOptions::default()).unwrap();
+        assert!(stream.append(other).is_err());
+    }
+
+    // An archive that starts `gap` bytes of zeros in, as if a sparse file
+    struct Sparse {
+        gap: u64,
+        data: Vec<u8>,
+        position: u64,
+    }
+
+    impl Read for Sparse {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.position < self.gap {
+                let len = buf.len().min((self.gap - self.position) as usize);
+                buf[..len].fill(0);
+                self.position += len as u64;
+                return Ok(len);
+            }
+
+            let start = ((self.position - self.gap) as usize).min(self.data.len());
+            let len = buf.len().min(self.data.len() - start);
+            buf[..len].copy_from_slice(&self.data[start..start + len]);
+            self.position += len as u64;
+            Ok(len)
+        }
+    }
+
+    impl Seek for Sparse {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            let len = self.gap + self.data.len() as u64;
+            self.position = match pos {
+                SeekFrom::Start(offset) => offset,
+                SeekFrom::End(offset) => len.checked_add_signed(offset).unwrap(),
+                SeekFrom::Current(offset) => self.position.checked_add_signed(offset).unwrap(),
+            };
+            Ok(self.position)
+        }
+    }
+
+    #[test]
+    fn moves_offsets_past_4_gib_to_zip64_fields() {
+        let gap = 5 << 30;
+        let mut stream = ZipStream::new(Vec::new());
+        // The parts start after 5 GiB of some earlier, unreadable part
+        stream.position = gap;
+        write_parts(&mut stream);
+        let data = stream.finish().unwrap();
+        let end = data.len() - 22;
+        assert_eq!(u32_at(&data, end + 16), u32::MAX);
+
+        let archive = Sparse {
+            gap,
+            data,
+            position: 0,
+        };
+        assert_eq!(read_parts(archive), expected_parts());
+    }
+}
@@ -8,14 +8,14 @@ from pandas.api.types import is_datetime64_any_dtype as is_datetime
 from pandas.api.types import is_numeric_dtype as is_numeric
 
 class CellTypes(Enum):
-    Date = "n\" s=\"1"
+    Date = "date"
     String = "str"
     Number = "n"
-    Formula = "str"
+    Formula = "formula"
     Boolean = "b"
 
-def csv_to_xlsx(buf: bytes) -> bytes:
-    return _excel_rs.csv_to_xlsx(buf)
+def csv_to_xlsx(buf: bytes, autofilter: bool = False, table: bool = False) -> bytes:
+    return _excel_rs.csv_to_xlsx(buf, autofilter, table)
 
 def df_to_xlsx(df: pd.DataFrame, should_infer_types: bool = False) -> bytes:
 
@@ -5,36 +5,59 @@ use std::io::Cursor;
 
 use chrono::NaiveDateTime;
 use excel_rs_csv::{bytes_to_csv, get_headers, get_next_record};
-use excel_rs_xlsx::WorkBook;
+use excel_rs_xlsx::{CellValue, WorkBook};
 use numpy::PyReadonlyArray2;
 use postgres::PyPostgresClient;
-use utils::chrono_to_xlsx_date;
-use pyo3::{prelude::*, types::{PyBytes, PyList}};
+use utils::PyCell;
+use pyo3::{exceptions::PyValueError, prelude::*, types::{PyBytes, PyList}};
 
 #[pymodule]
 fn _excel_rs<'py>(m: &Bound<'py, PyModule>) -> PyResult<()> {
     #[pyfn(m)]
-    #[pyo3(name = "csv_to_xlsx")]
-    fn csv_to_xlsx<'py>(py: Python<'py>, buf: Bound<'py, PyBytes>) -> Bound<'py, PyBytes> {
+    #[pyo3(name = "csv_to_xlsx", signature = (buf, autofilter = false, table = false))]
+    fn csv_to_xlsx<'py>(
+        py: Python<'py>,
+        buf: Bound<'py, PyBytes>,
+        autofilter: bool,
+        table: bool,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        // Tables carry their own filter buttons, so the CLI refuses this pair too
+        if autofilter && table {
+            return Err(PyValueError::new_err(
+                "autofilter and table cannot be used together",
+            ));
+        }
+
         let x = buf.as_bytes();
 
         let output_buffer = vec![];
         let mut workbook = WorkBook::new(Cursor::new(output_buffer));
-        let mut worksheet = workbook.get_worksheet(String::from("Sheet 1"));
+        let mut worksheet = match workbook.get_worksheet(String::from("Sheet 1")) {
+            Ok(sheet) => sheet,
+            Err(e) => panic!("{e}"),
+        };
 
         let mut reader = bytes_to_csv(x);
         let headers = get_headers(&mut reader);
 
-        if headers.is_some() {
-            let headers_to_bytes = headers.unwrap().iter().to_owned().collect();
-            if let Err(e) = worksheet.write_row(headers_to_bytes) {
+        if let Some(headers) = headers {
+            if table {
+                if let Err(e) = worksheet.add_header_table("Table1", None) {
+                    panic!("{e}");
+                }
+            }
+
+            if let Err(e) = worksheet.write_row_iter(headers) {
                 panic!("{e}");
             }
+
+            if autofilter {
+                worksheet.autofilter_header();
+            }
         }
 
         while let Some(record) = get_next_record(&mut reader) {
-            let row_data = record.iter().to_owned().collect();
-            if let Err(e) = worksheet.write_row(row_data) {
+            if let Err(e) = worksheet.write_row_iter(&record) {
                 panic!("{e}");
             }
         }
@@ -45,7 +68,7 @@ fn _excel_rs<'py>(m: &Bound<'py, PyModule>) -> PyResult<()> {
 
         let final_buffer = workbook.finish().ok().unwrap();
 
-        PyBytes::new_bound(py, &final_buffer.into_inner())
+        Ok(PyBytes::new_bound(py, &final_buffer.into_inner()))
     }
 
     #[pyfn(m)]
@@ -78,11 +101,13 @@ fn _excel_rs<'py>(m: &Bound<'py, PyModule>) -> PyResult<()> {
 
         let output_buffer = vec![];
         let mut workbook = WorkBook::new(Cursor::new(output_buffer));
-        let mut worksheet = workbook.get_worksheet(String::from("Sheet 1"));
+        let mut worksheet = match workbook.get_worksheet(String::from("Sheet 1")) {
+            Ok(sheet) => sheet,
+            Err(e) => panic!("{e}"),
+        };
 
         for row in ndarray_str.rows() {
-            let bytes = row.map(|x| x.as_bytes()).to_vec();
-            if let Err(e) = worksheet.write_row(bytes) {
+            if let Err(e) = worksheet.write_row_iter(row.iter().map(|x| x.as_bytes())) {
                 panic!("{e}");
             }
         }
@@ -105,42 +130,43 @@ fn _excel_rs<'py>(m: &Bound<'py, PyModule>) -> PyResult<()> {
     ) -> Bound<'py, PyBytes> {
         let ndarray = list.as_array();
 
-        let ndarray_str = ndarray.mapv(|x| {
-            if let Ok(inner_str) = x.extract::<String>(py) {
-                inner_str
-            } else {
-                if let Ok(inner_num) = x.extract::<f64>(py) {
-                    if inner_num.is_nan() {
-                        String::from("")
-                    } else {
-                        inner_num.to_string()
-                    }
-                } else {
-                    if let Ok(inner_date) = x.extract::<NaiveDateTime>(py) {
-                        format!("{}", chrono_to_xlsx_date(inner_date))
-                    } else {
-                        String::from("")
-                    }
-                }
-            }
-        });
-
-        let mut xlsx_types: Vec<String> = Vec::with_capacity(ndarray.len());
+        let mut xlsx_types: Vec<String> = Vec::with_capacity(ndarray.ncols());
 
         for item in types.iter() {
             let unwrapped = item.extract::<String>().unwrap_or(String::from(""));
             xlsx_types.push(unwrapped);
         }
 
-        let borrowed_xlsx_types = xlsx_types.iter().map(|x| x.as_str()).collect();
-
         let output_buffer = vec![];
         let mut workbook = WorkBook::new(Cursor::new(output_buffer));
-        let mut worksheet = workbook.get_typed_worksheet(String::from("Sheet 1"));
 
-        for row in ndarray_str.rows() {
-            let bytes = row.map(|x| x.as_bytes()).to_vec();
-            if let Err(e) = worksheet.write_row(bytes, &borrowed_xlsx_types) {
+        // Formulas are written without cached values, so Excel has to
+        // calculate them when the file is opened
+        if xlsx_types.iter().any(|col_type| col_type == "formula") {
+            workbook.recalculate_on_open();
+        }
+
+        let mut worksheet = match workbook.get_typed_worksheet(String::from("Sheet 1")) {
+            Ok(sheet) => sheet,
+            Err(e) => panic!("{e}"),
+        };
+
+        for (row_num, row) in ndarray.rows().into_iter().enumerate() {
+            // The first row holds the column names, which are always written as text
+            let cells: Vec<PyCell> = row
+                .iter()
+                .enumerate()
+                .map(|(col, x)| {
+                    let cell = PyCell::extract(py, x);
+                    match xlsx_types.get(col) {
+                        Some(col_type) if row_num > 0 => cell.into_type(col_type),
+                        _ => cell.into_text(),
+                    }
+                })
+                .collect();
+
+            let values: Vec<CellValue> = cells.iter().map(PyCell::as_cell_value).collect();
+            if let Err(e) = worksheet.write_row(&values) {
                 panic!("{e}");
             }
         }
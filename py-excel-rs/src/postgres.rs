@@ -79,7 +79,10 @@ impl PyPostgresClient {
 
         let output_buffer = vec![];
         let mut workbook = WorkBook::new(Cursor::new(output_buffer));
-        let mut worksheet = workbook.get_worksheet(String::from("Sheet 1"));
+        let mut worksheet = match workbook.get_worksheet(String::from("Sheet 1")) {
+            Ok(sheet) => sheet,
+            Err(e) => panic!("{e}"),
+        };
 
         let headers = iter.next().ok().unwrap().unwrap();
         let len = headers.len();
@@ -109,9 +112,7 @@ impl PyPostgresClient {
                 }
             }
 
-            let new_vec: Vec<&[u8]> = row_vec.iter().map(|x| x.as_ref()).collect();
-
-            if let Err(e) = worksheet.write_row(new_vec) {
+            if let Err(e) = worksheet.write_row_iter(&row_vec) {
                 panic!("{e}");
             }
         }
@@ -1,21 +1,118 @@
 use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use excel_rs_xlsx::CellValue;
+use pyo3::{PyObject, Python};
 
-const NANOSECONDS_IN_A_DAY: f64 = 1_000_000_000.0 * 60.0 * 60.0 * 24.0;
+// Text that a "date" column accepts, tried in order
+const DATE_TIME_FORMATS: [&str; 2] = ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"];
+const DATE_FORMAT: &str = "%Y-%m-%d";
 
-// const EXCEL1900_EPOCH: &str = "1899-12-30 00:00:00";
-// const EXCEL1904_EPOCH: &str = "1904-01-01 00:00:00";
-// const EXCEL_MIN_TIME1900_EPOCH: &str = "1899-12-31 00:00:00";
-// const EXCEL_BUGGY_DATE_START: &str = "1900-03-01 00:00:00";
-// const DATE_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+// Owned copy of a Python value, so rows of CellValue can borrow from it
+pub enum PyCell {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    DateTime(NaiveDateTime),
+    Formula(String),
+    Empty,
+}
+
+impl PyCell {
+    pub fn extract(py: Python<'_>, obj: &PyObject) -> PyCell {
+        if let Ok(inner_str) = obj.extract::<String>(py) {
+            PyCell::String(inner_str)
+        } else if let Ok(inner_bool) = obj.extract::<bool>(py) {
+            PyCell::Bool(inner_bool)
+        } else if let Ok(inner_num) = obj.extract::<f64>(py) {
+            if inner_num.is_nan() {
+                PyCell::Empty
+            } else {
+                PyCell::Number(inner_num)
+            }
+        } else if let Ok(inner_date) = obj.extract::<NaiveDateTime>(py) {
+            PyCell::DateTime(inner_date)
+        } else {
+            PyCell::Empty
+        }
+    }
+
+    // Converts the cell to the type inferred for its column: "str", "n", "b",
+    // "date" or "formula". Values that don't fit the type are left as they are.
+    pub fn into_type(self, col_type: &str) -> PyCell {
+        match col_type {
+            "str" => self.into_text(),
+            "n" => self.into_number(),
+            "b" => self.into_bool(),
+            "date" => self.into_date_time(),
+            "formula" => self.into_formula(),
+            _ => self,
+        }
+    }
+
+    pub fn into_text(self) -> PyCell {
+        match self {
+            PyCell::Number(n) => PyCell::String(n.to_string()),
+            PyCell::Bool(b) => PyCell::String(b.to_string()),
+            PyCell::DateTime(d) => PyCell::String(format!("{}", d.format("%Y-%m-%d %H:%M:%S"))),
+            cell => cell,
+        }
+    }
+
+    pub fn into_number(self) -> PyCell {
+        match self {
+            PyCell::String(s) => match s.trim().parse::<f64>() {
+                Ok(n) if n.is_finite() => PyCell::Number(n),
+                _ => PyCell::String(s),
+            },
+            cell => cell,
+        }
+    }
+
+    pub fn into_bool(self) -> PyCell {
+        match self {
+            PyCell::String(s) if s.trim().eq_ignore_ascii_case("true") => PyCell::Bool(true),
+            PyCell::String(s) if s.trim().eq_ignore_ascii_case("false") => PyCell::Bool(false),
+            PyCell::Number(n) if n == 0.0 || n == 1.0 => PyCell::Bool(n == 1.0),
+            cell => cell,
+        }
+    }
+
+    pub fn into_date_time(self) -> PyCell {
+        let s = match self {
+            PyCell::String(s) => s,
+            cell => return cell,
+        };
+
+        let text = s.trim();
+        let date_time = DATE_TIME_FORMATS
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+            .or_else(|| {
+                NaiveDate::parse_from_str(text, DATE_FORMAT)
+                    .ok()
+                    .map(|date| date.and_time(NaiveTime::MIN))
+            });
+
+        match date_time {
+            Some(date_time) => PyCell::DateTime(date_time),
+            None => PyCell::String(s),
+        }
+    }
 
-pub fn chrono_to_xlsx_date(date: NaiveDateTime) -> f64 {
-    let start_date_only = NaiveDate::from_ymd_opt(1900, 1, 1).expect("Excel Epoch");
-    let start_date = NaiveDateTime::new(
-        start_date_only,
-        NaiveTime::from_hms_opt(0, 0, 0).expect("Time Epoch"),
-    );
-    let delta = (date - start_date).num_nanoseconds().unwrap();
+    pub fn into_formula(self) -> PyCell {
+        match self.into_text() {
+            PyCell::String(s) => PyCell::Formula(s),
+            cell => cell,
+        }
+    }
 
-    let true_delta: f64 = (delta as f64 + NANOSECONDS_IN_A_DAY + NANOSECONDS_IN_A_DAY) / NANOSECONDS_IN_A_DAY;
-    return true_delta;
+    pub fn as_cell_value(&self) -> CellValue<'_> {
+        match self {
+            PyCell::String(s) => CellValue::String(s),
+            PyCell::Number(n) => CellValue::Number(*n),
+            PyCell::Bool(b) => CellValue::Bool(*b),
+            PyCell::DateTime(d) => CellValue::DateTime(*d),
+            PyCell::Formula(f) => CellValue::Formula(f, None),
+            PyCell::Empty => CellValue::Blank,
+        }
+    }
 }